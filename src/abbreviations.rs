@@ -0,0 +1,131 @@
+//! Standard abbreviations for address components, following USPS Publication 28, with the
+//! spellings found in the Grants Pass address layer.
+
+/// Directionals as (spelling, standard abbreviation).
+pub const DIRECTIONS: &[(&str, &str)] = &[
+    ("NORTH", "N"),
+    ("SOUTH", "S"),
+    ("EAST", "E"),
+    ("WEST", "W"),
+    ("NORTHEAST", "NE"),
+    ("NORTHWEST", "NW"),
+    ("SOUTHEAST", "SE"),
+    ("SOUTHWEST", "SW"),
+    ("N", "N"),
+    ("S", "S"),
+    ("E", "E"),
+    ("W", "W"),
+    ("NE", "NE"),
+    ("NW", "NW"),
+    ("SE", "SE"),
+    ("SW", "SW"),
+];
+
+/// Street types as (spelling, standard abbreviation).
+pub const STREET_TYPES: &[(&str, &str)] = &[
+    ("ALLEY", "ALY"),
+    ("ALY", "ALY"),
+    ("AVENUE", "AVE"),
+    ("AVE", "AVE"),
+    ("AV", "AVE"),
+    ("BOULEVARD", "BLVD"),
+    ("BLVD", "BLVD"),
+    ("CIRCLE", "CIR"),
+    ("CIR", "CIR"),
+    ("COURT", "CT"),
+    ("CT", "CT"),
+    ("DRIVE", "DR"),
+    ("DR", "DR"),
+    ("EXPRESSWAY", "EXPY"),
+    ("EXPY", "EXPY"),
+    ("HIGHWAY", "HWY"),
+    ("HWY", "HWY"),
+    ("LANE", "LN"),
+    ("LN", "LN"),
+    ("LOOP", "LOOP"),
+    ("PARKWAY", "PKWY"),
+    ("PKWY", "PKWY"),
+    ("PLACE", "PL"),
+    ("PL", "PL"),
+    ("PLAZA", "PLZ"),
+    ("PLZ", "PLZ"),
+    ("ROAD", "RD"),
+    ("RD", "RD"),
+    ("SQUARE", "SQ"),
+    ("SQ", "SQ"),
+    ("STREET", "ST"),
+    ("ST", "ST"),
+    ("TERRACE", "TER"),
+    ("TER", "TER"),
+    ("TRAIL", "TRL"),
+    ("TRL", "TRL"),
+    ("WAY", "WAY"),
+    // Spellings found in older Grants Pass and Josephine County records.
+    ("BL", "BLVD"),
+    ("CRT", "CT"),
+    ("DRV", "DR"),
+    ("HY", "HWY"),
+    ("LP", "LOOP"),
+    ("PKY", "PKWY"),
+    ("TERR", "TER"),
+];
+
+/// Secondary unit designators as (spelling, standard abbreviation).
+pub const UNIT_TYPES: &[(&str, &str)] = &[
+    ("APARTMENT", "APT"),
+    ("APT", "APT"),
+    ("BUILDING", "BLDG"),
+    ("BLDG", "BLDG"),
+    ("FLOOR", "FL"),
+    ("FL", "FL"),
+    ("LOT", "LOT"),
+    ("NUMBER", "#"),
+    ("NO", "#"),
+    ("#", "#"),
+    ("ROOM", "RM"),
+    ("RM", "RM"),
+    ("SPACE", "SPC"),
+    ("SPC", "SPC"),
+    ("SP", "SPC"),
+    ("SUITE", "STE"),
+    ("STE", "STE"),
+    ("TRAILER", "TRLR"),
+    ("TRLR", "TRLR"),
+    ("UNIT", "UNIT"),
+];
+
+fn lookup(table: &[(&str, &'static str)], word: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(spelling, _)| spelling.eq_ignore_ascii_case(word))
+        .map(|(_, abbreviation)| *abbreviation)
+}
+
+/// Standard abbreviation of a directional, if `word` is one.
+pub fn direction(word: &str) -> Option<&'static str> {
+    lookup(DIRECTIONS, word)
+}
+
+/// Standard abbreviation of a street type, if `word` is one.
+pub fn street_type(word: &str) -> Option<&'static str> {
+    lookup(STREET_TYPES, word)
+}
+
+/// Standard abbreviation of a unit designator, if `word` is one.
+pub fn unit_type(word: &str) -> Option<&'static str> {
+    lookup(UNIT_TYPES, word)
+}
+
+/// Replace `word` with its standard abbreviation if it is a directional, street type or unit
+/// designator, otherwise return it in upper case.
+pub fn standardize(word: &str) -> String {
+    if let Some(value) = direction(word) {
+        value.to_string()
+    } else if let Some(value) = street_type(word) {
+        value.to_string()
+    } else if let Some(value) = unit_type(word) {
+        value.to_string()
+    } else {
+        word.to_uppercase()
+    }
+}
@@ -1,4 +1,6 @@
 use crate::prelude::*;
+use crate::parser::{self, AddressParts, ParseError};
+use crate::table::preview;
 use galileo::layer::feature_layer::Feature;
 use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
 use galileo_types::geo::Projection;
@@ -6,8 +8,12 @@ use galileo_types::geometry::{CartesianGeometry2d, Geom, Geometry};
 use polite::Polite;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::sync::OnceLock;
 use tracing::info;
 
+/// Minimum similarity for a label to be returned by an address search.
+const SEARCH_THRESHOLD: f64 = 0.6;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Address {
     #[serde(rename(deserialize = "FULLADDRES"))]
@@ -18,10 +24,40 @@ pub struct Address {
     pub lat: f64,
     #[serde(rename(deserialize = "wgs84_x"))]
     pub lon: f64,
-    #[serde(rename(deserialize = "espg3857_x"))]
+    #[serde(rename(deserialize = "espg3857_x"), alias = "epsg3857_x")]
     pub x: f64,
-    #[serde(rename(deserialize = "espg3857_y"))]
+    #[serde(rename(deserialize = "espg3857_y"), alias = "epsg3857_y")]
     pub y: f64,
+    /// Components of the label, parsed on first use.
+    #[serde(skip)]
+    pub parsed: OnceLock<Result<AddressParts, ParseError>>,
+}
+
+impl Address {
+    /// The label split into its address components.  The label is parsed once and the result
+    /// kept, so set `parsed` to its default after changing `label`.
+    pub fn parts(&self) -> Result<&AddressParts, &ParseError> {
+        self.parsed.get_or_init(|| parser::parse(&self.label)).as_ref()
+    }
+}
+
+impl Columnar for Address {
+    fn headers() -> Vec<String> {
+        let mut headers = vec!["label".to_string(), "status".to_string()];
+        headers.extend(AddressParts::headers());
+        headers.extend(["lat", "lon", "x", "y"].iter().map(|v| v.to_string()));
+        headers
+    }
+
+    fn values(&self) -> Vec<String> {
+        let mut values = vec![self.label.clone(), self.status.clone()];
+        match self.parts() {
+            Ok(parts) => values.extend(parts.values()),
+            Err(_) => values.extend(vec![String::new(); AddressParts::headers().len()]),
+        }
+        values.extend([self.lat, self.lon, self.x, self.y].iter().map(|v| v.to_string()));
+        values
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -30,17 +66,53 @@ pub struct Addresses {
 }
 
 impl Addresses {
-    pub fn from_csv<P: AsRef<Path>>(path: P) -> Polite<Self> {
-        let records = from_csv(path)?;
-        Ok(Addresses { records })
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Whimsy<(Self, LoadReport)> {
+        let (records, report) = from_csv(path)?;
+        Ok((Addresses { records }, report))
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Polite<Self> {
+    pub fn load<P: AsRef<Path>>(path: P) -> Whimsy<Self> {
         info!("Deserializing from binary.");
-        let vec: Vec<u8> = std::fs::read(path)?;
-        let values: Addresses = bincode::deserialize(&vec[..])?;
+        let vec: Vec<u8> = std::fs::read(&path).map_err(|e| WhimsyError::io(&path, e))?;
+        let values: Addresses =
+            bincode::deserialize(&vec[..]).map_err(|e| WhimsyError::parse(&path, e))?;
         Ok(values)
     }
+
+    /// Read a CSV, shapefile or GeoJSON source file with the default field names, or a bincode
+    /// cache for any other extension.  CSV files may be gzip or zstd compressed.
+    pub fn read<P: AsRef<Path>>(path: P) -> Whimsy<(Self, LoadReport)> {
+        if is_csv(&path) {
+            return Self::from_csv(path);
+        }
+        let ext = path
+            .as_ref()
+            .extension()
+            .and_then(|v| v.to_str())
+            .map(|v| v.to_lowercase());
+        match ext.as_deref() {
+            Some("shp") => Self::from_shp(path, &FieldMap::default()),
+            Some("geojson") | Some("json") => Self::from_geojson(path, &FieldMap::default()),
+            _ => {
+                let values = Self::load(&path)?;
+                let mut report = LoadReport::new(&path);
+                report.loaded = values.records.len();
+                Ok((values, report))
+            }
+        }
+    }
+
+    /// Labels that fail to parse into address components, with the position of each record.
+    pub fn parse_failures(&self) -> Vec<(usize, ParseError)> {
+        let failures = self
+            .records
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.parts().err().map(|e| (i, e.clone())))
+            .collect::<Vec<(usize, ParseError)>>();
+        info!("{} of {} labels failed to parse.", failures.len(), self.records.len());
+        failures
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -65,6 +137,28 @@ impl From<Address> for AddressPoint {
     }
 }
 
+impl Tabular<Address> for Addresses {
+    fn headers() -> Vec<String> {
+        Address::headers()
+    }
+
+    fn rows(&self) -> Vec<Address> {
+        self.records.clone()
+    }
+
+    fn row(&self, index: usize) -> Option<Address> {
+        self.records.get(index).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn table(&self, ui: &mut egui::Ui) {
+        preview(ui, &self.records, 100);
+    }
+}
+
 impl Geometry for AddressPoint {
     type Point = Point2d;
 
@@ -105,6 +199,17 @@ impl Feature for AddressPoint {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AddressPoints {
     pub records: Vec<AddressPoint>,
+    /// Coordinate system of the point geometries.  Web Mercator for points in use, which the map,
+    /// overlays and comparisons draw and match in.  Other systems only appear in copies from
+    /// [`AddressPoints::reprojected`] for export.  The `x` and `y` fields of each address stay in
+    /// Web Mercator.
+    pub crs: Crs,
+    /// Normalized labels for searching, built by the first search.
+    #[serde(skip)]
+    pub search_index: OnceLock<SearchIndex>,
+    /// Changes whenever the records are replaced or their geometry changes.
+    #[serde(skip)]
+    pub revision: Revision,
 }
 
 impl From<Addresses> for AddressPoints {
@@ -114,23 +219,101 @@ impl From<Addresses> for AddressPoints {
             .iter()
             .map(|v| AddressPoint::from(v.clone()))
             .collect::<Vec<AddressPoint>>();
-        Self { records }
+        Self {
+            records,
+            crs: Crs::WebMercator,
+            search_index: Default::default(),
+            revision: Default::default(),
+        }
+    }
+}
+
+/// Address points layout of caches written before the cache format was versioned, always in Web
+/// Mercator.
+#[derive(Debug, Clone, Deserialize)]
+struct UnversionedAddressPoints {
+    records: Vec<AddressPoint>,
+}
+
+impl From<UnversionedAddressPoints> for AddressPoints {
+    fn from(points: UnversionedAddressPoints) -> Self {
+        Self {
+            records: points.records,
+            crs: Crs::WebMercator,
+            search_index: Default::default(),
+            revision: Default::default(),
+        }
     }
 }
 
 impl AddressPoints {
+    /// Write the address points to a versioned bincode cache.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Polite<()> {
-        info!("Serializing to binary.");
-        let encode = bincode::serialize(self)?;
-        info!("Writing to file.");
-        std::fs::write(path, encode)?;
+        write_cache(self, path)
+    }
+
+    /// Read address points from a cache written by [`AddressPoints::save`], or from an
+    /// unversioned cache written before address points recorded their CRS.  Points are returned
+    /// in Web Mercator whatever system the cache was written in.
+    pub fn load<P: AsRef<Path>>(path: P) -> Whimsy<Self> {
+        let mut points = read_cache::<Self, UnversionedAddressPoints, P>(path)?;
+        points.reproject(&Crs::WebMercator);
+        Ok(points)
+    }
+
+    /// Transform the point geometries and bounds to the system `to`.
+    fn reproject(&mut self, to: &Crs) {
+        if self.crs == *to {
+            return;
+        }
+        for record in self.records.iter_mut() {
+            record.geometry = self.crs.point(to, &record.geometry);
+            record.bounds = point_bounds(&record.geometry, 0.05);
+        }
+        self.crs = *to;
+        self.revision = Default::default();
+    }
+
+    /// Copy of the address points in the system `to`, for export.
+    pub fn reprojected(&self, to: &Crs) -> Self {
+        let mut points = self.clone();
+        points.reproject(to);
+        points
+    }
+
+    /// Write the labels, statuses and point coordinates in the system `to` to a CSV file.
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P, to: &Crs) -> Polite<()> {
+        let rows = self
+            .records
+            .iter()
+            .map(|v| {
+                let point = self.crs.point(to, &v.geometry);
+                (v.address.label.clone(), v.address.status.clone(), point.x(), point.y())
+            })
+            .collect::<Vec<(String, String, f64, f64)>>();
+        let mut wtr = csv::Writer::from_path(path).map_err(std::io::Error::from)?;
+        wtr.write_record(["label", "status", "x", "y"])
+            .map_err(std::io::Error::from)?;
+        for row in rows {
+            wtr.serialize(row).map_err(std::io::Error::from)?;
+        }
+        wtr.flush()?;
+        info!("Wrote {} points in {}.", self.records.len(), to);
         Ok(())
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Polite<Self> {
-        info!("Deserializing from binary.");
-        let vec: Vec<u8> = std::fs::read(path)?;
-        let addresses: AddressPoints = bincode::deserialize(&vec[..])?;
-        Ok(addresses)
+    /// Find up to `limit` address points with labels similar to `query`, best match first.  The
+    /// labels are normalized on the first search and reused after.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<AddressMatch> {
+        self.search_index
+            .get_or_init(|| SearchIndex::new(self.records.iter().map(|v| v.address.label.as_str())))
+            .rank(query, limit, SEARCH_THRESHOLD)
+    }
+
+    /// Mark the record at `index` as the only selected address point.
+    pub fn select_only(&mut self, index: usize) {
+        for (i, record) in self.records.iter_mut().enumerate() {
+            record.selected = i == index;
+        }
     }
 }
@@ -0,0 +1,364 @@
+use crate::prelude::*;
+use egui::Ui;
+use geo::{BoundingRect, EuclideanDistance};
+use polite::Polite;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// Bounding box of a parcel in state plane feet, keyed by the position of the parcel.
+pub type ParcelEnvelope = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+/// Which parcels share an edge or touch, measured in Oregon South state plane feet.
+#[derive(Debug, Clone)]
+pub struct Topology {
+    /// Parcel geometries in state plane feet, in dataset order.
+    pub grid: Vec<geo::MultiPolygon>,
+    pub tree: RTree<ParcelEnvelope>,
+    /// Positions of the parcels with each MapNum.
+    pub ids: HashMap<String, Vec<usize>>,
+    /// Positions of the parcels adjacent to each parcel.
+    pub adjacent: Vec<Vec<usize>>,
+    /// Gap in feet below which parcels count as touching, to absorb digitizing error.
+    pub tolerance: f64,
+    /// Revision of the parcels the topology was built from.
+    pub revision: Revision,
+}
+
+impl Topology {
+    pub fn new(parcels: &Parcels, tolerance: f64) -> Self {
+        let grid = parcels
+            .records
+            .par_iter()
+            .map(|v| {
+                Convert::new(v.geo())
+                    .reproject(&parcels.crs, &Crs::OregonSouth)
+                    .into_inner()
+            })
+            .collect::<Vec<geo::MultiPolygon>>();
+        let envelopes = grid
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| {
+                v.bounding_rect().map(|r| {
+                    let rect = Rectangle::from_corners([r.min().x, r.min().y], [r.max().x, r.max().y]);
+                    GeomWithData::new(rect, i)
+                })
+            })
+            .collect::<Vec<ParcelEnvelope>>();
+        let tree = RTree::bulk_load(envelopes);
+        let mut ids: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, parcel) in parcels.records.iter().enumerate() {
+            ids.entry(parcel.owner.id.clone()).or_default().push(i);
+        }
+        let mut topology = Self {
+            grid,
+            tree,
+            ids,
+            adjacent: Vec::new(),
+            tolerance,
+            revision: parcels.revision,
+        };
+        topology.adjacent = (0..topology.grid.len())
+            .into_par_iter()
+            .map(|i| {
+                topology
+                    .within_index(i, tolerance)
+                    .into_iter()
+                    .map(|(j, _)| j)
+                    .collect()
+            })
+            .collect();
+        info!(
+            "Built topology of {} parcels with {} adjacencies.",
+            topology.grid.len(),
+            topology.adjacent.iter().map(|v| v.len()).sum::<usize>() / 2
+        );
+        topology
+    }
+
+    /// Distance in feet between the parcels at positions `a` and `b`, zero if they overlap.
+    pub fn distance(&self, a: usize, b: usize) -> f64 {
+        let mut distance = f64::MAX;
+        for p in &self.grid[a] {
+            for q in &self.grid[b] {
+                distance = distance.min(p.euclidean_distance(q));
+            }
+        }
+        distance
+    }
+
+    /// Other parcels within `distance` feet of the parcel at `index`, with the distance to each,
+    /// nearest first.
+    pub fn within_index(&self, index: usize, distance: f64) -> Vec<(usize, f64)> {
+        let Some(rect) = self.grid[index].bounding_rect() else {
+            return Vec::new();
+        };
+        let search = AABB::from_corners(
+            [rect.min().x - distance, rect.min().y - distance],
+            [rect.max().x + distance, rect.max().y + distance],
+        );
+        let mut found = self
+            .tree
+            .locate_in_envelope_intersecting(&search)
+            .filter(|v| v.data != index)
+            .map(|v| (v.data, self.distance(index, v.data)))
+            .filter(|(_, d)| *d <= distance)
+            .collect::<Vec<(usize, f64)>>();
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        found
+    }
+
+    /// Parcels within `distance` feet of any parcel with the MapNum `map_id`, excluding those
+    /// parcels themselves, nearest first.
+    pub fn within(&self, map_id: &str, distance: f64) -> Vec<(usize, f64)> {
+        let Some(own) = self.ids.get(map_id) else {
+            return Vec::new();
+        };
+        let mut nearest: HashMap<usize, f64> = HashMap::new();
+        for i in own {
+            for (j, d) in self.within_index(*i, distance) {
+                if own.contains(&j) {
+                    continue;
+                }
+                let entry = nearest.entry(j).or_insert(d);
+                *entry = entry.min(d);
+            }
+        }
+        let mut found = nearest.into_iter().collect::<Vec<(usize, f64)>>();
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        found
+    }
+
+    /// Parcels sharing an edge or touching any parcel with the MapNum `map_id`.
+    pub fn neighbours(&self, map_id: &str) -> Vec<usize> {
+        let Some(own) = self.ids.get(map_id) else {
+            return Vec::new();
+        };
+        own.iter()
+            .flat_map(|i| self.adjacent[*i].iter().copied())
+            .filter(|j| !own.contains(j))
+            .collect::<BTreeSet<usize>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Owners to notify of a hearing on the parcel `map_id`, for parcels within `distance` feet.
+    pub fn notices(&self, parcels: &Parcels, map_id: &str, distance: f64) -> Vec<Notice> {
+        self.within(map_id, distance)
+            .into_iter()
+            .map(|(i, d)| Notice {
+                index: i,
+                map_num: parcels.records[i].owner.id.clone(),
+                owner: parcels.records[i].owner.name.clone().unwrap_or_default(),
+                mailing_address: parcels.records[i]
+                    .attributes
+                    .mailing_address
+                    .clone()
+                    .unwrap_or_default(),
+                distance: d,
+            })
+            .collect()
+    }
+}
+
+/// Gap in feet below which parcels count as touching in the notice panel.
+const TOUCH_TOLERANCE: f64 = 0.5;
+
+/// A parcel whose owner is notified of a land use hearing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Notice {
+    /// Position of the parcel in the dataset.
+    pub index: usize,
+    pub map_num: String,
+    pub owner: String,
+    pub mailing_address: String,
+    /// Distance in feet from the subject parcel.
+    pub distance: f64,
+}
+
+/// A row of the mailing list, for one owner at one mailing address.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Mailing {
+    pub owner: String,
+    pub mailing_address: String,
+    /// MapNums of the owner's parcels in the notice area, separated by semicolons.
+    pub map_nums: String,
+    /// Distance in feet from the subject parcel to the nearest parcel of the owner.
+    pub distance: f64,
+}
+
+/// Combine `notices` into one mailing per owner and mailing address, ignoring case and
+/// surrounding space, in order of the first notice of each.
+pub fn mailings(notices: &[Notice]) -> Vec<Mailing> {
+    let mut mailings: Vec<Mailing> = Vec::new();
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+    for notice in notices {
+        let key = (
+            notice.owner.trim().to_uppercase(),
+            notice.mailing_address.trim().to_uppercase(),
+        );
+        match positions.get(&key) {
+            Some(i) => {
+                let mailing = &mut mailings[*i];
+                mailing.map_nums.push_str("; ");
+                mailing.map_nums.push_str(&notice.map_num);
+                mailing.distance = mailing.distance.min(notice.distance);
+            }
+            None => {
+                positions.insert(key, mailings.len());
+                mailings.push(Mailing {
+                    owner: notice.owner.clone(),
+                    mailing_address: notice.mailing_address.clone(),
+                    map_nums: notice.map_num.clone(),
+                    distance: notice.distance,
+                });
+            }
+        }
+    }
+    mailings
+}
+
+/// Write a mailing list of notices, one row per owner and mailing address, to a CSV file.
+/// Returns the number of rows written.
+pub fn notices_to_csv<P: AsRef<std::path::Path>>(notices: &[Notice], path: P) -> Polite<usize> {
+    let mailings = mailings(notices);
+    to_csv(&mailings, path)?;
+    Ok(mailings.len())
+}
+
+/// Notification radius search in the GUI.  The parcel topology is built in the background on the
+/// first search, and again when the parcels change.
+#[derive(Debug, Clone)]
+pub struct NoticePanel {
+    pub topology: Option<Arc<Topology>>,
+    /// Topology being built in the background.
+    pub building: Option<Task<Option<Topology>>>,
+    /// Search to run when the topology is built, true for the radius search.
+    pub pending: Option<bool>,
+    pub map_num: String,
+    /// Notification radius in feet.
+    pub distance: f64,
+    pub notices: Vec<Notice>,
+    /// Revision of the parcels the notices were found in.
+    pub revision: Option<Revision>,
+    pub report_path: String,
+    pub status: String,
+}
+
+impl Default for NoticePanel {
+    fn default() -> Self {
+        Self {
+            topology: None,
+            building: None,
+            pending: None,
+            map_num: Default::default(),
+            distance: 300.0,
+            notices: Vec::new(),
+            revision: None,
+            report_path: "notices.csv".to_string(),
+            status: Default::default(),
+        }
+    }
+}
+
+impl NoticePanel {
+    /// Draw the search controls and owner list, selecting the parcels found on the map.  The list
+    /// is cleared when the parcels change.
+    pub fn show(&mut self, ui: &mut Ui, data: &Arc<RwLock<Parcels>>) {
+        let revision = data.read().ok().map(|v| v.revision);
+        if !self.notices.is_empty() && self.revision != revision {
+            self.notices.clear();
+            self.status = "Parcels changed since the last search.".to_string();
+        }
+        if let Some(built) = self.building.as_ref().and_then(|v| v.poll()) {
+            self.building = None;
+            self.topology = built.map(Arc::new);
+            if let Some(within) = self.pending.take() {
+                self.search(ui.ctx(), data, within);
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.map_num).hint_text("MapNum"));
+            ui.add(
+                egui::DragValue::new(&mut self.distance)
+                    .clamp_range(0.0..=5280.0)
+                    .suffix(" ft"),
+            );
+        });
+        ui.horizontal(|ui| {
+            let within = ui.button("Within radius").clicked();
+            let adjacent = ui.button("Adjacent").clicked();
+            if within || adjacent {
+                self.search(ui.ctx(), data, within);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.report_path).hint_text("Mailing list path"));
+            if ui.button("CSV").clicked() {
+                self.status = match notices_to_csv(&self.notices, &self.report_path) {
+                    Ok(rows) => format!("Wrote {} mailings to {}.", rows, self.report_path),
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+            for notice in &self.notices {
+                ui.label(format!(
+                    "{}: {} ({:.0} ft)",
+                    notice.map_num, notice.owner, notice.distance
+                ));
+            }
+        });
+    }
+
+    /// Find the parcels within the radius, or adjacent when `within` is false, and select them.
+    /// Starts building the topology instead if there is none for the current parcels, and
+    /// searches when it is done.
+    fn search(&mut self, ctx: &egui::Context, data: &Arc<RwLock<Parcels>>, within: bool) {
+        let Ok(parcels) = data.read() else {
+            return;
+        };
+        let topology = self
+            .topology
+            .as_ref()
+            .filter(|v| v.revision == parcels.revision)
+            .cloned();
+        let Some(topology) = topology else {
+            self.topology = None;
+            self.pending = Some(within);
+            if self.building.is_none() {
+                let data = data.clone();
+                self.building = Some(Task::spawn(ctx, move || {
+                    data.read()
+                        .ok()
+                        .map(|parcels| Topology::new(&parcels, TOUCH_TOLERANCE))
+                }));
+            }
+            self.status = "Building parcel topology.".to_string();
+            return;
+        };
+        let distance = if within {
+            self.distance
+        } else {
+            topology.tolerance
+        };
+        self.notices = topology.notices(&parcels, &self.map_num, distance);
+        self.revision = Some(parcels.revision);
+        drop(parcels);
+        let found = self.notices.iter().map(|v| v.index).collect::<BTreeSet<usize>>();
+        if let Ok(mut parcels) = data.write() {
+            for (i, parcel) in parcels.records.iter_mut().enumerate() {
+                parcel.selected = found.contains(&i);
+            }
+        }
+        self.status = format!("{} parcels found.", self.notices.len());
+    }
+}
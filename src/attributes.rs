@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use shapefile::dbase::{FieldValue, Record};
+use std::collections::BTreeMap;
+
+/// A typed attribute value read from an assessor table.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub enum Value {
+    #[default]
+    Null,
+    Text(String),
+    Number(f64),
+    Integer(i64),
+    Bool(bool),
+    /// Calendar date as YYYY-MM-DD.
+    Date(String),
+}
+
+impl Value {
+    /// The value as a number, parsing text if necessary.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(v) => Some(*v),
+            Self::Integer(v) => Some(*v as f64),
+            Self::Text(v) => v.trim().replace(['$', ','], "").parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as text, or `None` if it is null or blank.
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            Self::Null => None,
+            Self::Text(v) if v.trim().is_empty() => None,
+            Self::Text(v) => Some(v.trim().to_string()),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => Ok(()),
+            Self::Text(v) => write!(f, "{}", v.trim()),
+            Self::Number(v) => write!(f, "{}", v),
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Date(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl From<&FieldValue> for Value {
+    fn from(field: &FieldValue) -> Self {
+        match field {
+            FieldValue::Character(Some(v)) => Self::Text(v.clone()),
+            FieldValue::Memo(v) => Self::Text(v.clone()),
+            FieldValue::Numeric(Some(v)) => Self::Number(*v),
+            FieldValue::Float(Some(v)) => Self::Number(f64::from(*v)),
+            FieldValue::Double(v) => Self::Number(*v),
+            FieldValue::Currency(v) => Self::Number(*v),
+            FieldValue::Integer(v) => Self::Integer(i64::from(*v)),
+            FieldValue::Logical(Some(v)) => Self::Bool(*v),
+            FieldValue::Date(Some(v)) => {
+                Self::Date(format!("{:04}-{:02}-{:02}", v.year(), v.month(), v.day()))
+            }
+            FieldValue::Character(None)
+            | FieldValue::Numeric(None)
+            | FieldValue::Float(None)
+            | FieldValue::Logical(None)
+            | FieldValue::Date(None) => Self::Null,
+            other => Self::Text(format!("{:?}", other)),
+        }
+    }
+}
+
+/// Assessor fields beyond the owner, with typed values where the schema names them and every
+/// other field kept as it was read.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ParcelAttributes {
+    pub situs: Option<String>,
+    pub acreage: Option<f64>,
+    pub assessed_value: Option<f64>,
+    pub zoning: Option<String>,
+    pub tax_code: Option<String>,
+    pub mailing_address: Option<String>,
+    /// Fields not named by the schema.
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl ParcelAttributes {
+    pub fn headers() -> Vec<String> {
+        [
+            "situs",
+            "acreage",
+            "assessed_value",
+            "zoning",
+            "tax_code",
+            "mailing_address",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        let number = |v: &Option<f64>| v.map(|n| n.to_string()).unwrap_or_default();
+        vec![
+            self.situs.clone().unwrap_or_default(),
+            number(&self.acreage),
+            number(&self.assessed_value),
+            self.zoning.clone().unwrap_or_default(),
+            self.tax_code.clone().unwrap_or_default(),
+            self.mailing_address.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Names of the assessor fields holding each parcel attribute.  Adjust to match the columns of
+/// the taxlot table being read; fields that are absent from a record are left empty.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ParcelSchema {
+    pub map_num: String,
+    pub owner: String,
+    pub situs: Option<String>,
+    pub acreage: Option<String>,
+    pub assessed_value: Option<String>,
+    pub zoning: Option<String>,
+    pub tax_code: Option<String>,
+    /// Fields joined with ", " to form the mailing address, such as street, city, state and zip.
+    pub mailing_address: Vec<String>,
+}
+
+impl Default for ParcelSchema {
+    fn default() -> Self {
+        Self {
+            map_num: "MapNum".to_string(),
+            owner: "NAME".to_string(),
+            situs: Some("SITUS".to_string()),
+            acreage: Some("ACRES".to_string()),
+            assessed_value: Some("ASSDVALUE".to_string()),
+            zoning: Some("ZONING".to_string()),
+            tax_code: Some("TAXCODE".to_string()),
+            mailing_address: ["ADDRESS1", "ADDRESS2", "CITY", "STATE", "ZIP"]
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl ParcelSchema {
+    /// Read the MapNum, owner name and remaining attributes from an assessor record.
+    pub fn read(&self, record: Record) -> (Option<String>, Option<String>, ParcelAttributes) {
+        let mut fields = record
+            .into_iter()
+            .map(|(k, v)| (k, Value::from(&v)))
+            .collect::<BTreeMap<String, Value>>();
+        let mut take = |name: &Option<String>| -> Value {
+            name.as_ref().and_then(|v| fields.remove(v)).unwrap_or_default()
+        };
+        let map_num = take(&Some(self.map_num.clone())).as_text();
+        let owner = take(&Some(self.owner.clone())).as_text();
+        let situs = take(&self.situs).as_text();
+        let acreage = take(&self.acreage).as_f64();
+        let assessed_value = take(&self.assessed_value).as_f64();
+        let zoning = take(&self.zoning).as_text();
+        let tax_code = take(&self.tax_code).as_text();
+        let mailing = self
+            .mailing_address
+            .iter()
+            .filter_map(|v| take(&Some(v.clone())).as_text())
+            .collect::<Vec<String>>();
+        let mailing_address = if mailing.is_empty() {
+            None
+        } else {
+            Some(mailing.join(", "))
+        };
+        let attributes = ParcelAttributes {
+            situs,
+            acreage,
+            assessed_value,
+            zoning,
+            tax_code,
+            mailing_address,
+            extra: fields,
+        };
+        (map_num, owner, attributes)
+    }
+}
@@ -0,0 +1,415 @@
+use crate::prelude::*;
+use egui::{Color32, Painter, Ui};
+use geo::Contains;
+use polite::Polite;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use tracing::info;
+
+/// Kinds of problem found by an [`Audit`] of the address points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum IssueKind {
+    /// More than one point shares the label.
+    DuplicateLabel,
+    /// More than one point shares the coordinates.
+    DuplicateLocation,
+    /// The WGS84 coordinates do not project to the stored Web Mercator coordinates.
+    CoordinateMismatch,
+    /// The point lies outside the city boundary.
+    OutsideBoundary,
+    /// The status is not one of the expected values.
+    UnusualStatus,
+    /// The label does not parse into address components.
+    ParseFailure,
+}
+
+impl IssueKind {
+    pub const ALL: [IssueKind; 6] = [
+        IssueKind::DuplicateLabel,
+        IssueKind::DuplicateLocation,
+        IssueKind::CoordinateMismatch,
+        IssueKind::OutsideBoundary,
+        IssueKind::UnusualStatus,
+        IssueKind::ParseFailure,
+    ];
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            IssueKind::DuplicateLabel => Color32::from_rgb(230, 159, 0),
+            IssueKind::DuplicateLocation => Color32::from_rgb(86, 180, 233),
+            IssueKind::CoordinateMismatch => Color32::from_rgb(213, 94, 0),
+            IssueKind::OutsideBoundary => Color32::from_rgb(204, 121, 167),
+            IssueKind::UnusualStatus => Color32::from_rgb(240, 228, 66),
+            IssueKind::ParseFailure => Color32::from_rgb(0, 158, 115),
+        }
+    }
+}
+
+impl std::fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// A problem with a single address point.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    /// Position of the record in the dataset.
+    pub index: usize,
+    pub label: String,
+    pub kind: IssueKind,
+    pub detail: String,
+}
+
+/// Settings for an [`Audit`].
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// Distance in meters beyond which projected WGS84 coordinates disagree with the stored Web
+    /// Mercator coordinates.
+    pub tolerance: f64,
+    /// Expected status values.  If `None`, statuses used by fewer than `rare_share` of the
+    /// records are reported instead.
+    pub statuses: Option<Vec<String>>,
+    pub rare_share: f64,
+    /// City boundary in WGS84 longitude and latitude.
+    pub boundary: Option<geo::MultiPolygon>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 1.0,
+            statuses: None,
+            rare_share: 0.005,
+            boundary: None,
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Read the city boundary from the polygons in a GeoJSON file.
+    pub fn with_boundary<P: AsRef<Path>>(mut self, path: P) -> Polite<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let geojson = text.parse::<geojson::GeoJson>()?;
+        let collection: geo::GeometryCollection = geojson::quick_collection(&geojson)?;
+        let mut polygons = Vec::new();
+        for geometry in collection {
+            match geometry {
+                geo::Geometry::Polygon(poly) => polygons.push(poly),
+                geo::Geometry::MultiPolygon(multi) => polygons.extend(multi),
+                _ => {}
+            }
+        }
+        info!("Boundary has {} polygons.", polygons.len());
+        self.boundary = Some(geo::MultiPolygon::new(polygons));
+        Ok(self)
+    }
+}
+
+/// Data quality findings for a set of address points.
+#[derive(Debug, Clone, Default)]
+pub struct Audit {
+    pub issues: Vec<Issue>,
+}
+
+impl Audit {
+    pub fn addresses(addresses: &Addresses, config: &AuditConfig) -> Self {
+        Self::run(&addresses.records.iter().collect::<Vec<&Address>>(), config)
+    }
+
+    pub fn address_points(points: &AddressPoints, config: &AuditConfig) -> Self {
+        Self::run(
+            &points.records.iter().map(|v| &v.address).collect::<Vec<&Address>>(),
+            config,
+        )
+    }
+
+    pub fn run(records: &[&Address], config: &AuditConfig) -> Self {
+        let mut issues = Vec::new();
+        let mut push = |index: usize, kind: IssueKind, detail: String| {
+            issues.push(Issue {
+                index,
+                label: records[index].label.clone(),
+                kind,
+                detail,
+            })
+        };
+
+        let mut labels: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut locations: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        let mut statuses: HashMap<&str, usize> = HashMap::new();
+        for (i, record) in records.iter().enumerate() {
+            labels.entry(record.label.as_str()).or_default().push(i);
+            // Points within a centimeter share a location.
+            let key = ((record.x * 100.).round() as i64, (record.y * 100.).round() as i64);
+            locations.entry(key).or_default().push(i);
+            *statuses.entry(record.status.as_str()).or_default() += 1;
+        }
+
+        for indices in labels.values().filter(|v| v.len() > 1) {
+            for i in indices {
+                push(
+                    *i,
+                    IssueKind::DuplicateLabel,
+                    format!("Label shared by {} points.", indices.len()),
+                );
+            }
+        }
+
+        for indices in locations.values().filter(|v| v.len() > 1) {
+            for i in indices {
+                let others = indices
+                    .iter()
+                    .filter(|v| *v != i)
+                    .map(|v| records[*v].label.as_str())
+                    .collect::<Vec<&str>>()
+                    .join("; ");
+                push(*i, IssueKind::DuplicateLocation, format!("Same location as {}.", others));
+            }
+        }
+
+        let unusual = match &config.statuses {
+            Some(expected) => statuses
+                .keys()
+                .filter(|v| !expected.iter().any(|e| e.eq_ignore_ascii_case(v)))
+                .cloned()
+                .collect::<HashSet<&str>>(),
+            None => statuses
+                .iter()
+                .filter(|(_, count)| (**count as f64) < config.rare_share * records.len() as f64)
+                .map(|(status, _)| *status)
+                .collect::<HashSet<&str>>(),
+        };
+
+        for (i, record) in records.iter().enumerate() {
+            let (x, y) = wgs84_to_web_mercator(record.lon, record.lat);
+            let distance = ((x - record.x).powi(2) + (y - record.y).powi(2)).sqrt();
+            if distance > config.tolerance {
+                push(
+                    i,
+                    IssueKind::CoordinateMismatch,
+                    format!("WGS84 position projects {:.1} m from the Web Mercator position.", distance),
+                );
+            }
+
+            if let Some(boundary) = &config.boundary {
+                if !boundary.contains(&geo::Point::new(record.lon, record.lat)) {
+                    push(i, IssueKind::OutsideBoundary, "Outside the city boundary.".to_string());
+                }
+            }
+
+            if unusual.contains(record.status.as_str()) {
+                push(
+                    i,
+                    IssueKind::UnusualStatus,
+                    format!("Status {:?} used by {} points.", record.status, statuses[record.status.as_str()]),
+                );
+            }
+
+            if let Err(e) = record.parts() {
+                push(i, IssueKind::ParseFailure, e.to_string());
+            }
+        }
+
+        issues.sort_by(|a, b| a.index.cmp(&b.index).then(a.kind.cmp(&b.kind)));
+        info!("Audit found {} issues.", issues.len());
+        Self { issues }
+    }
+
+    /// Number of issues of each kind.
+    pub fn counts(&self) -> BTreeMap<IssueKind, usize> {
+        let mut counts = BTreeMap::new();
+        for issue in &self.issues {
+            *counts.entry(issue.kind).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P) -> Polite<()> {
+        to_csv(&self.issues, path)?;
+        Ok(())
+    }
+
+    pub fn to_html<P: AsRef<Path>>(&self, path: P) -> Polite<()> {
+        let mut file = std::fs::File::create(path)?;
+        writeln!(file, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">")?;
+        writeln!(file, "<title>Address Audit</title>")?;
+        writeln!(
+            file,
+            "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 6px}}</style></head><body>"
+        )?;
+        writeln!(file, "<h1>Address Audit</h1>\n<h2>Summary</h2>\n<table>")?;
+        writeln!(file, "<tr><th>Issue</th><th>Count</th></tr>")?;
+        for (kind, count) in self.counts() {
+            writeln!(file, "<tr><td>{}</td><td>{}</td></tr>", kind, count)?;
+        }
+        writeln!(file, "</table>\n<h2>Issues</h2>\n<table>")?;
+        writeln!(file, "<tr><th>Index</th><th>Label</th><th>Issue</th><th>Detail</th></tr>")?;
+        for issue in &self.issues {
+            writeln!(
+                file,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                issue.index,
+                escape(&issue.label),
+                issue.kind,
+                escape(&issue.detail)
+            )?;
+        }
+        writeln!(file, "</table>\n</body></html>")?;
+        Ok(())
+    }
+}
+
+/// Escape text for inclusion in HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Audit results in the GUI, with a filter by kind of issue.
+#[derive(Debug, Clone)]
+pub struct AuditPanel {
+    pub audit: Option<Audit>,
+    /// Revision of the address points audited.  Issues refer to points by position, so they only
+    /// apply to this revision.
+    pub revision: Option<Revision>,
+    pub config: AuditConfig,
+    pub visible: HashSet<IssueKind>,
+    pub boundary_path: String,
+    pub report_path: String,
+    pub status: String,
+}
+
+impl Default for AuditPanel {
+    fn default() -> Self {
+        Self {
+            audit: None,
+            revision: None,
+            config: Default::default(),
+            visible: IssueKind::ALL.into_iter().collect(),
+            boundary_path: Default::default(),
+            report_path: "address_audit".to_string(),
+            status: Default::default(),
+        }
+    }
+}
+
+impl AuditPanel {
+    /// Draw the audit controls and findings.  Returns the record index of an issue when the user
+    /// clicks it.  Issues for an earlier revision of the points are dropped.
+    pub fn show(&mut self, ui: &mut Ui, points: &AddressPoints) -> Option<usize> {
+        let mut picked = None;
+        if self.audit.is_some() && self.revision != Some(points.revision) {
+            self.audit = None;
+            self.status = "Addresses changed since the last audit.".to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.boundary_path).hint_text("Boundary GeoJSON"));
+            ui.add(egui::DragValue::new(&mut self.config.tolerance).prefix("Tolerance (m): "));
+        });
+        if ui.button("Run audit").clicked() {
+            let mut config = self.config.clone();
+            config.boundary = None;
+            if !self.boundary_path.is_empty() {
+                match config.with_boundary(&self.boundary_path) {
+                    Ok(value) => config = value,
+                    Err(e) => {
+                        self.status = format!("Could not read boundary: {}", e.to_string());
+                        config = self.config.clone();
+                    }
+                }
+            }
+            self.audit = Some(Audit::address_points(points, &config));
+            self.revision = Some(points.revision);
+        }
+
+        let Some(audit) = &self.audit else {
+            ui.label("No audit run.");
+            return None;
+        };
+
+        let counts = audit.counts();
+        for kind in IssueKind::ALL {
+            let mut visible = self.visible.contains(&kind);
+            let text = format!("{} ({})", kind, counts.get(&kind).unwrap_or(&0));
+            if ui
+                .checkbox(&mut visible, egui::RichText::new(text).color(kind.color()))
+                .changed()
+            {
+                if visible {
+                    self.visible.insert(kind);
+                } else {
+                    self.visible.remove(&kind);
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.report_path).hint_text("Report path"));
+            if ui.button("CSV").clicked() {
+                let path = format!("{}.csv", self.report_path);
+                self.status = match audit.to_csv(&path) {
+                    Ok(_) => format!("Wrote {}.", path),
+                    Err(e) => e.to_string(),
+                };
+            }
+            if ui.button("HTML").clicked() {
+                let path = format!("{}.html", self.report_path);
+                self.status = match audit.to_html(&path) {
+                    Ok(_) => format!("Wrote {}.", path),
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+
+        let issues = audit
+            .issues
+            .iter()
+            .filter(|v| self.visible.contains(&v.kind))
+            .collect::<Vec<&Issue>>();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical().max_height(300.).show_rows(
+            ui,
+            row_height,
+            issues.len(),
+            |ui, range| {
+                for issue in &issues[range] {
+                    let text = egui::RichText::new(format!("{}: {}", issue.label, issue.detail))
+                        .color(issue.kind.color());
+                    if ui.selectable_label(false, text).clicked() {
+                        picked = Some(issue.index);
+                    }
+                }
+            },
+        );
+        picked
+    }
+
+    /// Mark the points with visible issues on the map, unless `points` changed since the audit.
+    pub fn overlay(&self, painter: &Painter, map: &MapView, points: &AddressPoints) {
+        let Some(audit) = self.audit.as_ref().filter(|_| self.revision == Some(points.revision))
+        else {
+            return;
+        };
+        let extent = map.extent();
+        for issue in audit.issues.iter().filter(|v| self.visible.contains(&v.kind)) {
+            if let Some(point) = points.records.get(issue.index) {
+                if extent.contains(&point.geometry) {
+                    painter.circle_stroke(
+                        map.point_to_screen(&point.geometry),
+                        6.0,
+                        egui::Stroke::new(2.0, issue.kind.color()),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crate::prelude::*;
+use egui::{Color32, ColorImage, Painter, Pos2, TextureHandle, TextureOptions};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Half the width of the Web Mercator world, in meters.
+pub(crate) const WORLD_HALF: f64 = 20_037_508.342_789_244;
+/// Width of a tile in pixels.
+const TILE_SIZE: f64 = 256.0;
+/// Tiles kept in memory before the least recently drawn are dropped.
+const CACHE_TILES: usize = 512;
+/// Tiles sent to be read and decoded in one frame, so the first tiles of a view show while the
+/// rest load.
+const LOADS_PER_FRAME: usize = 24;
+
+/// Where the tiles of a basemap are stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum TileSource {
+    /// An MBTiles SQLite file, with rows in TMS order.
+    Mbtiles(PathBuf),
+    /// A directory of `z/x/y.png` or `z/x/y.jpg` files in XYZ order.
+    Directory(PathBuf),
+}
+
+impl TileSource {
+    /// MBTiles if `path` has an `.mbtiles` extension, otherwise a tile directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        match path.extension().and_then(|v| v.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("mbtiles") => Self::Mbtiles(path),
+            _ => Self::Directory(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Mbtiles(path) | Self::Directory(path) => path,
+        }
+    }
+}
+
+/// A raster basemap drawn beneath the map layers.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Basemap {
+    pub name: String,
+    pub source: TileSource,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+impl Basemap {
+    pub fn new<P: AsRef<Path>>(name: &str, path: P) -> Self {
+        Self {
+            name: name.to_string(),
+            source: TileSource::from_path(path),
+            min_zoom: 0,
+            max_zoom: 19,
+        }
+    }
+
+    /// Tile zoom level whose pixels best match a map `resolution` in meters per point.
+    pub fn zoom(&self, resolution: f64) -> u8 {
+        let zoom = (2.0 * WORLD_HALF / (TILE_SIZE * resolution)).log2().round();
+        (zoom.max(0.0) as u8).clamp(self.min_zoom, self.max_zoom)
+    }
+}
+
+/// Position of a tile in the XYZ scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileId {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// Web Mercator bounds of the tile as (x_min, y_min, x_max, y_max).
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let size = 2.0 * WORLD_HALF / f64::from(1u32 << self.z);
+        let x_min = -WORLD_HALF + f64::from(self.x) * size;
+        let y_max = WORLD_HALF - f64::from(self.y) * size;
+        (x_min, y_max - size, x_min + size, y_max)
+    }
+}
+
+/// Tiles at zoom level `z` covering the Web Mercator area from (`x_min`, `y_min`) to (`x_max`,
+/// `y_max`).
+pub fn covering_tiles(x_min: f64, y_min: f64, x_max: f64, y_max: f64, z: u8) -> Vec<TileId> {
+    let count = 1u32 << z;
+    let size = 2.0 * WORLD_HALF / f64::from(count);
+    let column = |x: f64| (((x + WORLD_HALF) / size).floor().max(0.0) as u32).min(count - 1);
+    let row = |y: f64| (((WORLD_HALF - y) / size).floor().max(0.0) as u32).min(count - 1);
+    let mut tiles = Vec::new();
+    for y in row(y_max)..=row(y_min) {
+        for x in column(x_min)..=column(x_max) {
+            tiles.push(TileId { z, x, y });
+        }
+    }
+    tiles
+}
+
+/// Tiles covering the visible extent of `map` at zoom level `z`.
+pub fn visible_tiles(map: &MapView, z: u8) -> Vec<TileId> {
+    let extent = map.extent();
+    covering_tiles(extent.x_min(), extent.y_min(), extent.x_max(), extent.y_max(), z)
+}
+
+/// A tile of a particular basemap.
+type TileKey = (TileSource, TileId);
+
+/// Decoded tile images, with `None` for tiles that are missing or unreadable.
+type Decoded = Vec<(TileKey, Option<ColorImage>)>;
+
+/// Decoded tiles of the basemaps in use, kept as GPU textures.  Missing tiles are remembered as
+/// `None` so they are not looked up again and draw as blank background.  Tiles are read and
+/// decoded on background threads, and only the finished images are uploaded by the ui.
+#[derive(Clone, Default)]
+pub struct TileCache {
+    pub tiles: HashMap<TileKey, Option<TextureHandle>>,
+    /// Tiles by the draw in which they were last used, least recent first.
+    order: BTreeMap<u64, TileKey>,
+    /// The draw in which each tile was last used, indexing `order`.
+    used: HashMap<TileKey, u64>,
+    /// Count of tile uses, ordering `order`.
+    tick: u64,
+    connections: HashMap<PathBuf, Arc<Mutex<Connection>>>,
+    /// MBTiles files that could not be opened, so they are not retried for every tile.
+    failed: HashSet<PathBuf>,
+    /// Tiles being read and decoded in the background.
+    loading: HashSet<TileKey>,
+    /// Batches of tiles being read and decoded, uploaded as each finishes.
+    tasks: Vec<Task<Decoded>>,
+}
+
+impl std::fmt::Debug for TileCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TileCache")
+            .field("tiles", &self.tiles.len())
+            .field("loading", &self.loading.len())
+            .field("connections", &self.connections.len())
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+/// Reads the tiles of one source, on any thread.
+#[derive(Clone)]
+enum TileReader {
+    Directory(PathBuf),
+    Mbtiles(PathBuf, Arc<Mutex<Connection>>),
+    /// An MBTiles file that could not be opened.
+    Missing,
+}
+
+impl TileReader {
+    /// Encoded image bytes of a tile, or `None` if the source has no such tile.
+    fn read(&self, tile: &TileId) -> Whimsy<Option<Vec<u8>>> {
+        match self {
+            Self::Directory(dir) => {
+                for ext in ["png", "jpg", "jpeg"] {
+                    let path = dir
+                        .join(tile.z.to_string())
+                        .join(tile.x.to_string())
+                        .join(format!("{}.{}", tile.y, ext));
+                    if path.exists() {
+                        return std::fs::read(&path)
+                            .map(Some)
+                            .map_err(|e| WhimsyError::io(&path, e));
+                    }
+                }
+                Ok(None)
+            }
+            Self::Mbtiles(path, shared) => {
+                let connection = shared.lock().map_err(|e| WhimsyError::parse(path, e))?;
+                // MBTiles number rows from the south, XYZ from the north.
+                let row = (1u32 << tile.z) - 1 - tile.y;
+                connection
+                    .query_row(
+                        "SELECT tile_data FROM tiles \
+                         WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                        rusqlite::params![tile.z, tile.x, row],
+                        |r| r.get::<_, Vec<u8>>(0),
+                    )
+                    .optional()
+                    .map_err(|e| WhimsyError::parse(path, e))
+            }
+            Self::Missing => Ok(None),
+        }
+    }
+
+    /// Read and decode a tile, logging and returning `None` if it is missing or unreadable.
+    fn decode(&self, tile: &TileId) -> Option<ColorImage> {
+        match self.read(tile) {
+            Ok(Some(bytes)) => match image::load_from_memory(&bytes) {
+                Ok(decoded) => {
+                    let rgba = decoded.to_rgba8();
+                    let size = [rgba.width() as usize, rgba.height() as usize];
+                    Some(ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+                }
+                Err(e) => {
+                    tracing::warn!("Could not decode tile {:?}: {}", tile, e);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("Could not read tile {:?}: {}", tile, e);
+                None
+            }
+        }
+    }
+}
+
+impl TileCache {
+    /// Encoded image bytes of a tile, or `None` if the source has no such tile.
+    pub(crate) fn read(&mut self, source: &TileSource, tile: &TileId) -> Whimsy<Option<Vec<u8>>> {
+        self.reader(source)?.read(tile)
+    }
+
+    /// Reader for the tiles of `source`, opening MBTiles files on first use.
+    fn reader(&mut self, source: &TileSource) -> Whimsy<TileReader> {
+        match source {
+            TileSource::Directory(dir) => Ok(TileReader::Directory(dir.clone())),
+            TileSource::Mbtiles(path) => {
+                if self.failed.contains(path) {
+                    return Ok(TileReader::Missing);
+                }
+                let shared = match self.connections.get(path) {
+                    Some(shared) => shared.clone(),
+                    None => {
+                        let connection =
+                            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                                .map_err(|e| {
+                                    self.failed.insert(path.clone());
+                                    WhimsyError::parse(path, e)
+                                })?;
+                        let shared = Arc::new(Mutex::new(connection));
+                        self.connections.insert(path.clone(), shared.clone());
+                        shared
+                    }
+                };
+                Ok(TileReader::Mbtiles(path.clone(), shared))
+            }
+        }
+    }
+
+    /// Work reading and decoding `tiles` of `source`, to run on any thread.
+    fn decoder(
+        &mut self,
+        source: &TileSource,
+        tiles: Vec<TileId>,
+    ) -> impl FnOnce() -> Decoded + Send + 'static {
+        let reader = match self.reader(source) {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::warn!("Could not open basemap: {}", e);
+                TileReader::Missing
+            }
+        };
+        let source = source.clone();
+        move || {
+            tiles
+                .into_iter()
+                .map(|tile| ((source.clone(), tile), reader.decode(&tile)))
+                .collect()
+        }
+    }
+
+    /// Upload decoded tiles as textures, dropping the least recently drawn beyond
+    /// [`CACHE_TILES`].
+    fn upload(&mut self, ctx: &egui::Context, decoded: Decoded) {
+        for (key, image) in decoded {
+            let tile = key.1;
+            let texture = image.map(|image| {
+                let name = format!("tile/{}/{}/{}", tile.z, tile.x, tile.y);
+                ctx.load_texture(name, image, TextureOptions::LINEAR)
+            });
+            self.loading.remove(&key);
+            self.tiles.insert(key.clone(), texture);
+            self.touch(&key);
+        }
+        while self.tiles.len() > CACHE_TILES {
+            let Some((_, old)) = self.order.pop_first() else {
+                break;
+            };
+            self.used.remove(&old);
+            self.tiles.remove(&old);
+        }
+    }
+
+    /// Mark the tile `key` as the most recently used.
+    fn touch(&mut self, key: &TileKey) {
+        self.tick += 1;
+        if let Some(last) = self.used.insert(key.clone(), self.tick) {
+            self.order.remove(&last);
+        }
+        self.order.insert(self.tick, key.clone());
+    }
+
+    /// Draw the tiles of `basemap` covering the view, with `opacity` from 0 to 1.  Missing tiles
+    /// are read and decoded in the background, and drawn once a later frame finds them ready.
+    pub fn draw(&mut self, painter: &Painter, map: &MapView, basemap: &Basemap, opacity: f32) {
+        let ctx = painter.ctx().clone();
+        let mut finished = Vec::new();
+        self.tasks.retain(|v| match v.poll() {
+            Some(decoded) => {
+                finished.push(decoded);
+                false
+            }
+            None => true,
+        });
+        for decoded in finished {
+            self.upload(&ctx, decoded);
+        }
+
+        let z = basemap.zoom(map.resolution);
+        let tiles = visible_tiles(map, z);
+        let mut missing = tiles
+            .iter()
+            .copied()
+            .filter(|v| {
+                let key = (basemap.source.clone(), *v);
+                !self.tiles.contains_key(&key) && !self.loading.contains(&key)
+            })
+            .collect::<Vec<TileId>>();
+        if missing.len() > LOADS_PER_FRAME {
+            missing.truncate(LOADS_PER_FRAME);
+            ctx.request_repaint();
+        }
+        if !missing.is_empty() {
+            for tile in &missing {
+                self.loading.insert((basemap.source.clone(), *tile));
+            }
+            let work = self.decoder(&basemap.source, missing);
+            self.tasks.push(Task::spawn(&ctx, work));
+        }
+        self.paint(painter, map, basemap, opacity, &tiles);
+    }
+
+    /// Draw as [`TileCache::draw`], reading and decoding every missing tile on this thread
+    /// first.  For offscreen rendering, where no later frame would show tiles still loading.
+    pub fn draw_now(&mut self, painter: &Painter, map: &MapView, basemap: &Basemap, opacity: f32) {
+        let z = basemap.zoom(map.resolution);
+        let tiles = visible_tiles(map, z);
+        let missing = tiles
+            .iter()
+            .copied()
+            .filter(|v| !self.tiles.contains_key(&(basemap.source.clone(), *v)))
+            .collect::<Vec<TileId>>();
+        if !missing.is_empty() {
+            let decoded = self.decoder(&basemap.source, missing)();
+            self.upload(painter.ctx(), decoded);
+        }
+        self.paint(painter, map, basemap, opacity, &tiles);
+    }
+
+    /// Draw the uploaded textures among `tiles`, marking them as used.
+    fn paint(
+        &mut self,
+        painter: &Painter,
+        map: &MapView,
+        basemap: &Basemap,
+        opacity: f32,
+        tiles: &[TileId],
+    ) {
+        let tint = Color32::WHITE.gamma_multiply(opacity);
+        let uv = egui::Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        for tile in tiles {
+            let key = (basemap.source.clone(), *tile);
+            if !self.tiles.contains_key(&key) {
+                continue;
+            }
+            self.touch(&key);
+            if let Some(Some(texture)) = self.tiles.get(&key) {
+                let (x_min, y_min, x_max, y_max) = tile.bounds();
+                let rect = egui::Rect::from_min_max(
+                    map.to_screen(x_min, y_max),
+                    map.to_screen(x_max, y_min),
+                );
+                painter.image(texture.id(), rect, uv, tint);
+            }
+        }
+    }
+}
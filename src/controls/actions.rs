@@ -17,6 +17,7 @@ pub enum Action {
     DragWindow,
     DragResizeWindow,
     ShowWindowMenu,
+    CycleTheme,
     // #[cfg(macos_platform)]
     // CycleOptionAsAlt,
     // #[cfg(macos_platform)]
@@ -43,6 +44,7 @@ impl Action {
             Action::DragWindow => "Start window drag",
             Action::DragResizeWindow => "Start window drag-resize",
             Action::ShowWindowMenu => "Show window menu",
+            Action::CycleTheme => "Cycle light, dark and system themes",
             // #[cfg(macos_platform)]
             // Action::CycleOptionAsAlt => "Cycle option as alt mode",
             // #[cfg(macos_platform)]
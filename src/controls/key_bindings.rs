@@ -39,6 +39,7 @@ pub const KEY_BINDINGS: &[Binding<&'static str>] = &[
     Binding::new("C", ModifiersState::CONTROL, Action::NextCursor),
     Binding::new("C", ModifiersState::ALT, Action::NextCustomCursor),
     Binding::new("Z", ModifiersState::CONTROL, Action::ToggleCursorVisibility),
+    Binding::new("T", ModifiersState::CONTROL, Action::CycleTheme),
     #[cfg(macos_platform)]
     Binding::new("T", ModifiersState::SUPER, Action::CreateNewTab),
     #[cfg(macos_platform)]
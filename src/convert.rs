@@ -1,9 +1,17 @@
-// use crate::prelude::*;
+use crate::prelude::*;
 use galileo_types::cartesian::{CartesianPoint2d, Point2d};
-use galileo_types::impls::ClosedContour;
+use galileo_types::contour::Contour as _;
+use galileo_types::geometry::Geom;
+use galileo_types::impls::{ClosedContour, Contour, MultiContour};
+use galileo_types::multi_contour::MultiContour as _;
+use galileo_types::multi_point::MultiPoint as _;
 use geo::algorithm::bounding_rect::BoundingRect;
+use geo::algorithm::map_coords::MapCoordsInPlace;
 use geo::geometry::Rect;
-use geo_types::{Coord, LineString, MultiPolygon, Point, Polygon};
+use geo_types::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon, Point,
+    Polygon,
+};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::fmt::Debug;
 
@@ -20,6 +28,19 @@ impl<T: Debug + Clone> Convert<T> {
     }
 }
 
+impl<T: Debug + Clone + MapCoordsInPlace<f64>> Convert<T> {
+    /// Transform the coordinates of the geometry from the system `from` to the system `to`.
+    pub fn reproject(mut self, from: &Crs, to: &Crs) -> Self {
+        if from != to {
+            self.0.map_coords_in_place(|c| {
+                let (x, y) = from.transform(to, c.x, c.y);
+                Coord { x, y }
+            });
+        }
+        self
+    }
+}
+
 impl Convert<MultiPolygon> {
     pub fn multipolygon(self) -> galileo_types::impls::MultiPolygon<Point2d> {
         let conv = self
@@ -145,6 +166,17 @@ impl Convert<LineString> {
         ClosedContour::new(points)
     }
 
+    /// Open contour through the points of the line.
+    pub fn line(self) -> Contour<Point2d> {
+        let points = self
+            .0
+            .into_inner()
+            .into_iter()
+            .map(|v| Convert::new(v).point())
+            .collect::<Vec<Point2d>>();
+        Contour::open(points)
+    }
+
     pub fn contour_point(self) -> ClosedContour<Point2d> {
         let line = self.0.into_inner();
         let points = line
@@ -191,3 +223,173 @@ impl Convert<Coord> {
         Point2d::new(self.x(), self.y())
     }
 }
+
+impl Convert<MultiLineString> {
+    pub fn multicontour(self) -> MultiContour<Point2d> {
+        self.0
+            .into_iter()
+            .map(|v| Convert::new(v).line())
+            .collect::<Vec<Contour<Point2d>>>()
+            .into()
+    }
+}
+
+impl Convert<MultiPoint> {
+    pub fn multipoint(self) -> galileo_types::impls::MultiPoint<Point2d> {
+        self.0
+            .into_iter()
+            .map(|v| Convert::new(v).point())
+            .collect::<Vec<Point2d>>()
+            .into()
+    }
+}
+
+impl Convert<Geometry> {
+    /// Galileo geometries equivalent to the geometry.  Collections are flattened, lines become
+    /// open contours, and rectangles and triangles become polygons.
+    pub fn geoms(self) -> Vec<Geom<Point2d>> {
+        match self.0 {
+            Geometry::Point(v) => vec![Geom::Point(Convert::new(v).point())],
+            Geometry::Line(v) => vec![Geom::Contour(Convert::new(LineString::from(v)).line())],
+            Geometry::LineString(v) => vec![Geom::Contour(Convert::new(v).line())],
+            Geometry::Polygon(v) => vec![Geom::Polygon(Convert::new(v).polygon())],
+            Geometry::MultiPoint(v) => vec![Geom::MultiPoint(Convert::new(v).multipoint())],
+            Geometry::MultiLineString(v) => {
+                vec![Geom::MultiContour(Convert::new(v).multicontour())]
+            }
+            Geometry::MultiPolygon(v) => vec![Geom::MultiPolygon(Convert::new(v).multipolygon())],
+            Geometry::GeometryCollection(v) => Convert::new(v).geoms(),
+            Geometry::Rect(v) => vec![Geom::Polygon(Convert::new(v.to_polygon()).polygon())],
+            Geometry::Triangle(v) => vec![Geom::Polygon(Convert::new(v.to_polygon()).polygon())],
+        }
+    }
+
+    /// Polygons in the geometry, or `None` if it has none.  Other members of a collection are
+    /// ignored.
+    pub fn multipolygon(self) -> Option<MultiPolygon> {
+        let polygons = match self.0 {
+            Geometry::Polygon(v) => vec![v],
+            Geometry::MultiPolygon(v) => v.0,
+            Geometry::Rect(v) => vec![v.to_polygon()],
+            Geometry::Triangle(v) => vec![v.to_polygon()],
+            Geometry::GeometryCollection(v) => v
+                .into_iter()
+                .filter_map(|g| Convert::new(g).multipolygon())
+                .flat_map(|m| m.0)
+                .collect(),
+            _ => Vec::new(),
+        };
+        if polygons.is_empty() {
+            None
+        } else {
+            Some(MultiPolygon::new(polygons))
+        }
+    }
+}
+
+impl Convert<GeometryCollection> {
+    pub fn geoms(self) -> Vec<Geom<Point2d>> {
+        self.0
+            .into_iter()
+            .flat_map(|v| Convert::new(v).geoms())
+            .collect()
+    }
+}
+
+impl Convert<Point2d> {
+    pub fn geo(self) -> Point {
+        Point::new(self.0.x(), self.0.y())
+    }
+}
+
+impl Convert<Vec<Point2d>> {
+    pub fn geo(self) -> LineString {
+        self.0
+            .into_iter()
+            .map(|v| Coord { x: v.x(), y: v.y() })
+            .collect::<Vec<Coord>>()
+            .into()
+    }
+}
+
+impl Convert<Contour<Point2d>> {
+    /// Line string through the points, repeating the first point if the contour is closed.
+    pub fn geo(self) -> LineString {
+        let mut points = self.0.iter_points().cloned().collect::<Vec<Point2d>>();
+        if self.0.is_closed() {
+            if let Some(first) = points.first().cloned() {
+                points.push(first);
+            }
+        }
+        Convert::new(points).geo()
+    }
+}
+
+impl Convert<ClosedContour<Point2d>> {
+    /// Closed line string around the contour.
+    pub fn geo(self) -> LineString {
+        let mut line = Convert::new(self.0.points).geo();
+        line.close();
+        line
+    }
+}
+
+impl Convert<galileo_types::impls::Polygon<Point2d>> {
+    pub fn geo(self) -> Polygon {
+        let exterior = Convert::new(self.0.outer_contour).geo();
+        let interiors = self
+            .0
+            .inner_contours
+            .into_iter()
+            .map(|v| Convert::new(v).geo())
+            .collect::<Vec<LineString>>();
+        Polygon::new(exterior, interiors)
+    }
+}
+
+impl Convert<galileo_types::impls::MultiPolygon<Point2d>> {
+    pub fn geo(self) -> MultiPolygon {
+        MultiPolygon::new(
+            self.0
+                .parts
+                .into_iter()
+                .map(|v| Convert::new(v).geo())
+                .collect(),
+        )
+    }
+}
+
+impl Convert<MultiContour<Point2d>> {
+    pub fn geo(self) -> MultiLineString {
+        MultiLineString::new(
+            self.0
+                .contours()
+                .map(|v| Convert::new(v.clone()).geo())
+                .collect(),
+        )
+    }
+}
+
+impl Convert<galileo_types::impls::MultiPoint<Point2d>> {
+    pub fn geo(self) -> MultiPoint {
+        MultiPoint::new(
+            self.0
+                .iter_points()
+                .map(|v| Convert::new(*v).geo())
+                .collect(),
+        )
+    }
+}
+
+impl Convert<Geom<Point2d>> {
+    pub fn geo(self) -> Geometry {
+        match self.0 {
+            Geom::Point(v) => Geometry::Point(Convert::new(v).geo()),
+            Geom::MultiPoint(v) => Geometry::MultiPoint(Convert::new(v).geo()),
+            Geom::Contour(v) => Geometry::LineString(Convert::new(v).geo()),
+            Geom::MultiContour(v) => Geometry::MultiLineString(Convert::new(v).geo()),
+            Geom::Polygon(v) => Geometry::Polygon(Convert::new(v).geo()),
+            Geom::MultiPolygon(v) => Geometry::MultiPolygon(Convert::new(v).geo()),
+        }
+    }
+}
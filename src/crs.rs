@@ -0,0 +1,320 @@
+use crate::prelude::*;
+use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+use galileo_types::impls::{ClosedContour, MultiPolygon, Polygon};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+/// Semi-major axis of the GRS80 ellipsoid, in meters.
+pub const GRS80_A: f64 = 6_378_137.0;
+/// Inverse flattening of the GRS80 ellipsoid.
+pub const GRS80_RF: f64 = 298.257_222_101;
+/// Length of the international foot, in meters.
+pub const FOOT: f64 = 0.3048;
+/// Length of the US survey foot, in meters.
+pub const US_SURVEY_FOOT: f64 = 1200.0 / 3937.0;
+/// Feet in a statute mile.
+pub const FEET_PER_MILE: f64 = 5280.0;
+
+/// Parameters of a Lambert Conformal Conic projection with two standard parallels on the GRS80
+/// ellipsoid, as used by the NAD83 state plane zones.  Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct LambertConformal {
+    pub lat_1: f64,
+    pub lat_2: f64,
+    pub lat_0: f64,
+    pub lon_0: f64,
+    /// False easting in meters.
+    pub false_easting: f64,
+    /// False northing in meters.
+    pub false_northing: f64,
+    /// Length of the coordinate unit in meters.
+    pub unit: f64,
+}
+
+/// NAD83 / Oregon South (ft), EPSG:2270.  The EPSG definition uses the international foot; set
+/// `unit` to [`US_SURVEY_FOOT`] for data delivered in survey feet.
+pub const OREGON_SOUTH: LambertConformal = LambertConformal {
+    lat_1: 44.0,
+    lat_2: 42.0 + 1.0 / 3.0,
+    lat_0: 41.0 + 2.0 / 3.0,
+    lon_0: -120.5,
+    false_easting: 1_500_000.000_101_6,
+    false_northing: 0.0,
+    unit: FOOT,
+};
+
+impl LambertConformal {
+    fn eccentricity() -> f64 {
+        let f = 1.0 / GRS80_RF;
+        (2.0 * f - f * f).sqrt()
+    }
+
+    fn m(phi: f64, e: f64) -> f64 {
+        phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt()
+    }
+
+    fn t(phi: f64, e: f64) -> f64 {
+        let es = e * phi.sin();
+        (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - es) / (1.0 + es)).powf(e / 2.0)
+    }
+
+    /// Cone constant `n`, scale `a * F` and radius at the origin latitude.
+    fn cone(&self) -> (f64, f64, f64) {
+        let e = Self::eccentricity();
+        let (phi_1, phi_2) = (self.lat_1.to_radians(), self.lat_2.to_radians());
+        let (m1, m2) = (Self::m(phi_1, e), Self::m(phi_2, e));
+        let (t1, t2) = (Self::t(phi_1, e), Self::t(phi_2, e));
+        let n = (m1.ln() - m2.ln()) / (t1.ln() - t2.ln());
+        let af = GRS80_A * m1 / (n * t1.powf(n));
+        let rho_0 = af * Self::t(self.lat_0.to_radians(), e).powf(n);
+        (n, af, rho_0)
+    }
+
+    /// Project longitude and latitude in degrees to grid coordinates.
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let e = Self::eccentricity();
+        let (n, af, rho_0) = self.cone();
+        let rho = af * Self::t(lat.to_radians(), e).powf(n);
+        let theta = n * (lon - self.lon_0).to_radians();
+        let x = self.false_easting + rho * theta.sin();
+        let y = self.false_northing + rho_0 - rho * theta.cos();
+        (x / self.unit, y / self.unit)
+    }
+
+    /// Unproject grid coordinates to longitude and latitude in degrees.
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let e = Self::eccentricity();
+        let (n, af, rho_0) = self.cone();
+        let dx = x * self.unit - self.false_easting;
+        let dy = rho_0 - (y * self.unit - self.false_northing);
+        let rho = n.signum() * (dx * dx + dy * dy).sqrt();
+        let theta = (n.signum() * dx).atan2(n.signum() * dy);
+        let t = (rho / af).powf(1.0 / n);
+        let mut phi = FRAC_PI_2 - 2.0 * t.atan();
+        for _ in 0..15 {
+            let es = e * phi.sin();
+            let next = FRAC_PI_2 - 2.0 * (t * ((1.0 - es) / (1.0 + es)).powf(e / 2.0)).atan();
+            let done = (next - phi).abs() < 1e-12;
+            phi = next;
+            if done {
+                break;
+            }
+        }
+        ((theta / n).to_degrees() + self.lon_0, phi.to_degrees())
+    }
+}
+
+/// Coordinate reference system of a dataset.  Transforms pass through WGS84 longitude and
+/// latitude, treating NAD83 and WGS84 as the same datum, which holds to about a meter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum Crs {
+    /// Longitude and latitude in degrees, EPSG:4326.
+    Wgs84,
+    /// Spherical Mercator meters, EPSG:3857.  Used for display.
+    #[default]
+    WebMercator,
+    /// Oregon State Plane South in feet, EPSG:2270.
+    OregonSouth,
+    /// Any other Lambert Conformal Conic grid.
+    Lambert(LambertConformal),
+}
+
+impl Crs {
+    /// EPSG code, if the system has one.
+    pub fn epsg(&self) -> Option<u32> {
+        match self {
+            Self::Wgs84 => Some(4326),
+            Self::WebMercator => Some(3857),
+            Self::OregonSouth => Some(2270),
+            Self::Lambert(_) => None,
+        }
+    }
+
+    pub fn from_epsg(code: u32) -> Option<Self> {
+        match code {
+            4326 => Some(Self::Wgs84),
+            3857 | 900913 => Some(Self::WebMercator),
+            2270 => Some(Self::OregonSouth),
+            _ => None,
+        }
+    }
+
+    /// Convert coordinates in this system to WGS84 longitude and latitude.
+    pub fn to_wgs84(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Self::Wgs84 => (x, y),
+            Self::WebMercator => web_mercator_to_wgs84(x, y),
+            Self::OregonSouth => OREGON_SOUTH.inverse(x, y),
+            Self::Lambert(lcc) => lcc.inverse(x, y),
+        }
+    }
+
+    /// Convert WGS84 longitude and latitude to coordinates in this system.
+    pub fn from_wgs84(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Self::Wgs84 => (lon, lat),
+            Self::WebMercator => wgs84_to_web_mercator(lon, lat),
+            Self::OregonSouth => OREGON_SOUTH.forward(lon, lat),
+            Self::Lambert(lcc) => lcc.forward(lon, lat),
+        }
+    }
+
+    /// Convert coordinates in this system to the system `to`.
+    pub fn transform(&self, to: &Crs, x: f64, y: f64) -> (f64, f64) {
+        if self == to {
+            return (x, y);
+        }
+        let (lon, lat) = self.to_wgs84(x, y);
+        to.from_wgs84(lon, lat)
+    }
+
+    pub fn point(&self, to: &Crs, point: &Point2d) -> Point2d {
+        let (x, y) = self.transform(to, point.x(), point.y());
+        Point2d::new(x, y)
+    }
+
+    pub fn contour(&self, to: &Crs, contour: &ClosedContour<Point2d>) -> ClosedContour<Point2d> {
+        ClosedContour::new(contour.points.iter().map(|v| self.point(to, v)).collect())
+    }
+
+    pub fn polygon(&self, to: &Crs, polygon: &Polygon<Point2d>) -> Polygon<Point2d> {
+        Polygon {
+            outer_contour: self.contour(to, &polygon.outer_contour),
+            inner_contours: polygon
+                .inner_contours
+                .iter()
+                .map(|v| self.contour(to, v))
+                .collect(),
+        }
+    }
+
+    pub fn multipolygon(&self, to: &Crs, multi: &MultiPolygon<Point2d>) -> MultiPolygon<Point2d> {
+        MultiPolygon {
+            parts: multi.parts.iter().map(|v| self.polygon(to, v)).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Crs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.epsg() {
+            Some(code) => write!(f, "EPSG:{}", code),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl std::str::FromStr for Crs {
+    type Err = WhimsyError;
+
+    /// Parse an EPSG code such as "EPSG:2270" or "2270".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim();
+        let code = code
+            .strip_prefix("EPSG:")
+            .or_else(|| code.strip_prefix("epsg:"))
+            .unwrap_or(code);
+        code.parse::<u32>()
+            .ok()
+            .and_then(Crs::from_epsg)
+            .ok_or_else(|| WhimsyError::Crs(format!("unsupported coordinate system {}", s)))
+    }
+}
+
+/// Bounding box of the outer contours of `multi`.
+pub fn multipolygon_bounds(multi: &MultiPolygon<Point2d>) -> Rect {
+    let mut points = multi.parts.iter().flat_map(|v| v.outer_contour.points.iter());
+    let Some(first) = points.next() else {
+        return Rect::new(0.0, 0.0, 0.0, 0.0);
+    };
+    points.fold(
+        Rect::new(first.x(), first.y(), first.x(), first.y()),
+        |rect, v| {
+            Rect::new(
+                rect.x_min().min(v.x()),
+                rect.y_min().min(v.y()),
+                rect.x_max().max(v.x()),
+                rect.y_max().max(v.y()),
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points across the Oregon South zone and its margins, as longitude and latitude.
+    const POINTS: [(f64, f64); 6] = [
+        (-123.328, 42.439),
+        (-124.5, 42.0),
+        (-116.5, 44.4),
+        (-120.5, 41.0 + 2.0 / 3.0),
+        (-121.75, 43.2),
+        (-118.0, 42.1),
+    ];
+
+    #[test]
+    fn projects_origin_to_false_origin() {
+        // EPSG:2270 places the natural origin at 41°40'N 120°30'W, with a false easting of
+        // 4,921,259.843 ft and no false northing.
+        let (x, y) = OREGON_SOUTH.forward(-120.5, 41.0 + 2.0 / 3.0);
+        assert!((x - 4_921_259.843).abs() < 1e-3, "{}", x);
+        assert!(y.abs() < 1e-6, "{}", y);
+        let (lon, lat) = OREGON_SOUTH.inverse(4_921_259.843, 0.0);
+        assert!((lon + 120.5).abs() < 1e-8, "{}", lon);
+        assert!((lat - 41.0 - 2.0 / 3.0).abs() < 1e-8, "{}", lat);
+    }
+
+    #[test]
+    fn keeps_true_scale_on_standard_parallels() {
+        // Distances along each standard parallel match the ellipsoid, so a small step in
+        // longitude covers a * m * dλ on the grid.
+        let e = LambertConformal::eccentricity();
+        let step: f64 = 1e-4;
+        for lat in [OREGON_SOUTH.lat_1, OREGON_SOUTH.lat_2] {
+            let (x0, y0) = OREGON_SOUTH.forward(-120.5, lat);
+            let (x1, y1) = OREGON_SOUTH.forward(-120.5 + step, lat);
+            let grid = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt() * FOOT;
+            let ground = GRS80_A * LambertConformal::m(lat.to_radians(), e) * step.to_radians();
+            assert!((grid / ground - 1.0).abs() < 1e-7, "{}: {}", lat, grid / ground);
+        }
+    }
+
+    #[test]
+    fn round_trips_oregon_south() {
+        for (lon, lat) in POINTS {
+            let (x, y) = OREGON_SOUTH.forward(lon, lat);
+            let (lon_2, lat_2) = OREGON_SOUTH.inverse(x, y);
+            assert!((lon_2 - lon).abs() < 1e-9, "{} {}: {}", lon, lat, lon_2);
+            assert!((lat_2 - lat).abs() < 1e-9, "{} {}: {}", lon, lat, lat_2);
+        }
+    }
+
+    #[test]
+    fn round_trips_survey_feet() {
+        let survey = LambertConformal {
+            unit: US_SURVEY_FOOT,
+            ..OREGON_SOUTH
+        };
+        for (lon, lat) in POINTS {
+            let (x, y) = survey.forward(lon, lat);
+            let (x_ft, y_ft) = OREGON_SOUTH.forward(lon, lat);
+            assert!((x * US_SURVEY_FOOT - x_ft * FOOT).abs() < 1e-6);
+            assert!((y * US_SURVEY_FOOT - y_ft * FOOT).abs() < 1e-6);
+            let (lon_2, lat_2) = survey.inverse(x, y);
+            assert!((lon_2 - lon).abs() < 1e-9 && (lat_2 - lat).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_web_mercator() {
+        for (lon, lat) in POINTS {
+            let (x, y) = OREGON_SOUTH.forward(lon, lat);
+            let (mx, my) = Crs::OregonSouth.transform(&Crs::WebMercator, x, y);
+            let (x_2, y_2) = Crs::WebMercator.transform(&Crs::OregonSouth, mx, my);
+            assert!((x_2 - x).abs() < 1e-4, "{} {}: {}", lon, lat, x_2);
+            assert!((y_2 - y).abs() < 1e-4, "{} {}: {}", lon, lat, y_2);
+        }
+    }
+}
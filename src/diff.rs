@@ -0,0 +1,412 @@
+use crate::prelude::*;
+use egui::{Color32, Painter, Ui};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+use polite::Polite;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use tracing::info;
+
+/// Kinds of change between two snapshots of a dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum ChangeKind {
+    /// The record is only in the newer snapshot.
+    Added,
+    /// The record is only in the older snapshot.
+    Removed,
+    /// The address status differs.
+    Status,
+    /// The parcel owner name differs.
+    Owner,
+    /// The geometry moved farther than the tolerance.
+    Moved,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 5] = [
+        ChangeKind::Added,
+        ChangeKind::Removed,
+        ChangeKind::Status,
+        ChangeKind::Owner,
+        ChangeKind::Moved,
+    ];
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            ChangeKind::Added => Color32::from_rgb(0, 158, 115),
+            ChangeKind::Removed => Color32::from_rgb(213, 94, 0),
+            ChangeKind::Status => Color32::from_rgb(240, 228, 66),
+            ChangeKind::Owner => Color32::from_rgb(204, 121, 167),
+            ChangeKind::Moved => Color32::from_rgb(86, 180, 233),
+        }
+    }
+}
+
+impl std::fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// A single entry in the change log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    /// Address label or parcel MapNum used to match the records.
+    pub key: String,
+    pub kind: ChangeKind,
+    pub before: String,
+    pub after: String,
+    /// Distance moved in Web Mercator meters, for [`ChangeKind::Moved`].
+    pub distance: f64,
+    /// Web Mercator position of the change, from the newer snapshot where possible.
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Which kind of dataset a [`Diff`] compares.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffSource {
+    #[default]
+    Addresses,
+    Parcels,
+}
+
+impl std::fmt::Display for DiffSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// Changes between an older and a newer snapshot of the addresses or parcels.
+#[derive(Debug, Clone, Default)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    /// Compare address snapshots, matching records by label.  Points that moved farther than
+    /// `tolerance` meters are reported.
+    pub fn addresses(old: &Addresses, new: &Addresses, tolerance: f64) -> Self {
+        let mut changes = Vec::new();
+        let pairs = pair(
+            old.records.iter().map(|v| v.label.as_str()),
+            new.records.iter().map(|v| v.label.as_str()),
+        );
+        for (key, before, after) in pairs {
+            let change = |kind, before: String, after: String, distance, x, y| Change {
+                key: key.clone(),
+                kind,
+                before,
+                after,
+                distance,
+                x,
+                y,
+            };
+            match (before.map(|i| &old.records[i]), after.map(|i| &new.records[i])) {
+                (Some(a), Some(b)) => {
+                    if a.status != b.status {
+                        changes.push(change(
+                            ChangeKind::Status,
+                            a.status.clone(),
+                            b.status.clone(),
+                            0.0,
+                            b.x,
+                            b.y,
+                        ));
+                    }
+                    let distance = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+                    if distance > tolerance {
+                        changes.push(change(
+                            ChangeKind::Moved,
+                            format!("{:.1}, {:.1}", a.x, a.y),
+                            format!("{:.1}, {:.1}", b.x, b.y),
+                            distance,
+                            b.x,
+                            b.y,
+                        ));
+                    }
+                }
+                (None, Some(b)) => changes.push(change(
+                    ChangeKind::Added,
+                    String::new(),
+                    b.status.clone(),
+                    0.0,
+                    b.x,
+                    b.y,
+                )),
+                (Some(a), None) => changes.push(change(
+                    ChangeKind::Removed,
+                    a.status.clone(),
+                    String::new(),
+                    0.0,
+                    a.x,
+                    a.y,
+                )),
+                (None, None) => {}
+            }
+        }
+        info!("{} address changes.", changes.len());
+        Self { changes }
+    }
+
+    /// Compare parcel snapshots, matching records by MapNum.  A parcel has moved when a corner
+    /// of its bounding box shifted farther than `tolerance` meters, which also catches reshaped
+    /// parcels.
+    pub fn parcels(old: &Parcels, new: &Parcels, tolerance: f64) -> Self {
+        let mut changes = Vec::new();
+        let pairs = pair(
+            old.records.iter().map(|v| v.owner.id.as_str()),
+            new.records.iter().map(|v| v.owner.id.as_str()),
+        );
+        let owner = |parcel: &Parcel| parcel.owner.name.clone().unwrap_or_default();
+        for (key, before, after) in pairs {
+            let change = |kind, before: String, after: String, distance, bounds: &Rect| {
+                let center = center(bounds);
+                Change {
+                    key: key.clone(),
+                    kind,
+                    before,
+                    after,
+                    distance,
+                    x: center.x(),
+                    y: center.y(),
+                }
+            };
+            match (before.map(|i| &old.records[i]), after.map(|i| &new.records[i])) {
+                (Some(a), Some(b)) => {
+                    if a.owner.name != b.owner.name {
+                        changes.push(change(ChangeKind::Owner, owner(a), owner(b), 0.0, &b.bounds));
+                    }
+                    let distance = shift(&a.bounds, &b.bounds);
+                    if distance > tolerance {
+                        changes.push(change(
+                            ChangeKind::Moved,
+                            String::new(),
+                            String::new(),
+                            distance,
+                            &b.bounds,
+                        ));
+                    }
+                }
+                (None, Some(b)) => changes.push(change(
+                    ChangeKind::Added,
+                    String::new(),
+                    owner(b),
+                    0.0,
+                    &b.bounds,
+                )),
+                (Some(a), None) => changes.push(change(
+                    ChangeKind::Removed,
+                    owner(a),
+                    String::new(),
+                    0.0,
+                    &a.bounds,
+                )),
+                (None, None) => {}
+            }
+        }
+        info!("{} parcel changes.", changes.len());
+        Self { changes }
+    }
+
+    /// Read two snapshots from bincode caches or source files and compare them.
+    pub fn from_paths(source: DiffSource, old: &str, new: &str, tolerance: f64) -> Whimsy<Self> {
+        match source {
+            DiffSource::Addresses => Ok(Self::addresses(
+                &Addresses::read(old)?.0,
+                &Addresses::read(new)?.0,
+                tolerance,
+            )),
+            DiffSource::Parcels => Ok(Self::parcels(
+                &Parcels::read(old)?.0,
+                &Parcels::read(new)?.0,
+                tolerance,
+            )),
+        }
+    }
+
+    /// Number of changes of each kind.
+    pub fn counts(&self) -> BTreeMap<ChangeKind, usize> {
+        let mut counts = BTreeMap::new();
+        for change in &self.changes {
+            *counts.entry(change.kind).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_csv<P: AsRef<std::path::Path>>(&self, path: P) -> Polite<()> {
+        to_csv(&self.changes, path)?;
+        Ok(())
+    }
+}
+
+/// Pair records from two snapshots by key, in key order.  Records sharing a key are paired in the
+/// order they appear, and any left over are unmatched.
+fn pair<'a>(
+    old: impl Iterator<Item = &'a str>,
+    new: impl Iterator<Item = &'a str>,
+) -> Vec<(String, Option<usize>, Option<usize>)> {
+    let mut keys: BTreeMap<&str, (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+    for (i, key) in old.enumerate() {
+        keys.entry(key).or_default().0.push(i);
+    }
+    for (i, key) in new.enumerate() {
+        keys.entry(key).or_default().1.push(i);
+    }
+    let mut pairs = Vec::new();
+    for (key, (before, after)) in keys {
+        for i in 0..before.len().max(after.len()) {
+            pairs.push((key.to_string(), before.get(i).copied(), after.get(i).copied()));
+        }
+    }
+    pairs
+}
+
+fn center(bounds: &Rect) -> Point2d {
+    Point2d::new(
+        (bounds.x_min() + bounds.x_max()) / 2.0,
+        (bounds.y_min() + bounds.y_max()) / 2.0,
+    )
+}
+
+/// Largest displacement of a corner between two bounding boxes.
+fn shift(a: &Rect, b: &Rect) -> f64 {
+    let dx = (a.x_min() - b.x_min()).abs().max((a.x_max() - b.x_max()).abs());
+    let dy = (a.y_min() - b.y_min()).abs().max((a.y_max() - b.y_max()).abs());
+    (dx.powi(2) + dy.powi(2)).sqrt()
+}
+
+/// Snapshot comparison in the GUI, with a filter by kind of change.
+#[derive(Debug, Clone)]
+pub struct DiffPanel {
+    pub diff: Option<Diff>,
+    pub source: DiffSource,
+    pub old_path: String,
+    pub new_path: String,
+    pub tolerance: f64,
+    pub visible: HashSet<ChangeKind>,
+    pub report_path: String,
+    pub status: String,
+}
+
+impl Default for DiffPanel {
+    fn default() -> Self {
+        Self {
+            diff: None,
+            source: Default::default(),
+            old_path: Default::default(),
+            new_path: Default::default(),
+            tolerance: 1.0,
+            visible: ChangeKind::ALL.into_iter().collect(),
+            report_path: "changes.csv".to_string(),
+            status: Default::default(),
+        }
+    }
+}
+
+impl DiffPanel {
+    /// Draw the comparison controls and change log.  Returns the position of a change when the
+    /// user clicks it.
+    pub fn show(&mut self, ui: &mut Ui) -> Option<Point2d> {
+        let mut picked = None;
+        ui.horizontal(|ui| {
+            for source in [DiffSource::Addresses, DiffSource::Parcels] {
+                ui.radio_value(&mut self.source, source, source.to_string());
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut self.old_path).hint_text("Older snapshot"));
+        ui.add(egui::TextEdit::singleline(&mut self.new_path).hint_text("Newer snapshot"));
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.tolerance).prefix("Tolerance (m): "));
+            if ui.button("Compare").clicked() {
+                match Diff::from_paths(self.source, &self.old_path, &self.new_path, self.tolerance) {
+                    Ok(diff) => {
+                        self.status = format!("{} changes.", diff.changes.len());
+                        self.diff = Some(diff);
+                    }
+                    Err(e) => self.status = format!("Could not compare: {}", e.to_string()),
+                }
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+
+        let Some(diff) = &self.diff else {
+            return None;
+        };
+
+        let counts = diff.counts();
+        for kind in ChangeKind::ALL {
+            let mut visible = self.visible.contains(&kind);
+            let text = format!("{} ({})", kind, counts.get(&kind).unwrap_or(&0));
+            if ui
+                .checkbox(&mut visible, egui::RichText::new(text).color(kind.color()))
+                .changed()
+            {
+                if visible {
+                    self.visible.insert(kind);
+                } else {
+                    self.visible.remove(&kind);
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.report_path).hint_text("Change log path"));
+            if ui.button("CSV").clicked() {
+                self.status = match diff.to_csv(&self.report_path) {
+                    Ok(_) => format!("Wrote {}.", self.report_path),
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+
+        let changes = diff
+            .changes
+            .iter()
+            .filter(|v| self.visible.contains(&v.kind))
+            .collect::<Vec<&Change>>();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical().max_height(300.).show_rows(
+            ui,
+            row_height,
+            changes.len(),
+            |ui, range| {
+                for change in &changes[range] {
+                    let text = match change.kind {
+                        ChangeKind::Moved => format!("{}: moved {:.1} m", change.key, change.distance),
+                        ChangeKind::Added | ChangeKind::Removed => {
+                            format!("{}: {}", change.key, change.kind)
+                        }
+                        _ => format!("{}: {} -> {}", change.key, change.before, change.after),
+                    };
+                    let text = egui::RichText::new(text).color(change.kind.color());
+                    if ui.selectable_label(false, text).clicked() {
+                        picked = Some(Point2d::new(change.x, change.y));
+                    }
+                }
+            },
+        );
+        picked
+    }
+
+    /// Mark the visible changes on the map.
+    pub fn overlay(&self, painter: &Painter, map: &MapView) {
+        let Some(diff) = &self.diff else {
+            return;
+        };
+        let extent = map.extent();
+        for change in diff.changes.iter().filter(|v| self.visible.contains(&v.kind)) {
+            let point = Point2d::new(change.x, change.y);
+            if extent.contains(&point) {
+                let center = map.point_to_screen(&point);
+                let stroke = egui::Stroke::new(2.0, change.kind.color());
+                painter.rect_stroke(
+                    egui::Rect::from_center_size(center, egui::vec2(12.0, 12.0)),
+                    0.0,
+                    stroke,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+/// One attempt to find a graphics adapter, recorded so that startup failures can report
+/// everything that was tried.
+#[derive(Debug, Clone)]
+pub struct AdapterAttempt {
+    pub backends: wgpu::Backends,
+    pub force_fallback_adapter: bool,
+    /// Adapter found by the attempt, if any.
+    pub adapter: Option<wgpu::AdapterInfo>,
+    /// Why the attempt did not produce a usable device.
+    pub failure: Option<String>,
+}
+
+impl std::fmt::Display for AdapterAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "backends {:?}", self.backends)?;
+        if self.force_fallback_adapter {
+            write!(f, " (fallback adapter)")?;
+        }
+        if let Some(info) = &self.adapter {
+            write!(
+                f,
+                ": {} on {:?} ({:?}, driver {} {})",
+                info.name, info.backend, info.device_type, info.driver, info.driver_info
+            )?;
+        }
+        if let Some(failure) = &self.failure {
+            write!(f, ": {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Failure to set up rendering for a window or offscreen target.
+#[derive(Debug)]
+pub enum GpuError {
+    /// The event loop could not start, as on a machine without a display server.
+    EventLoop(winit::error::EventLoopError),
+    /// The operating system could not create a window.
+    Window(winit::error::OsError),
+    /// No backend produced an adapter and device able to render.
+    NoAdapter(Vec<AdapterAttempt>),
+    /// The adapter cannot present to the window surface.
+    Surface(wgpu::AdapterInfo),
+}
+
+impl std::fmt::Display for GpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EventLoop(e) => write!(
+                f,
+                "Could not connect to a display: {}.  Use --snapshot or the other command line \
+                 options to run without a window.",
+                e
+            ),
+            Self::Window(e) => write!(f, "Could not create a window: {}", e),
+            Self::NoAdapter(attempts) => {
+                writeln!(f, "No usable graphics adapter was found.  Tried:")?;
+                for attempt in attempts {
+                    writeln!(f, "  - {}", attempt)?;
+                }
+                write!(
+                    f,
+                    "Check that graphics drivers are installed, or select a backend with the \
+                     WGPU_BACKEND environment variable (vulkan, metal, dx12, gl)."
+                )
+            }
+            Self::Surface(info) => write!(
+                f,
+                "The graphics adapter {} ({:?}) cannot draw to this window.",
+                info.name, info.backend
+            ),
+        }
+    }
+}
+
+impl GpuError {
+    /// Log the failure and describe it on standard error, where a user starting whimsy from a
+    /// terminal will see it.
+    pub fn report(&self) {
+        tracing::error!("Rendering setup failed: {:?}", self);
+        eprintln!("{}", self);
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+impl From<winit::error::EventLoopError> for GpuError {
+    fn from(e: winit::error::EventLoopError) -> Self {
+        Self::EventLoop(e)
+    }
+}
+
+impl From<winit::error::OsError> for GpuError {
+    fn from(e: winit::error::OsError) -> Self {
+        Self::Window(e)
+    }
+}
+
+/// Errors from reading and converting whimsy datasets.
+#[derive(Debug, Clone)]
+pub enum WhimsyError {
+    /// A file could not be opened, read or written.
+    Io {
+        path: String,
+        source: std::sync::Arc<std::io::Error>,
+    },
+    /// A file or record could not be parsed.  `row` counts data records from 1, and `field`
+    /// names the offending column where known.
+    Parse {
+        file: String,
+        row: Option<usize>,
+        field: Option<String>,
+        message: String,
+    },
+    /// A geometry is missing, empty or of an unsupported type.
+    Geometry { id: Option<String>, message: String },
+    /// A coordinate reference system is unknown or cannot be applied.
+    Crs(String),
+    /// A field required by the schema is missing or empty.
+    Schema { field: String, message: String },
+    /// Offscreen rendering could not start.
+    Gpu(std::sync::Arc<GpuError>),
+}
+
+impl WhimsyError {
+    pub fn io<P: AsRef<std::path::Path>>(path: P, source: std::io::Error) -> Self {
+        Self::Io {
+            path: path.as_ref().display().to_string(),
+            source: std::sync::Arc::new(source),
+        }
+    }
+
+    pub fn parse<P: AsRef<std::path::Path>>(path: P, message: impl std::fmt::Display) -> Self {
+        Self::Parse {
+            file: path.as_ref().display().to_string(),
+            row: None,
+            field: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Short name of the variant, for reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Io { .. } => "io",
+            Self::Parse { .. } => "parse",
+            Self::Geometry { .. } => "geometry",
+            Self::Crs(_) => "crs",
+            Self::Schema { .. } => "schema",
+            Self::Gpu(_) => "gpu",
+        }
+    }
+}
+
+impl std::fmt::Display for WhimsyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "Could not access {}: {}", path, source),
+            Self::Parse {
+                file,
+                row,
+                field,
+                message,
+            } => {
+                write!(f, "Could not parse {}", file)?;
+                if let Some(row) = row {
+                    write!(f, " at row {}", row)?;
+                }
+                if let Some(field) = field {
+                    write!(f, " in field {}", field)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Self::Geometry { id: Some(id), message } => write!(f, "Geometry of {}: {}", id, message),
+            Self::Geometry { id: None, message } => write!(f, "Geometry: {}", message),
+            Self::Crs(message) => write!(f, "Coordinate system: {}", message),
+            Self::Schema { field, message } => write!(f, "Field {}: {}", field, message),
+            Self::Gpu(e) => write!(f, "Graphics: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WhimsyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source.as_ref()),
+            Self::Gpu(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Lets loaders that return [`WhimsyError`] be called from functions returning `Polite`.
+impl From<WhimsyError> for polite::FauxPas {
+    fn from(e: WhimsyError) -> Self {
+        std::io::Error::other(e).into()
+    }
+}
+
+impl From<GpuError> for WhimsyError {
+    fn from(e: GpuError) -> Self {
+        Self::Gpu(std::sync::Arc::new(e))
+    }
+}
+
+pub type Whimsy<T> = Result<T, WhimsyError>;
+
+/// A record left out of a loaded dataset.
+#[derive(Debug, Clone)]
+pub struct Dropped {
+    /// Position of the record in the source, counting from 1.
+    pub row: usize,
+    pub reason: WhimsyError,
+}
+
+/// What a loader read from a source and which records it skipped, and why.
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub source: String,
+    pub loaded: usize,
+    pub dropped: Vec<Dropped>,
+}
+
+impl LoadReport {
+    pub fn new<P: AsRef<std::path::Path>>(source: P) -> Self {
+        Self {
+            source: source.as_ref().display().to_string(),
+            loaded: 0,
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Record that the record at `row` was skipped.
+    pub fn skip(&mut self, row: usize, reason: WhimsyError) {
+        tracing::debug!("Dropping row {}: {}", row, reason);
+        self.dropped.push(Dropped { row, reason });
+    }
+
+    /// Number of records skipped for each kind of error.
+    pub fn counts(&self) -> std::collections::BTreeMap<&'static str, usize> {
+        let mut counts = std::collections::BTreeMap::new();
+        for dropped in &self.dropped {
+            *counts.entry(dropped.reason.kind()).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{}: {} loaded, {} dropped",
+            self.source,
+            self.loaded,
+            self.dropped.len()
+        );
+        let counts = self.counts();
+        if !counts.is_empty() {
+            let kinds = counts
+                .iter()
+                .map(|(k, v)| format!("{} {}", v, k))
+                .collect::<Vec<String>>()
+                .join(", ");
+            summary.push_str(&format!(" ({})", kinds));
+        }
+        summary.push('.');
+        summary
+    }
+
+    /// Write the dropped records, one row each, to a CSV file.
+    pub fn to_csv<P: AsRef<std::path::Path>>(&self, path: P) -> Whimsy<()> {
+        let rows = self
+            .dropped
+            .iter()
+            .map(|v| (v.row, v.reason.kind(), v.reason.to_string()))
+            .collect::<Vec<(usize, &str, String)>>();
+        let mut wtr = csv::Writer::from_path(&path).map_err(|e| WhimsyError::io(&path, e.into()))?;
+        let header = ["row", "kind", "reason"];
+        wtr.write_record(header).map_err(|e| WhimsyError::io(&path, e.into()))?;
+        for row in rows {
+            wtr.serialize(row).map_err(|e| WhimsyError::io(&path, e.into()))?;
+        }
+        wtr.flush().map_err(|e| WhimsyError::io(&path, e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use crate::prelude::*;
+use crate::select::PICK_RADIUS;
+use egui::{Context, Pos2, Ui};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+
+/// A feature found under the cursor, by position in its dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Identified {
+    Parcel(usize),
+    Address(usize),
+}
+
+/// Popup describing the topmost feature clicked on the map.
+#[derive(Debug, Clone, Default)]
+pub struct IdentifyPopup {
+    pub feature: Option<Identified>,
+    /// Screen position of the click, where the popup opens.
+    pub anchor: Pos2,
+    /// Whether to list the addresses on a parcel, or the parcels under an address.
+    pub show_related: bool,
+    pub status: String,
+    /// Bounds and fields of the feature, found when it is picked.
+    pub fields: Option<(Rect, Vec<(String, String)>)>,
+    /// Addresses on the parcel or parcels under the address, found when the feature is picked.
+    /// `None` unless both parcels and addresses are loaded.
+    pub related: Option<Vec<(Identified, String)>>,
+    /// Revisions of the parcels and address points the feature was found in.
+    pub revisions: (Option<Revision>, Option<Revision>),
+}
+
+impl IdentifyPopup {
+    /// Find the topmost feature at the screen position `pos`.  Address points are drawn over
+    /// parcels, so they are checked first.
+    pub fn pick(
+        &mut self,
+        pos: Pos2,
+        map: &MapView,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+    ) {
+        let point = map.to_map(pos);
+        let tolerance = PICK_RADIUS * map.resolution;
+        let address = addresses.and_then(|v| {
+            v.records
+                .iter()
+                .rposition(|r| r.hit(&point, tolerance))
+                .map(Identified::Address)
+        });
+        let parcel = || {
+            parcels.and_then(|v| {
+                v.records
+                    .iter()
+                    .rposition(|r| r.hit(&point, 0.0))
+                    .map(Identified::Parcel)
+            })
+        };
+        self.feature = address.or_else(parcel);
+        self.anchor = pos;
+        self.show_related = false;
+        self.status = Default::default();
+        self.describe(parcels, addresses);
+    }
+
+    /// Look up the fields and related records of the feature, so the popup does not search the
+    /// datasets each frame.
+    fn describe(&mut self, parcels: Option<&Parcels>, addresses: Option<&AddressPoints>) {
+        self.revisions = revisions(parcels, addresses);
+        self.fields = match self.feature {
+            Some(Identified::Parcel(i)) => parcels.and_then(|v| parcel_fields(v, i)),
+            Some(Identified::Address(i)) => addresses.and_then(|v| address_fields(v, i)),
+            None => None,
+        };
+        self.related = match (self.feature, parcels, addresses) {
+            (Some(feature), Some(parcels), Some(addresses)) => {
+                Some(related(feature, parcels, addresses))
+            }
+            _ => None,
+        };
+    }
+
+    /// Draw the popup, returning the map area to zoom to if asked.  The datasets are searched
+    /// only when a related record is picked.  Closes when either dataset changes, since the
+    /// feature may no longer be at the same position.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+    ) -> Option<Rect> {
+        self.feature?;
+        if self.revisions != revisions(parcels, addresses) {
+            self.feature = None;
+            self.fields = None;
+            self.related = None;
+            return None;
+        }
+        let mut zoom = None;
+        let mut open = true;
+        let mut picked = None;
+        egui::Area::new(egui::Id::new("identify_popup"))
+            .fixed_pos(self.anchor + egui::vec2(12., 12.))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(320.);
+                    let Some((bounds, fields)) = &self.fields else {
+                        open = false;
+                        return;
+                    };
+                    egui::Grid::new("identify_fields").num_columns(2).show(ui, |ui| {
+                        for (name, value) in fields {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Zoom to").clicked() {
+                            zoom = Some(*bounds);
+                        }
+                        if ui.button("Copy").clicked() {
+                            let text = fields
+                                .iter()
+                                .map(|(k, v)| format!("{}: {}", k, v))
+                                .collect::<Vec<String>>()
+                                .join("\n");
+                            ui.output_mut(|o| o.copied_text = text);
+                            self.status = "Copied.".to_string();
+                        }
+                        ui.toggle_value(&mut self.show_related, "Related");
+                        if ui.button("Close").clicked() {
+                            open = false;
+                        }
+                    });
+                    if self.show_related {
+                        picked = show_related(ui, self.related.as_deref());
+                    }
+                    if !self.status.is_empty() {
+                        ui.label(&self.status);
+                    }
+                });
+            });
+        if let Some(next) = picked {
+            self.feature = Some(next);
+            self.describe(parcels, addresses);
+        }
+        if !open {
+            self.feature = None;
+        }
+        zoom
+    }
+}
+
+fn revisions(
+    parcels: Option<&Parcels>,
+    addresses: Option<&AddressPoints>,
+) -> (Option<Revision>, Option<Revision>) {
+    (parcels.map(|v| v.revision), addresses.map(|v| v.revision))
+}
+
+/// The addresses on a parcel, or the parcels under an address point, with their labels.
+fn related(
+    feature: Identified,
+    parcels: &Parcels,
+    addresses: &AddressPoints,
+) -> Vec<(Identified, String)> {
+    match feature {
+        Identified::Parcel(i) => {
+            let Some(parcel) = parcels.records.get(i) else {
+                return Vec::new();
+            };
+            addresses
+                .records
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| parcel.hit(&addresses.crs.point(&parcels.crs, &v.geometry), 0.0))
+                .map(|(j, v)| (Identified::Address(j), v.address.label.clone()))
+                .collect()
+        }
+        Identified::Address(i) => {
+            let Some(address) = addresses.records.get(i) else {
+                return Vec::new();
+            };
+            let point = addresses.crs.point(&parcels.crs, &address.geometry);
+            parcels
+                .records
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.hit(&point, 0.0))
+                .map(|(j, v)| (Identified::Parcel(j), v.owner.id.clone()))
+                .collect()
+        }
+    }
+}
+
+/// List the `found` related records, returning the one clicked.
+fn show_related(ui: &mut Ui, found: Option<&[(Identified, String)]>) -> Option<Identified> {
+    let Some(found) = found else {
+        ui.label("Load parcels and addresses to find related records.");
+        return None;
+    };
+    if found.is_empty() {
+        ui.label("No related records.");
+    }
+    let mut picked = None;
+    egui::ScrollArea::vertical().max_height(150.).show(ui, |ui| {
+        for (target, label) in found {
+            if ui.link(label).clicked() {
+                picked = Some(*target);
+            }
+        }
+    });
+    picked
+}
+
+/// Coordinates of `point` in the systems used by the county, labelled by EPSG code.
+fn coordinates(point: &Point2d, crs: &Crs) -> Vec<(String, String)> {
+    [Crs::Wgs84, Crs::WebMercator, Crs::OregonSouth]
+        .iter()
+        .map(|to| {
+            let p = crs.point(to, point);
+            let value = match to {
+                Crs::Wgs84 => format!("{:.6}, {:.6}", p.y(), p.x()),
+                _ => format!("{:.1}, {:.1}", p.x(), p.y()),
+            };
+            (to.to_string(), value)
+        })
+        .collect()
+}
+
+fn parcel_fields(parcels: &Parcels, index: usize) -> Option<(Rect, Vec<(String, String)>)> {
+    let parcel = parcels.records.get(index)?;
+    let mut fields = vec![
+        ("MapNum".to_string(), parcel.owner.id.clone()),
+        ("Owner".to_string(), parcel.owner.name.clone().unwrap_or_default()),
+    ];
+    // The first two attributes are the MapNum and owner, listed above.
+    let headers = parcels.attribute_headers();
+    fields.extend(
+        headers
+            .iter()
+            .cloned()
+            .zip(parcels.attribute_values(index, headers))
+            .skip(2)
+            .filter(|(_, v)| !v.is_empty()),
+    );
+    let bounds = parcel.bounds;
+    let center = Point2d::new(
+        (bounds.x_min() + bounds.x_max()) / 2.0,
+        (bounds.y_min() + bounds.y_max()) / 2.0,
+    );
+    fields.extend(coordinates(&center, &parcels.crs));
+    Some((bounds, fields))
+}
+
+fn address_fields(
+    addresses: &AddressPoints,
+    index: usize,
+) -> Option<(Rect, Vec<(String, String)>)> {
+    let point = addresses.records.get(index)?;
+    let mut fields = vec![
+        ("Address".to_string(), point.address.label.clone()),
+        ("Status".to_string(), point.address.status.clone()),
+    ];
+    fields.extend(coordinates(&point.geometry, &addresses.crs));
+    Some((point.bounds, fields))
+}
@@ -0,0 +1,409 @@
+use crate::prelude::*;
+use std::path::Path;
+use tracing::info;
+
+/// Names of the source fields holding each part of an [`Address`].  Coordinate fields are
+/// optional: missing WGS84 or Web Mercator coordinates are taken from the point geometry, or
+/// computed from the other coordinate pair.
+#[derive(Debug, Clone)]
+pub struct FieldMap {
+    pub label: String,
+    pub status: String,
+    pub lat: Option<String>,
+    pub lon: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    /// Coordinate system of shapefile point geometries.  GeoJSON geometries are always WGS84.
+    pub crs: Crs,
+    /// Delimiter, encoding and compression of CSV sources.
+    pub csv: CsvOptions,
+}
+
+impl Default for FieldMap {
+    /// Field names of the Grants Pass address layer.
+    fn default() -> Self {
+        Self {
+            label: "FULLADDRES".to_string(),
+            status: "STATUS".to_string(),
+            lat: Some("wgs84_y".to_string()),
+            lon: Some("wgs84_x".to_string()),
+            x: Some("espg3857_x".to_string()),
+            y: Some("espg3857_y".to_string()),
+            crs: Crs::WebMercator,
+            csv: Default::default(),
+        }
+    }
+}
+
+impl FieldMap {
+    /// Map with only the label and status fields, taking coordinates from the geometry.
+    pub fn new(label: &str, status: &str, crs: Crs) -> Self {
+        Self {
+            label: label.to_string(),
+            status: status.to_string(),
+            lat: None,
+            lon: None,
+            x: None,
+            y: None,
+            crs,
+            csv: Default::default(),
+        }
+    }
+
+    /// Build an address from a source record.  `text` and `number` look up a field by name, and
+    /// `point` is the record geometry in the coordinates given by `crs`.
+    pub fn address(
+        &self,
+        text: impl Fn(&str) -> Option<String>,
+        number: impl Fn(&str) -> Option<f64>,
+        point: Option<(f64, f64)>,
+        crs: Crs,
+    ) -> Whimsy<Address> {
+        let label = text(&self.label).ok_or(WhimsyError::Schema {
+            field: self.label.clone(),
+            message: "missing or empty label".to_string(),
+        })?;
+        let status = text(&self.status).unwrap_or_default();
+        let field = |name: &Option<String>| name.as_deref().and_then(&number);
+
+        let mut wgs84 = field(&self.lon).zip(field(&self.lat));
+        let mut mercator = field(&self.x).zip(field(&self.y));
+        if let Some((x, y)) = point {
+            match crs {
+                Crs::WebMercator => mercator = mercator.or(Some((x, y))),
+                _ => wgs84 = wgs84.or(Some(crs.to_wgs84(x, y))),
+            }
+        }
+        let ((lon, lat), (x, y)) = match (wgs84, mercator) {
+            (Some(a), Some(b)) => (a, b),
+            (Some((lon, lat)), None) => ((lon, lat), wgs84_to_web_mercator(lon, lat)),
+            (None, Some((x, y))) => (web_mercator_to_wgs84(x, y), (x, y)),
+            (None, None) => {
+                return Err(WhimsyError::Geometry {
+                    id: Some(label),
+                    message: "no coordinates in the fields or geometry".to_string(),
+                })
+            }
+        };
+        Ok(Address {
+            label,
+            status,
+            lat,
+            lon,
+            x,
+            y,
+            parsed: Default::default(),
+        })
+    }
+}
+
+impl Addresses {
+    /// Read addresses from a CSV file with the field names in `fields`, parsing records in
+    /// parallel.
+    pub fn from_csv_with<P: AsRef<Path>>(
+        path: P,
+        fields: &FieldMap,
+    ) -> Whimsy<(Self, LoadReport)> {
+        let mut records = Vec::new();
+        let report = stream_csv(
+            &path,
+            &fields.csv,
+            |record, headers| {
+                let get = |name: &str| {
+                    headers
+                        .iter()
+                        .position(|v| v == name)
+                        .and_then(|i| record.get(i))
+                        .map(|v| v.trim())
+                        .filter(|v| !v.is_empty())
+                };
+                fields.address(
+                    |name| get(name).map(|v| v.to_string()),
+                    |name| get(name).and_then(|v| v.parse().ok()),
+                    None,
+                    fields.crs,
+                )
+            },
+            |chunk| records.extend(chunk),
+        )?;
+        Ok((Addresses { records }, report))
+    }
+
+    /// Read addresses from a point shapefile with the field names in `fields`.
+    pub fn from_shp<P: AsRef<Path>>(path: P, fields: &FieldMap) -> Whimsy<(Self, LoadReport)> {
+        let mut report = LoadReport::new(&path);
+        let shapes = shapefile::read_as::<_, shapefile::Point, shapefile::dbase::Record>(&path)
+            .map_err(|e| WhimsyError::parse(&path, e))?;
+        let mut records = Vec::new();
+        for (i, (point, record)) in shapes.into_iter().enumerate() {
+            let address = fields.address(
+                |name| match record.get(name) {
+                    Some(shapefile::dbase::FieldValue::Character(Some(value))) => {
+                        Some(value.trim().to_string())
+                    }
+                    Some(shapefile::dbase::FieldValue::Memo(value)) => Some(value.trim().to_string()),
+                    _ => None,
+                },
+                |name| match record.get(name) {
+                    Some(shapefile::dbase::FieldValue::Numeric(value)) => *value,
+                    Some(shapefile::dbase::FieldValue::Float(value)) => value.map(f64::from),
+                    Some(shapefile::dbase::FieldValue::Double(value)) => Some(*value),
+                    Some(shapefile::dbase::FieldValue::Integer(value)) => Some(f64::from(*value)),
+                    Some(shapefile::dbase::FieldValue::Character(Some(value))) => {
+                        value.trim().parse().ok()
+                    }
+                    _ => None,
+                },
+                Some((point.x, point.y)),
+                fields.crs,
+            );
+            match address {
+                Ok(value) => records.push(value),
+                Err(e) => report.skip(i + 1, e),
+            }
+        }
+        report.loaded = records.len();
+        info!("{}", report.summary());
+        Ok((Addresses { records }, report))
+    }
+
+    /// Read addresses from GeoJSON point features with the property names in `fields`.
+    pub fn from_geojson<P: AsRef<Path>>(
+        path: P,
+        fields: &FieldMap,
+    ) -> Whimsy<(Self, LoadReport)> {
+        let mut report = LoadReport::new(&path);
+        let file = std::fs::File::open(&path).map_err(|e| WhimsyError::io(&path, e))?;
+        let reader = geojson::FeatureReader::from_reader(std::io::BufReader::new(file));
+        let mut records = Vec::new();
+        for (i, feature) in reader.features().enumerate() {
+            let feature = match feature {
+                Ok(value) => value,
+                Err(e) => {
+                    let mut reason = WhimsyError::parse(&path, e);
+                    if let WhimsyError::Parse { row, .. } = &mut reason {
+                        *row = Some(i + 1);
+                    }
+                    report.skip(i + 1, reason);
+                    continue;
+                }
+            };
+            let point = match feature.geometry.as_ref().map(|v| &v.value) {
+                Some(geojson::Value::Point(position)) if position.len() >= 2 => {
+                    Some((position[0], position[1]))
+                }
+                _ => None,
+            };
+            let property = |name: &str| feature.property(name).filter(|v| !v.is_null());
+            let address = fields.address(
+                |name| {
+                    property(name).map(|v| match v.as_str() {
+                        Some(text) => text.trim().to_string(),
+                        None => v.to_string(),
+                    })
+                },
+                |name| {
+                    property(name).and_then(|v| v.as_f64().or(v.as_str().and_then(|s| s.trim().parse().ok())))
+                },
+                point,
+                Crs::Wgs84,
+            );
+            match address {
+                Ok(value) => records.push(value),
+                Err(e) => report.skip(i + 1, e),
+            }
+        }
+        report.loaded = records.len();
+        info!("{}", report.summary());
+        Ok((Addresses { records }, report))
+    }
+}
+
+/// A dataset read by the [`ImportPanel`].
+#[derive(Debug, Clone)]
+pub enum Imported {
+    Addresses(AddressPoints),
+    Parcels(Parcels),
+}
+
+/// Which kind of dataset the [`ImportPanel`] loads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportSource {
+    #[default]
+    Addresses,
+    Parcels,
+}
+
+/// Load a source file in the GUI and list the records that were skipped.
+#[derive(Debug, Clone)]
+pub struct ImportPanel {
+    pub source: ImportSource,
+    pub path: String,
+    /// EPSG code of parcel shapefiles.
+    pub crs: String,
+    /// Text encoding of CSV sources, such as "windows-1252", or blank for UTF-8.
+    pub encoding: String,
+    pub delimiter: char,
+    pub report: Option<LoadReport>,
+    pub report_path: String,
+    pub status: String,
+    /// Load running in the background.
+    pub loading: Option<Task<Whimsy<(Imported, LoadReport)>>>,
+}
+
+impl Default for ImportPanel {
+    fn default() -> Self {
+        Self {
+            source: Default::default(),
+            path: Default::default(),
+            crs: Crs::OregonSouth.to_string(),
+            encoding: Default::default(),
+            delimiter: ',',
+            report: None,
+            report_path: "dropped.csv".to_string(),
+            status: Default::default(),
+            loading: None,
+        }
+    }
+}
+
+impl ImportPanel {
+    /// Default address fields, with the delimiter and encoding chosen in the panel.
+    fn fields(&self) -> Whimsy<FieldMap> {
+        let mut csv = match self.encoding.trim() {
+            "" => CsvOptions::default(),
+            label => CsvOptions::with_encoding(label)?,
+        };
+        csv.delimiter = u8::try_from(self.delimiter).map_err(|_| WhimsyError::Schema {
+            field: "delimiter".to_string(),
+            message: format!("delimiter {} is not a single byte", self.delimiter),
+        })?;
+        Ok(FieldMap {
+            csv,
+            ..Default::default()
+        })
+    }
+
+    /// Copy of the load settings without the report of the last load, to move to the thread
+    /// loading the source.
+    fn settings(&self) -> Self {
+        Self {
+            source: self.source,
+            path: self.path.clone(),
+            crs: self.crs.clone(),
+            encoding: self.encoding.clone(),
+            delimiter: self.delimiter,
+            report: None,
+            report_path: Default::default(),
+            status: Default::default(),
+            loading: None,
+        }
+    }
+
+    fn load(&self) -> Whimsy<(Imported, LoadReport)> {
+        match self.source {
+            ImportSource::Addresses => {
+                let (addresses, report) = if is_csv(&self.path) {
+                    Addresses::from_csv_with(&self.path, &self.fields()?)?
+                } else {
+                    Addresses::read(&self.path)?
+                };
+                Ok((Imported::Addresses(AddressPoints::from(addresses)), report))
+            }
+            ImportSource::Parcels => {
+                let shp = Path::new(&self.path)
+                    .extension()
+                    .and_then(|v| v.to_str())
+                    .is_some_and(|v| v.eq_ignore_ascii_case("shp"));
+                let (parcels, report) = if shp {
+                    Parcels::from_shp(&self.path, &self.crs.parse::<Crs>()?)?
+                } else {
+                    Parcels::read(&self.path)?
+                };
+                Ok((Imported::Parcels(parcels), report))
+            }
+        }
+    }
+
+    /// Draw the load controls and the report of the last load, returning the dataset read.  The
+    /// source is read on a background thread, and the dataset returned on the frame it finishes.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<Imported> {
+        let mut imported = None;
+        if let Some(loaded) = self.loading.as_ref().and_then(|v| v.poll()) {
+            self.loading = None;
+            match loaded {
+                Ok((data, report)) => {
+                    self.status = report.summary();
+                    self.report = Some(report);
+                    imported = Some(data);
+                }
+                Err(e) => {
+                    self.status = format!("Could not load: {}", e);
+                    self.report = None;
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.source, ImportSource::Addresses, "Addresses");
+            ui.selectable_value(&mut self.source, ImportSource::Parcels, "Parcels");
+            if self.source == ImportSource::Parcels {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.crs)
+                        .hint_text("Shapefile CRS")
+                        .desired_width(80.),
+                );
+            } else {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.encoding)
+                        .hint_text("Encoding")
+                        .desired_width(100.),
+                );
+                egui::ComboBox::from_id_source("import_delimiter")
+                    .selected_text(match self.delimiter {
+                        '\t' => "Tab".to_string(),
+                        c => c.to_string(),
+                    })
+                    .show_ui(ui, |ui| {
+                        for (c, name) in [(',', ","), (';', ";"), ('|', "|"), ('\t', "Tab")] {
+                            ui.selectable_value(&mut self.delimiter, c, name);
+                        }
+                    });
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.path).hint_text("Source path"));
+            let idle = self.loading.is_none();
+            if ui.add_enabled(idle, egui::Button::new("Load")).clicked() {
+                let settings = self.settings();
+                self.status = format!("Loading {}...", self.path);
+                self.report = None;
+                self.loading = Some(Task::spawn(ui.ctx(), move || settings.load()));
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        if let Some(report) = &self.report {
+            if !report.dropped.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.report_path)
+                            .hint_text("Dropped records path"),
+                    );
+                    if ui.button("CSV").clicked() {
+                        self.status = match report.to_csv(&self.report_path) {
+                            Ok(_) => format!("Wrote {}.", self.report_path),
+                            Err(e) => e.to_string(),
+                        };
+                    }
+                });
+                egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                    for dropped in &report.dropped {
+                        ui.label(format!("Row {}: {}", dropped.row, dropped.reason));
+                    }
+                });
+            }
+        }
+        imported
+    }
+}
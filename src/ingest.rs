@@ -0,0 +1,240 @@
+use crate::error::{LoadReport, Whimsy, WhimsyError};
+use csv::StringRecord;
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::de::DeserializeOwned;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Compression of a CSV source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Detect gzip or zstd from the first bytes of the file.
+    #[default]
+    Auto,
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression indicated by the magic number at the start of `bytes`.
+    pub fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// How to read a delimited text file.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Text encoding of the source.  When `None`, the file is read as UTF-8, or as UTF-16 if it
+    /// starts with a byte order mark.
+    pub encoding: Option<&'static Encoding>,
+    pub compression: Compression,
+    /// Records parsed together on the thread pool.
+    pub chunk_size: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            encoding: None,
+            compression: Compression::Auto,
+            chunk_size: 10_000,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Options for a file in the encoding named `label`, such as "windows-1252" or "latin1".
+    pub fn with_encoding(label: &str) -> Whimsy<Self> {
+        let encoding = Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| {
+            WhimsyError::Schema {
+                field: "encoding".to_string(),
+                message: format!("unknown text encoding {}", label),
+            }
+        })?;
+        Ok(Self {
+            encoding: Some(encoding),
+            ..Default::default()
+        })
+    }
+
+    /// Options for a file with fields separated by `delimiter`, such as b'\t' or b'|'.
+    pub fn with_delimiter(delimiter: u8) -> Self {
+        Self {
+            delimiter,
+            ..Default::default()
+        }
+    }
+
+    /// Open `path`, decompressing and decoding it to UTF-8, with a progress bar tracking the
+    /// bytes read from disk.
+    pub fn reader<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Whimsy<(csv::Reader<Box<dyn Read + Send>>, ProgressBar)> {
+        let file = std::fs::File::open(&path).map_err(|e| WhimsyError::io(&path, e))?;
+        let len = file.metadata().map(|v| v.len()).unwrap_or_default();
+        let bar = ProgressBar::new(len);
+        if let Ok(style) = ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {msg}") {
+            bar.set_style(style);
+        }
+        let mut raw = BufReader::new(bar.wrap_read(file));
+        let compression = match self.compression {
+            Compression::Auto => {
+                Compression::sniff(raw.fill_buf().map_err(|e| WhimsyError::io(&path, e))?)
+            }
+            other => other,
+        };
+        let decompressed: Box<dyn Read + Send> = match compression {
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(raw)),
+            Compression::Zstd => Box::new(
+                zstd::stream::read::Decoder::with_buffer(raw)
+                    .map_err(|e| WhimsyError::io(&path, e))?,
+            ),
+            _ => Box::new(raw),
+        };
+        let decoded: Box<dyn Read + Send> = Box::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(self.encoding)
+                .build(decompressed),
+        );
+        let rdr = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .from_reader(decoded);
+        Ok((rdr, bar))
+    }
+}
+
+/// Read the delimited file at `path` in chunks of `options.chunk_size` records, converting each
+/// chunk on the thread pool with `parse` and passing the results to `sink` in file order.
+/// Records that fail to read or convert are listed in the returned report.
+pub fn stream_csv<T, P, F, G>(
+    path: P,
+    options: &CsvOptions,
+    parse: F,
+    mut sink: G,
+) -> Whimsy<LoadReport>
+where
+    T: Send,
+    P: AsRef<Path>,
+    F: Fn(&StringRecord, &StringRecord) -> Whimsy<T> + Sync,
+    G: FnMut(Vec<T>),
+{
+    let mut report = LoadReport::new(&path);
+    let (mut rdr, bar) = options.reader(&path)?;
+    let headers = rdr
+        .headers()
+        .map_err(|e| WhimsyError::parse(&path, e))?
+        .clone();
+    let chunk_size = options.chunk_size.max(1);
+    let mut row = 0;
+    let mut done = false;
+    while !done {
+        let mut chunk = Vec::with_capacity(chunk_size);
+        while chunk.len() < chunk_size {
+            let mut record = StringRecord::new();
+            row += 1;
+            match rdr.read_record(&mut record) {
+                Ok(true) => chunk.push((row, record)),
+                Ok(false) => {
+                    done = true;
+                    break;
+                }
+                Err(e) if e.is_io_error() => {
+                    return Err(WhimsyError::io(&path, std::io::Error::from(e)));
+                }
+                Err(e) => {
+                    let reason = WhimsyError::Parse {
+                        file: report.source.clone(),
+                        row: Some(row),
+                        field: None,
+                        message: e.to_string(),
+                    };
+                    report.skip(row, reason);
+                }
+            }
+        }
+        let results = chunk
+            .into_par_iter()
+            .map(|(row, record)| (row, parse(&record, &headers)))
+            .collect::<Vec<(usize, Whimsy<T>)>>();
+        let mut records = Vec::with_capacity(results.len());
+        for (row, result) in results {
+            match result {
+                Ok(value) => records.push(value),
+                Err(mut reason) => {
+                    if let WhimsyError::Parse { row: at, .. } = &mut reason {
+                        *at = Some(row);
+                    }
+                    report.skip(row, reason);
+                }
+            }
+        }
+        report.loaded += records.len();
+        bar.set_message(format!("{} records", report.loaded));
+        sink(records);
+    }
+    bar.finish_and_clear();
+    tracing::info!("{}", report.summary());
+    Ok(report)
+}
+
+/// Deserialize every record of the delimited file at `path` into `T`, in parallel.
+pub fn from_csv_with<T, P>(path: P, options: &CsvOptions) -> Whimsy<(Vec<T>, LoadReport)>
+where
+    T: DeserializeOwned + Send,
+    P: AsRef<Path>,
+{
+    let file = path.as_ref().display().to_string();
+    let mut records = Vec::new();
+    let report = stream_csv(
+        &path,
+        options,
+        |record, headers| {
+            record.deserialize(Some(headers)).map_err(|e| {
+                let field = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err
+                        .field()
+                        .and_then(|v| headers.get(v as usize))
+                        .map(|v| v.to_string()),
+                    _ => None,
+                };
+                WhimsyError::Parse {
+                    file: file.clone(),
+                    row: None,
+                    field,
+                    message: e.to_string(),
+                }
+            })
+        },
+        |chunk| records.extend(chunk),
+    )?;
+    Ok((records, report))
+}
+
+/// True if `path` names a CSV file, compressed or not.
+pub fn is_csv<P: AsRef<Path>>(path: P) -> bool {
+    let name = path
+        .as_ref()
+        .file_name()
+        .and_then(|v| v.to_str())
+        .map(|v| v.to_lowercase())
+        .unwrap_or_default();
+    let name = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".zst"))
+        .unwrap_or(&name);
+    name.ends_with(".csv")
+}
@@ -0,0 +1,153 @@
+use crate::prelude::*;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Vec2};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use geo::InteriorPoint;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+/// How the features of a layer are labelled.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct LabelStyle {
+    pub visible: bool,
+    /// Labels show only when zoomed in to this many map units per screen point, or closer.
+    pub max_resolution: f64,
+    pub size: f32,
+    /// Text color, following the theme while `None`.
+    pub color: Option<Color32>,
+    /// Outline the text in the background color so it reads over linework.
+    pub halo: bool,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            max_resolution: 0.5,
+            size: 11.0,
+            color: None,
+            halo: true,
+        }
+    }
+}
+
+impl LabelStyle {
+    pub fn show(&mut self, ui: &mut egui::Ui, theme: Color32) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.visible, "Labels");
+            ui.add(
+                egui::DragValue::new(&mut self.max_resolution)
+                    .clamp_range(0.01..=50.0)
+                    .speed(0.05)
+                    .prefix("Below ")
+                    .suffix(" m/pt"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.size)
+                    .clamp_range(6.0..=32.0)
+                    .prefix("Size "),
+            );
+        });
+        ui.horizontal(|ui| {
+            let mut color = self.color.unwrap_or(theme);
+            if ui.color_edit_button_srgba(&mut color).changed() {
+                self.color = Some(color);
+            }
+            if self.color.is_some() && ui.small_button("Theme").clicked() {
+                self.color = None;
+            }
+            ui.checkbox(&mut self.halo, "Halo");
+        });
+    }
+}
+
+/// Label text and anchor of each parcel: the MapNum at a point inside the parcel.
+pub fn parcel_labels(parcels: &Parcels) -> Vec<Option<(Point2d, String)>> {
+    parcels
+        .records
+        .par_iter()
+        .map(|v| {
+            v.geo()
+                .interior_point()
+                .map(|p| (Point2d::new(p.x(), p.y()), v.owner.id.clone()))
+        })
+        .collect()
+}
+
+/// Label text and anchor of each address point: the house number, or the full label if it
+/// does not parse.
+pub fn address_labels(addresses: &AddressPoints) -> Vec<Option<(Point2d, String)>> {
+    addresses
+        .records
+        .par_iter()
+        .map(|v| {
+            let text = match v.address.parts() {
+                Ok(parts) => parts.house_number(),
+                Err(_) => v.address.label.clone(),
+            };
+            Some((v.geometry, text))
+        })
+        .collect()
+}
+
+/// Screen areas taken by labels already drawn in a frame.  Labels are placed greedily, so
+/// layers and features drawn first win.
+#[derive(Debug, Clone, Default)]
+pub struct LabelPlacer {
+    pub placed: Vec<Rect>,
+}
+
+impl LabelPlacer {
+    /// Claim `rect` if it overlaps no label already placed.
+    pub fn claim(&mut self, rect: Rect) -> bool {
+        if self.placed.iter().any(|v| v.intersects(rect)) {
+            return false;
+        }
+        self.placed.push(rect);
+        true
+    }
+
+    /// Draw `text` at the first of `candidates` that is free, each an alignment of the text
+    /// against `anchor` and an offset from it, in the text and halo `colors`.  Returns false if
+    /// every candidate collides.
+    pub fn place(
+        &mut self,
+        painter: &Painter,
+        anchor: Pos2,
+        text: &str,
+        candidates: &[(Align2, Vec2)],
+        style: &LabelStyle,
+        (color, halo): (Color32, Color32),
+    ) -> bool {
+        let font = FontId::proportional(style.size);
+        let galley = painter.layout_no_wrap(text.to_string(), font.clone(), color);
+        for (align, offset) in candidates {
+            let rect = align.anchor_size(anchor + *offset, galley.size());
+            if !painter.clip_rect().contains_rect(rect) || !self.claim(rect.expand(1.0)) {
+                continue;
+            }
+            if style.halo {
+                for d in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+                    painter.text(rect.min + d, Align2::LEFT_TOP, text, font.clone(), halo);
+                }
+            }
+            painter.galley(rect.min, galley, color);
+            return true;
+        }
+        false
+    }
+}
+
+/// Placements tried for point labels: right, left, above and below the symbol.
+pub const POINT_CANDIDATES: [(Align2, Vec2); 4] = [
+    (Align2::LEFT_CENTER, Vec2::new(5., 0.)),
+    (Align2::RIGHT_CENTER, Vec2::new(-5., 0.)),
+    (Align2::CENTER_BOTTOM, Vec2::new(0., -5.)),
+    (Align2::CENTER_TOP, Vec2::new(0., 5.)),
+];
+
+/// Placements tried for area labels: centred, then nudged up or down by a line.
+pub const AREA_CANDIDATES: [(Align2, Vec2); 3] = [
+    (Align2::CENTER_CENTER, Vec2::ZERO),
+    (Align2::CENTER_CENTER, Vec2::new(0., -12.)),
+    (Align2::CENTER_CENTER, Vec2::new(0., 12.)),
+];
@@ -0,0 +1,463 @@
+use crate::prelude::*;
+use egui::{Color32, Ui};
+use galileo_types::cartesian::Point2d;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Colors given to categories in the order they are found, before the user picks their own.
+const CATEGORY_COLORS: [Color32; 10] = [
+    Color32::from_rgb(31, 119, 180),
+    Color32::from_rgb(255, 127, 14),
+    Color32::from_rgb(44, 160, 44),
+    Color32::from_rgb(214, 39, 40),
+    Color32::from_rgb(148, 103, 189),
+    Color32::from_rgb(140, 86, 75),
+    Color32::from_rgb(227, 119, 194),
+    Color32::from_rgb(127, 127, 127),
+    Color32::from_rgb(188, 189, 34),
+    Color32::from_rgb(23, 190, 207),
+];
+
+/// Name of the derived field classifying parcel owners as public, business, trust or individual.
+pub const OWNER_TYPE: &str = "owner_type";
+
+/// Dataset drawn by a layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LayerKind {
+    Parcels,
+    Addresses,
+}
+
+impl std::fmt::Display for LayerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// Symbol of a layer.  Colors left as `None` follow the theme [`MapPalette`].
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct LayerStyle {
+    pub fill: Option<Color32>,
+    pub stroke: Option<Color32>,
+    pub stroke_width: f32,
+    /// Radius of point symbols, in screen points.
+    pub size: f32,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        Self {
+            fill: None,
+            stroke: None,
+            stroke_width: 1.0,
+            size: 2.0,
+        }
+    }
+}
+
+/// Display settings of one dataset on the map.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Layer {
+    pub kind: LayerKind,
+    pub visible: bool,
+    /// From 0 for transparent to 1 for opaque.
+    pub opacity: f32,
+    pub style: LayerStyle,
+    /// Field whose values pick the fill color of each feature, if any.
+    pub category: Option<String>,
+    /// Color of each value of the category field.
+    pub categories: BTreeMap<String, Color32>,
+    /// Value of the category field for each feature, by position in the dataset.  Rebuilt from
+    /// the data rather than saved.
+    #[serde(skip)]
+    pub values: Vec<Option<String>>,
+    pub labels: LabelStyle,
+    /// Anchor and text of the label of each feature, rebuilt from the data like `values`.
+    #[serde(skip)]
+    pub anchors: Vec<Option<(Point2d, String)>>,
+    /// Triangulated fill of each parcel, rebuilt from the data like `values`.
+    #[serde(skip)]
+    pub fills: Vec<ParcelFill>,
+    /// Revision of the dataset the values, labels and fills were built from.
+    #[serde(skip)]
+    pub revision: Option<Revision>,
+}
+
+impl Layer {
+    pub fn new(kind: LayerKind) -> Self {
+        Self {
+            kind,
+            visible: true,
+            opacity: 1.0,
+            style: Default::default(),
+            category: None,
+            categories: BTreeMap::new(),
+            values: Vec::new(),
+            labels: Default::default(),
+            anchors: Vec::new(),
+            fills: Vec::new(),
+            revision: None,
+        }
+    }
+
+    /// Look up the category value, label and fill of every feature, and give new values a color.
+    /// Does nothing if they already cover every feature of the current revision of the data.
+    pub fn refresh(&mut self, parcels: Option<&Parcels>, addresses: Option<&AddressPoints>) {
+        let (len, revision) = match self.kind {
+            LayerKind::Parcels => (parcels.map(|v| v.records.len()), parcels.map(|v| v.revision)),
+            LayerKind::Addresses => (
+                addresses.map(|v| v.records.len()),
+                addresses.map(|v| v.revision),
+            ),
+        };
+        if revision != self.revision {
+            self.values.clear();
+            self.anchors.clear();
+            self.fills.clear();
+            self.revision = revision;
+        }
+        if let (LayerKind::Parcels, Some(data)) = (self.kind, parcels) {
+            if self.fills.len() != data.records.len() {
+                self.fills = data.records.par_iter().map(ParcelFill::new).collect();
+            }
+        }
+        if self.labels.visible && len != Some(self.anchors.len()) {
+            self.anchors = match (self.kind, parcels, addresses) {
+                (LayerKind::Parcels, Some(data), _) => parcel_labels(data),
+                (LayerKind::Addresses, _, Some(data)) => address_labels(data),
+                _ => Vec::new(),
+            };
+        }
+        let Some(field) = &self.category else {
+            self.values.clear();
+            return;
+        };
+        if len == Some(self.values.len()) {
+            return;
+        }
+        self.values = match (self.kind, parcels, addresses) {
+            (LayerKind::Parcels, Some(data), _) => {
+                let headers = data.attribute_headers();
+                (0..data.records.len())
+                    .map(|i| parcel_category(data, headers, i, field))
+                    .collect()
+            }
+            (LayerKind::Addresses, _, Some(data)) => (0..data.records.len())
+                .map(|i| address_category(data, i, field))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut distinct = self.values.iter().flatten().cloned().collect::<Vec<String>>();
+        distinct.sort();
+        distinct.dedup();
+        self.refresh_categories(distinct);
+    }
+
+    /// Fill color of the feature at `index`, by its category if the layer is categorized.
+    pub fn feature_fill(&self, index: usize, palette: &MapPalette) -> Color32 {
+        match self.values.get(index) {
+            Some(value) => self.category_fill(value.as_deref(), palette),
+            None => self.fill(palette),
+        }
+    }
+
+    /// Fill or point color of the layer, from the theme unless set, with the opacity applied.
+    pub fn fill(&self, palette: &MapPalette) -> Color32 {
+        let color = self.style.fill.unwrap_or(match self.kind {
+            LayerKind::Parcels => palette.parcel_fill,
+            LayerKind::Addresses => palette.address,
+        });
+        color.gamma_multiply(self.opacity)
+    }
+
+    pub fn stroke(&self, palette: &MapPalette) -> Color32 {
+        let color = self.style.stroke.unwrap_or(match self.kind {
+            LayerKind::Parcels => palette.parcel_stroke,
+            LayerKind::Addresses => palette.address,
+        });
+        color.gamma_multiply(self.opacity)
+    }
+
+    /// Fill color of a feature whose category field holds `value`, falling back to the layer
+    /// fill for values without a color.
+    pub fn category_fill(&self, value: Option<&str>, palette: &MapPalette) -> Color32 {
+        match value.and_then(|v| self.categories.get(v)) {
+            Some(color) => color.gamma_multiply(self.opacity),
+            None => self.fill(palette),
+        }
+    }
+
+    /// Assign colors to the distinct `values` of the category field, keeping colors already
+    /// chosen.
+    pub fn refresh_categories<I: IntoIterator<Item = String>>(&mut self, values: I) {
+        for value in values {
+            if self.categories.contains_key(&value) {
+                continue;
+            }
+            // Past the end of the palette, repeat its colors dimmed.
+            let n = self.categories.len();
+            let color = CATEGORY_COLORS[n % CATEGORY_COLORS.len()];
+            let color = if n < CATEGORY_COLORS.len() {
+                color
+            } else {
+                color.gamma_multiply(0.6)
+            };
+            self.categories.insert(value, color);
+        }
+    }
+}
+
+/// Classify a parcel owner by name as public, business, trust or individual.
+pub fn owner_type(name: Option<&str>) -> &'static str {
+    let Some(name) = name else {
+        return "Unknown";
+    };
+    let name = name.to_uppercase();
+    let words = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<&str>>();
+    let has = |word: &str| words.contains(&word);
+    if ["CITY OF", "COUNTY", "STATE OF", "UNITED STATES", "SCHOOL DIST", "DISTRICT"]
+        .iter()
+        .any(|v| name.contains(v))
+    {
+        "Public"
+    } else if ["LLC", "INC", "CORP", "CORPORATION", "CO", "COMPANY", "LP", "LLP", "LTD"]
+        .iter()
+        .any(|v| has(v))
+    {
+        "Business"
+    } else if ["TRUST", "TRS", "TRUSTEE", "TRUSTEES", "ESTATE"].iter().any(|v| has(v)) {
+        "Trust"
+    } else {
+        "Individual"
+    }
+}
+
+/// Value of `field` for the parcel at `index`, where `field` is [`OWNER_TYPE`] or one of the
+/// attribute `headers` of `parcels`.
+pub fn parcel_category(
+    parcels: &Parcels,
+    headers: &[String],
+    index: usize,
+    field: &str,
+) -> Option<String> {
+    if field == OWNER_TYPE {
+        let owner = parcels.records.get(index)?.owner.name.as_deref();
+        return Some(owner_type(owner).to_string());
+    }
+    let column = headers.iter().position(|v| v == field)?;
+    parcels
+        .attribute_values(index, headers)
+        .into_iter()
+        .nth(column)
+        .filter(|v| !v.is_empty())
+}
+
+/// Value of `field` for the address point at `index`.
+pub fn address_category(addresses: &AddressPoints, index: usize, field: &str) -> Option<String> {
+    let record = addresses.records.get(index)?;
+    let value = match field {
+        "status" => record.address.status.clone(),
+        "label" => record.address.label.clone(),
+        _ => return None,
+    };
+    Some(value).filter(|v| !v.is_empty())
+}
+
+/// The map layers, drawn from first to last so the last is on top, over an optional basemap.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Layers {
+    pub layers: Vec<Layer>,
+    pub basemaps: Vec<Basemap>,
+    /// Position in `basemaps` of the basemap shown, if any.
+    pub basemap: Option<usize>,
+    pub basemap_opacity: f32,
+    /// Name and path typed into the panel for a new basemap.
+    #[serde(skip)]
+    pub new_basemap: (String, String),
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            layers: vec![Layer::new(LayerKind::Parcels), Layer::new(LayerKind::Addresses)],
+            basemaps: Vec::new(),
+            basemap: None,
+            basemap_opacity: 1.0,
+            new_basemap: Default::default(),
+        }
+    }
+}
+
+impl Layers {
+    /// The basemap shown beneath the layers, if any.
+    pub fn active_basemap(&self) -> Option<&Basemap> {
+        self.basemap.and_then(|i| self.basemaps.get(i))
+    }
+
+    /// Controls to pick, add and remove basemaps.
+    pub fn show_basemaps(&mut self, ui: &mut Ui) {
+        ui.label("Basemap");
+        ui.radio_value(&mut self.basemap, None, "None");
+        let mut remove = None;
+        for (i, basemap) in self.basemaps.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.basemap, Some(i), &basemap.name)
+                    .on_hover_text(basemap.source.path().display().to_string());
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.basemaps.remove(i);
+            self.basemap = match self.basemap {
+                Some(j) if j == i => None,
+                Some(j) if j > i => Some(j - 1),
+                other => other,
+            };
+        }
+        ui.add(egui::Slider::new(&mut self.basemap_opacity, 0.0..=1.0).text("Opacity"));
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_basemap.0)
+                    .hint_text("Name")
+                    .desired_width(80.),
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_basemap.1)
+                    .hint_text("MBTiles file or tile directory"),
+            );
+            let (name, path) = &self.new_basemap;
+            if ui
+                .add_enabled(!path.trim().is_empty(), egui::Button::new("Add"))
+                .clicked()
+            {
+                let name = if name.trim().is_empty() { path } else { name };
+                self.basemaps.push(Basemap::new(name.trim(), path.trim()));
+                self.basemap = Some(self.basemaps.len() - 1);
+                self.new_basemap = Default::default();
+            }
+        });
+    }
+
+    pub fn get(&self, kind: LayerKind) -> Option<&Layer> {
+        self.layers.iter().find(|v| v.kind == kind)
+    }
+
+    /// Bring the category values of every layer up to date with the data.
+    pub fn refresh(&mut self, parcels: Option<&Parcels>, addresses: Option<&AddressPoints>) {
+        for layer in &mut self.layers {
+            layer.refresh(parcels, addresses);
+        }
+    }
+
+    /// Draw the layer list with controls for visibility, order, opacity and style.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+        palette: &MapPalette,
+    ) {
+        self.show_basemaps(ui);
+        let count = self.layers.len();
+        let mut swap = None;
+        // List the top layer first, as it is drawn last.
+        for i in (0..count).rev() {
+            let layer = &mut self.layers[i];
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut layer.visible, layer.kind.to_string());
+                if ui.add_enabled(i + 1 < count, egui::Button::new("Up")).clicked() {
+                    swap = Some((i, i + 1));
+                }
+                if ui.add_enabled(i > 0, egui::Button::new("Down")).clicked() {
+                    swap = Some((i, i - 1));
+                }
+            });
+            ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("Opacity"));
+            ui.horizontal(|ui| {
+                let (fill, stroke) = match layer.kind {
+                    LayerKind::Parcels => (palette.parcel_fill, palette.parcel_stroke),
+                    LayerKind::Addresses => (palette.address, palette.address),
+                };
+                color_choice(ui, "Fill", &mut layer.style.fill, fill);
+                color_choice(ui, "Stroke", &mut layer.style.stroke, stroke);
+            });
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut layer.style.stroke_width)
+                        .clamp_range(0.0..=10.0)
+                        .speed(0.1)
+                        .prefix("Stroke "),
+                );
+                if layer.kind == LayerKind::Addresses {
+                    ui.add(
+                        egui::DragValue::new(&mut layer.style.size)
+                            .clamp_range(0.5..=20.0)
+                            .speed(0.1)
+                            .prefix("Size "),
+                    );
+                }
+            });
+            layer.labels.show(ui, palette.text);
+            let fields = match layer.kind {
+                LayerKind::Parcels => {
+                    let mut fields = vec![OWNER_TYPE.to_string()];
+                    if let Some(data) = parcels {
+                        fields.extend(data.attribute_headers().iter().cloned());
+                    }
+                    fields
+                }
+                LayerKind::Addresses => vec!["status".to_string(), "label".to_string()],
+            };
+            let before = layer.category.clone();
+            egui::ComboBox::from_id_source(("layer_category", layer.kind.to_string()))
+                .selected_text(layer.category.clone().unwrap_or("Single symbol".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut layer.category, None, "Single symbol");
+                    for field in fields {
+                        ui.selectable_value(&mut layer.category, Some(field.clone()), field);
+                    }
+                });
+            if layer.category != before {
+                layer.categories.clear();
+                layer.values.clear();
+                layer.refresh(parcels, addresses);
+            }
+            if !layer.categories.is_empty() {
+                egui::CollapsingHeader::new("Categories")
+                    .id_source(("layer_categories", layer.kind.to_string()))
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+                            for (value, color) in layer.categories.iter_mut() {
+                                ui.horizontal(|ui| {
+                                    ui.color_edit_button_srgba(color);
+                                    ui.label(value);
+                                });
+                            }
+                        });
+                    });
+            }
+        }
+        if let Some((a, b)) = swap {
+            self.layers.swap(a, b);
+        }
+    }
+}
+
+/// Color picker for a color that follows the theme color `theme` while `None`.
+fn color_choice(ui: &mut Ui, name: &str, color: &mut Option<Color32>, theme: Color32) {
+    ui.label(name);
+    let mut value = color.unwrap_or(theme);
+    if ui.color_edit_button_srgba(&mut value).changed() {
+        *color = Some(value);
+    }
+    if color.is_some() && ui.small_button("Theme").clicked() {
+        *color = None;
+    }
+}
@@ -1,22 +1,79 @@
+pub mod abbreviations;
+pub mod adjacency;
 pub mod addresses;
+pub mod attributes;
+pub mod audit;
+pub mod basemap;
 pub mod controls;
 pub mod convert;
+pub mod crs;
+pub mod diff;
+pub mod error;
+pub mod identify;
+pub mod import;
+pub mod ingest;
+pub mod labels;
+pub mod layers;
+pub mod map;
+pub mod measure;
+pub mod metrics;
+pub mod mvt;
 pub mod parcels;
+pub mod parser;
+pub mod print;
 pub mod run;
 pub mod run_ui;
+pub mod search;
+pub mod select;
+pub mod session;
 pub mod state;
 pub mod table;
+pub mod task;
 pub mod utils;
+pub mod validate;
 
 pub mod prelude {
+    pub use crate::adjacency::{mailings, notices_to_csv, Mailing, Notice, NoticePanel, Topology};
     pub use crate::addresses::{Address, AddressPoint, AddressPoints, Addresses};
+    pub use crate::attributes::{ParcelAttributes, ParcelSchema, Value};
+    pub use crate::basemap::{covering_tiles, visible_tiles, Basemap, TileCache, TileId, TileSource};
+    pub use crate::audit::{Audit, AuditConfig, AuditPanel, Issue, IssueKind};
     pub use crate::controls::{Action, Binding, KEY_BINDINGS, MOUSE_BINDINGS};
     pub use crate::convert::Convert;
+    pub use crate::crs::{Crs, LambertConformal, OREGON_SOUTH};
+    pub use crate::diff::{Change, ChangeKind, Diff, DiffPanel, DiffSource};
+    pub use crate::error::{AdapterAttempt, Dropped, GpuError, LoadReport, Whimsy, WhimsyError};
+    pub use crate::identify::{Identified, IdentifyPopup};
+    pub use crate::import::{FieldMap, ImportPanel, ImportSource, Imported};
+    pub use crate::ingest::{from_csv_with, is_csv, stream_csv, Compression, CsvOptions};
+    pub use crate::labels::{
+        address_labels, parcel_labels, LabelPlacer, LabelStyle, AREA_CANDIDATES, POINT_CANDIDATES,
+    };
+    pub use crate::layers::{owner_type, Layer, LayerKind, LayerStyle, Layers, OWNER_TYPE};
+    pub use crate::map::{MapView, ParcelFill};
+    pub use crate::measure::{
+        quadrant_bearing, AreaUnit, LengthUnit, MeasureMode, MeasureTool, Method, Snap, SnapIndex,
+    };
+    pub use crate::metrics::{ParcelMetrics, ParcelReport, SQUARE_FEET_PER_ACRE};
+    pub use crate::mvt::{export_mvt, MvtOptions, ADDRESS_LAYER, PARCEL_LAYER};
     pub use crate::parcels::{Parcel, Parcels};
+    pub use crate::parser::{AddressParts, ParseError};
+    pub use crate::print::{today, Mark, PageSize, PrintLayout, PrintPanel, Sheet};
     pub use crate::run::run;
     pub use crate::run_ui::UiState;
-    pub use crate::state::{EguiState, App, WgpuFrame};
+    pub use crate::search::{AddressMatch, AddressSearch, SearchIndex};
+    pub use crate::select::{
+        is_selection, select_at, select_in, selected, set_selected, SelectLayer, SelectMode,
+        SelectTarget, SelectTool, Selectable,
+    };
+    pub use crate::session::{Session, SESSION_PATH};
+    pub use crate::state::{Appearance, App, EguiState, Gpu, Headless, MapPalette, ThemeMode, WgpuFrame};
     pub use crate::table::{Columnar, Tabular, TableView};
-    pub use crate::utils::{from_csv, point_bounds};
+    pub use crate::task::Task;
+    pub use crate::validate::{Defect, Finding, Validation, ValidationConfig, ValidationPanel};
+    pub use crate::utils::{
+        from_csv, point_bounds, read_cache, rect_intersects, to_csv, web_mercator_to_wgs84,
+        wgs84_to_web_mercator, write_cache, Revision, CACHE_MAGIC, CACHE_VERSION, EARTH_RADIUS,
+    };
 }
 
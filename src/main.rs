@@ -1,4 +1,8 @@
-use whimsy::prelude::run;
+use whimsy::prelude::{
+    export_mvt, run, AddressPoints, Addresses, Crs, GpuError, Headless, Layers, LoadReport,
+    MapView, MvtOptions, PageSize, Parcels, PrintLayout, Session, Sheet, TileSource, UiState,
+    Whimsy, WhimsyError, SESSION_PATH,
+};
 use polite::Polite;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -11,14 +15,196 @@ async fn main() -> Polite<()> {
                 .unwrap_or_else(|_| "whimsy=info".into()),
         )
         .with(tracing_subscriber::fmt::layer())
-        .try_init().is_ok() {}; 
+        .try_init().is_ok() {};
     tracing::info!("Subscriber initialized.");
 
-    let event_loop = winit::event_loop::EventLoop::new().unwrap();
-    let window = winit::window::WindowBuilder::new()
+    // `whimsy --snapshot out.png` renders a single frame offscreen instead of opening a window.
+    let args = std::env::args().collect::<Vec<String>>();
+    if let Some(index) = args.iter().position(|v| v == "--snapshot") {
+        let path = args.get(index + 1).cloned().unwrap_or("snapshot.png".to_string());
+        let mut headless = match Headless::new(1280, 800).await {
+            Ok(value) => value,
+            Err(e) => startup_failed(e),
+        };
+        let mut ui_state = UiState::new();
+        ui_state.appearance.apply(&headless.context);
+        headless.save_png(&path, |ctx| ui_state.run(ctx))?;
+        tracing::info!("Snapshot written to {}.", path);
+        return Ok(());
+    }
+
+    // `whimsy --load-parcels taxlots.shp --crs EPSG:2270` reads a source file and lists the
+    // records it skipped, without opening a window.  `--report dropped.csv` saves the list.
+    for flag in ["--load-addresses", "--load-parcels"] {
+        if let Some(index) = args.iter().position(|v| v == flag) {
+            let Some(path) = args.get(index + 1) else {
+                eprintln!("{} needs a path.", flag);
+                std::process::exit(2);
+            };
+            // Addresses ignore the CRS, so only parcels fail on an unparseable `--crs`.
+            let result = match flag {
+                "--load-addresses" => Addresses::read(path).map(|(_, report)| report),
+                _ => option(&args, "--crs")
+                    .map(|v| v.parse::<Crs>())
+                    .transpose()
+                    .and_then(|crs| load_parcels(path, crs))
+                    .map(|(_, report)| report),
+            };
+            match result {
+                Ok(report) => {
+                    print_report(&report);
+                    if let Some(out) = option(&args, "--report") {
+                        report.to_csv(&out)?;
+                        println!("Dropped records written to {}.", out);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Could not load {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    // `whimsy --export-mvt tiles.mbtiles --zoom 12-16` writes vector tiles of the parcel and
+    // address caches, or of the files named by `--parcels` and `--addresses`.  A path without an
+    // `.mbtiles` extension is written as a `z/x/y.pbf` directory.
+    if let Some(out) = option(&args, "--export-mvt") {
+        match export_tiles(&args, &out) {
+            Ok(count) => println!("Wrote {} tiles to {}.", count, out),
+            Err(e) => {
+                eprintln!("Could not export tiles to {}: {}", out, e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // `whimsy --print map.pdf --title "Zoning" --page tabloid --dpi 300` prints the view and
+    // layers of the saved session to a PDF or PNG page offscreen.  `--portrait` turns the page.
+    if let Some(out) = option(&args, "--print") {
+        let mut layout = PrintLayout {
+            landscape: !args.iter().any(|v| v == "--portrait"),
+            ..Default::default()
+        };
+        if let Some(title) = option(&args, "--title") {
+            layout.title = title;
+        }
+        if let Some(page) = option(&args, "--page") {
+            match page.parse::<PageSize>() {
+                Ok(page) => layout.page = page,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+        if let Some(dpi) = option(&args, "--dpi") {
+            match dpi.parse::<u32>() {
+                Ok(dpi) => layout.dpi = dpi.clamp(72, 600),
+                Err(_) => {
+                    let e = WhimsyError::Schema {
+                        field: "dpi".to_string(),
+                        message: format!("{} is not a whole number of dots per inch", dpi),
+                    };
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+        let parcels = Parcels::load("data/parcels.data").ok();
+        let addresses = Addresses::load("data/addresses.data")
+            .ok()
+            .map(AddressPoints::from);
+        let mut map = MapView::default();
+        let mut layers = Layers::default();
+        if let Ok(session) = Session::load(SESSION_PATH) {
+            session.apply(&mut map, &mut layers);
+        }
+        layers.refresh(parcels.as_ref(), addresses.as_ref());
+        let sheet = Sheet::new(&layout, &map, parcels.as_ref(), addresses.as_ref(), &layers);
+        if let Err(e) = sheet.save(&out).await {
+            eprintln!("Could not print to {}: {}", out, e);
+            std::process::exit(1);
+        }
+        println!("Printed map to {}.", out);
+        return Ok(());
+    }
+
+    let event_loop = match winit::event_loop::EventLoop::new() {
+        Ok(event_loop) => event_loop,
+        Err(e) => startup_failed(e.into()),
+    };
+    let window = match winit::window::WindowBuilder::new()
         .with_title("Whimsy")
-        .build(&event_loop)?;
+        .build(&event_loop)
+    {
+        Ok(window) => window,
+        Err(e) => startup_failed(e.into()),
+    };
 
-    run(window, event_loop).await;
+    if let Err(e) = run(window, event_loop).await {
+        startup_failed(e);
+    }
     Ok(())
 }
+
+/// Report why rendering could not start and exit, rather than panicking with a backtrace.
+fn startup_failed(e: GpuError) -> ! {
+    eprintln!("Whimsy could not start.");
+    e.report();
+    std::process::exit(1)
+}
+
+/// Value following `flag` on the command line.
+fn option(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|v| v == flag)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+/// Read parcels from a shapefile in `crs`, defaulting to Oregon South, or from any other source
+/// `Parcels::read` accepts.
+fn load_parcels(path: &str, crs: Option<Crs>) -> Whimsy<(Parcels, LoadReport)> {
+    let shp = std::path::Path::new(path)
+        .extension()
+        .and_then(|v| v.to_str())
+        .is_some_and(|v| v.eq_ignore_ascii_case("shp"));
+    if shp {
+        Parcels::from_shp(path, &crs.unwrap_or(Crs::OregonSouth))
+    } else {
+        Parcels::read(path)
+    }
+}
+
+/// Export vector tiles for the `--export-mvt` flag, skipping a default cache that is missing.
+fn export_tiles(args: &[String], out: &str) -> Whimsy<usize> {
+    let crs = option(args, "--crs").map(|v| v.parse::<Crs>()).transpose()?;
+    let parcels = match option(args, "--parcels") {
+        Some(path) => Some(load_parcels(&path, crs)?.0),
+        None => Parcels::load("data/parcels.data").ok(),
+    };
+    let addresses = match option(args, "--addresses") {
+        Some(path) => Some(Addresses::read(&path)?.0),
+        None => Addresses::load("data/addresses.data").ok(),
+    };
+    let addresses = addresses.map(AddressPoints::from);
+    let mut options = MvtOptions::default();
+    if let Some(range) = option(args, "--zoom") {
+        options = options.with_zoom(&range)?;
+    }
+    export_mvt(
+        parcels.as_ref(),
+        addresses.as_ref(),
+        &TileSource::from_path(out),
+        &options,
+    )
+}
+
+fn print_report(report: &LoadReport) {
+    println!("{}", report.summary());
+    for dropped in &report.dropped {
+        println!("  row {}: {}", dropped.row, dropped.reason);
+    }
+}
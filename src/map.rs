@@ -0,0 +1,301 @@
+use crate::prelude::*;
+use egui::epaint::Mesh;
+use egui::{Color32, Painter, Pos2, Response, Sense, Shape, Stroke, Ui, Vec2};
+use geo::TriangulateEarcut;
+use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+
+/// Triangulated fill of a parcel in map coordinates, holes included.  Built once per parcel by
+/// [`Layer::refresh`] rather than each frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParcelFill {
+    pub vertices: Vec<Point2d>,
+    /// Positions in `vertices` of the corners of each triangle, three to a triangle.
+    pub indices: Vec<u32>,
+}
+
+impl ParcelFill {
+    pub fn new(parcel: &Parcel) -> Self {
+        let mut fill = Self::default();
+        for poly in parcel.geo() {
+            let raw = poly.earcut_triangles_raw();
+            let base = fill.vertices.len() as u32;
+            fill.vertices.extend(
+                raw.vertices
+                    .chunks_exact(2)
+                    .map(|xy| Point2d::new(xy[0], xy[1])),
+            );
+            fill.indices
+                .extend(raw.triangle_indices.iter().map(|v| base + *v as u32));
+        }
+        fill
+    }
+}
+
+/// Web Mercator coordinates of downtown Grants Pass, used before any data is loaded.
+const HOME: (f64, f64) = (-13_729_000.0, 5_233_000.0);
+
+/// The visible area of the map in a single window.  Map coordinates are Web Mercator meters,
+/// screen coordinates are egui points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapView {
+    pub center: Point2d,
+    /// Map units per screen point.
+    pub resolution: f64,
+    /// Screen area the map was last drawn into.
+    pub screen: egui::Rect,
+    /// Whether dragging with the primary button pans.  Off while a selection tool uses the drag;
+    /// the middle button always pans.
+    pub pan: bool,
+}
+
+impl Default for MapView {
+    fn default() -> Self {
+        Self {
+            center: Point2d::new(HOME.0, HOME.1),
+            resolution: 2.0,
+            screen: egui::Rect::from_min_size(Pos2::ZERO, Vec2::new(800., 600.)),
+            pan: true,
+        }
+    }
+}
+
+impl MapView {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn to_screen(&self, x: f64, y: f64) -> Pos2 {
+        let center = self.screen.center();
+        Pos2::new(
+            center.x + ((x - self.center.x()) / self.resolution) as f32,
+            center.y - ((y - self.center.y()) / self.resolution) as f32,
+        )
+    }
+
+    pub fn point_to_screen(&self, point: &Point2d) -> Pos2 {
+        self.to_screen(point.x(), point.y())
+    }
+
+    pub fn to_map(&self, pos: Pos2) -> Point2d {
+        let center = self.screen.center();
+        Point2d::new(
+            self.center.x() + (pos.x - center.x) as f64 * self.resolution,
+            self.center.y() - (pos.y - center.y) as f64 * self.resolution,
+        )
+    }
+
+    /// Map area covered by the screen rectangle.
+    pub fn extent(&self) -> Rect {
+        let half_width = self.screen.width() as f64 * self.resolution / 2.0;
+        let half_height = self.screen.height() as f64 * self.resolution / 2.0;
+        Rect::new(
+            self.center.x() - half_width,
+            self.center.y() - half_height,
+            self.center.x() + half_width,
+            self.center.y() + half_height,
+        )
+    }
+
+    pub fn centre_on(&mut self, point: &Point2d) {
+        self.center = Point2d::new(point.x(), point.y());
+    }
+
+    /// Centre the view on `bounds` and zoom out until it fits on screen.
+    pub fn fit(&mut self, bounds: &Rect) {
+        self.center = Point2d::new(
+            (bounds.x_min() + bounds.x_max()) / 2.0,
+            (bounds.y_min() + bounds.y_max()) / 2.0,
+        );
+        let x_res = (bounds.x_max() - bounds.x_min()) / self.screen.width().max(1.) as f64;
+        let y_res = (bounds.y_max() - bounds.y_min()) / self.screen.height().max(1.) as f64;
+        let resolution = x_res.max(y_res) * 1.1;
+        if resolution > 0.0 {
+            self.resolution = resolution;
+        }
+    }
+
+    pub fn pan(&mut self, delta: Vec2) {
+        self.center = Point2d::new(
+            self.center.x() - delta.x as f64 * self.resolution,
+            self.center.y() + delta.y as f64 * self.resolution,
+        );
+    }
+
+    /// Zoom by `factor` (greater than one zooms in), keeping the map location under `anchor`
+    /// fixed on screen.
+    pub fn zoom(&mut self, factor: f64, anchor: Pos2) {
+        let before = self.to_map(anchor);
+        self.resolution = (self.resolution / factor).clamp(0.01, 20_000.0);
+        let after = self.to_map(anchor);
+        self.center = Point2d::new(
+            self.center.x() + before.x() - after.x(),
+            self.center.y() + before.y() - after.y(),
+        );
+    }
+
+    /// Pan and zoom in response to pointer input over `response`.
+    pub fn interact(&mut self, ui: &Ui, response: &Response) {
+        if (self.pan && response.dragged_by(egui::PointerButton::Primary))
+            || response.dragged_by(egui::PointerButton::Middle)
+        {
+            self.pan(response.drag_delta());
+        }
+        if let Some(anchor) = response.hover_pos() {
+            let (scroll, zoom) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+            let factor = (scroll as f64 * 0.002).exp() * zoom as f64;
+            if factor != 1.0 {
+                self.zoom(factor, anchor);
+            }
+        }
+    }
+
+    /// Draw the basemap and visible layers into the available space of `ui`, bottom layer first.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+        layers: &Layers,
+        tiles: &mut TileCache,
+        palette: &MapPalette,
+    ) -> Response {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
+        self.screen = response.rect;
+        self.interact(ui, &response);
+        painter.rect_filled(self.screen, 0.0, palette.background);
+        if let Some(basemap) = layers.active_basemap() {
+            tiles.draw(&painter, self, basemap, layers.basemap_opacity);
+        }
+
+        for layer in layers.layers.iter().filter(|v| v.visible) {
+            match layer.kind {
+                LayerKind::Parcels => {
+                    if let Some(data) = parcels {
+                        self.draw_parcels(&painter, data, layer, palette);
+                    }
+                }
+                LayerKind::Addresses => {
+                    if let Some(data) = addresses {
+                        self.draw_addresses(&painter, data, layer, palette);
+                    }
+                }
+            }
+        }
+
+        // Labels go over every layer, the top layer placing its labels first.
+        let mut placer = LabelPlacer::default();
+        for layer in layers.layers.iter().rev().filter(|v| {
+            v.visible && v.labels.visible && self.resolution <= v.labels.max_resolution
+        }) {
+            self.draw_labels(&painter, layer, &mut placer, palette);
+        }
+
+        response
+    }
+
+    fn draw_labels(
+        &self,
+        painter: &Painter,
+        layer: &Layer,
+        placer: &mut LabelPlacer,
+        palette: &MapPalette,
+    ) {
+        let extent = self.extent();
+        let colors = (
+            layer.labels.color.unwrap_or(palette.text),
+            palette.background,
+        );
+        let candidates = match layer.kind {
+            LayerKind::Parcels => &AREA_CANDIDATES[..],
+            LayerKind::Addresses => &POINT_CANDIDATES[..],
+        };
+        for (anchor, text) in layer.anchors.iter().flatten() {
+            if !extent.contains(anchor) {
+                continue;
+            }
+            let pos = self.point_to_screen(anchor);
+            placer.place(painter, pos, text, candidates, &layer.labels, colors);
+        }
+    }
+
+    fn draw_parcels(&self, painter: &Painter, data: &Parcels, layer: &Layer, palette: &MapPalette) {
+        let extent = self.extent();
+        let stroke = Stroke::new(layer.style.stroke_width, layer.stroke(palette));
+        for (i, parcel) in data
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| rect_intersects(&v.bounds, &extent))
+        {
+            let fill = layer.feature_fill(i, palette);
+            // Skip filling parcels smaller than a few points across, where it would not show.
+            let width = (parcel.bounds.x_max() - parcel.bounds.x_min()) / self.resolution;
+            if fill.a() > 0 && width > 3.0 {
+                let mesh = match layer.fills.get(i) {
+                    Some(triangles) => self.fill_mesh(triangles, fill),
+                    None => self.fill_mesh(&ParcelFill::new(parcel), fill),
+                };
+                painter.add(mesh);
+            }
+            let stroke = if parcel.selected {
+                Stroke::new(stroke.width + 1.0, palette.selected)
+            } else {
+                stroke
+            };
+            for poly in &parcel.geometry.parts {
+                painter.add(Shape::closed_line(
+                    self.ring_to_screen(&poly.outer_contour.points),
+                    stroke,
+                ));
+                for ring in &poly.inner_contours {
+                    painter.add(Shape::closed_line(self.ring_to_screen(&ring.points), stroke));
+                }
+            }
+        }
+    }
+
+    fn draw_addresses(
+        &self,
+        painter: &Painter,
+        data: &AddressPoints,
+        layer: &Layer,
+        palette: &MapPalette,
+    ) {
+        let extent = self.extent();
+        let size = layer.style.size;
+        for (i, point) in data
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| extent.contains(&v.geometry))
+        {
+            let pos = self.point_to_screen(&point.geometry);
+            if point.selected {
+                painter.circle_filled(pos, size * 2.0, palette.selected);
+            } else if layer.style.stroke.is_some() {
+                painter.circle(
+                    pos,
+                    size,
+                    layer.feature_fill(i, palette),
+                    Stroke::new(layer.style.stroke_width, layer.stroke(palette)),
+                );
+            } else {
+                painter.circle_filled(pos, size, layer.feature_fill(i, palette));
+            }
+        }
+    }
+
+    /// Mesh of the triangles of a parcel fill on screen.
+    fn fill_mesh(&self, triangles: &ParcelFill, color: Color32) -> Mesh {
+        let mut mesh = Mesh::default();
+        for point in &triangles.vertices {
+            mesh.colored_vertex(self.point_to_screen(point), color);
+        }
+        mesh.indices.extend_from_slice(&triangles.indices);
+        mesh
+    }
+
+    fn ring_to_screen(&self, points: &[Point2d]) -> Vec<Pos2> {
+        points.iter().map(|v| self.point_to_screen(v)).collect()
+    }
+}
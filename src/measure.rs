@@ -0,0 +1,579 @@
+use crate::crs::{FEET_PER_MILE, FOOT};
+use crate::prelude::*;
+use crate::select::PICK_RADIUS;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Response, Shape, Stroke, Ui, Vec2};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use geo::{Area, EuclideanLength, GeodesicArea, GeodesicBearing, GeodesicLength};
+use rstar::primitives::GeomWithData;
+use rstar::{PointDistance, RTree};
+use std::sync::{Arc, RwLock};
+
+/// What clicks on the map measure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MeasureMode {
+    /// Measuring is off and clicks select and identify features.
+    #[default]
+    Off,
+    /// Length of a polyline through the clicked points.
+    Distance,
+    /// Area and perimeter of the polygon through the clicked points.
+    Area,
+    /// Bearing and distance from the first clicked point to the second.
+    Bearing,
+}
+
+/// How map distances are turned into ground distances.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Method {
+    /// Planar measure in Oregon South state plane feet, as the county surveys.
+    #[default]
+    StatePlane,
+    /// Measure on the GRS80 ellipsoid.
+    Geodesic,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LengthUnit {
+    #[default]
+    Feet,
+    Miles,
+}
+
+impl LengthUnit {
+    pub fn format(&self, feet: f64) -> String {
+        match self {
+            Self::Feet => format!("{:.1} ft", feet),
+            Self::Miles => format!("{:.3} mi", feet / FEET_PER_MILE),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AreaUnit {
+    SquareFeet,
+    #[default]
+    Acres,
+}
+
+impl AreaUnit {
+    pub fn format(&self, square_feet: f64) -> String {
+        match self {
+            Self::SquareFeet => format!("{:.0} sq ft", square_feet),
+            Self::Acres => format!("{:.3} ac", square_feet / SQUARE_FEET_PER_ACRE),
+        }
+    }
+}
+
+/// Web Mercator `points` in Oregon South state plane feet.
+fn state_plane(points: &[Point2d]) -> Vec<geo::Coord> {
+    points
+        .iter()
+        .map(|v| {
+            let (x, y) = Crs::WebMercator.transform(&Crs::OregonSouth, v.x(), v.y());
+            geo::coord! { x: x, y: y }
+        })
+        .collect()
+}
+
+/// Web Mercator `points` as longitude and latitude.
+fn lon_lat(points: &[Point2d]) -> Vec<geo::Coord> {
+    points
+        .iter()
+        .map(|v| {
+            let (x, y) = Crs::WebMercator.transform(&Crs::Wgs84, v.x(), v.y());
+            geo::coord! { x: x, y: y }
+        })
+        .collect()
+}
+
+/// Ground length in feet of the polyline through the Web Mercator `points`.
+pub fn length(points: &[Point2d], method: Method) -> f64 {
+    match method {
+        Method::StatePlane => geo::LineString::from(state_plane(points)).euclidean_length(),
+        Method::Geodesic => geo::LineString::from(lon_lat(points)).geodesic_length() / FOOT,
+    }
+}
+
+/// Ground area in square feet of the polygon through the Web Mercator `points`.
+pub fn area(points: &[Point2d], method: Method) -> f64 {
+    if points.len() < 3 {
+        return 0.0;
+    }
+    match method {
+        Method::StatePlane => {
+            geo::Polygon::new(state_plane(points).into(), Vec::new()).unsigned_area()
+        }
+        Method::Geodesic => {
+            geo::Polygon::new(lon_lat(points).into(), Vec::new()).geodesic_area_unsigned()
+                / (FOOT * FOOT)
+        }
+    }
+}
+
+/// Azimuth in degrees clockwise from north, 0 to 360, from `from` to `to`.  State plane
+/// bearings are grid bearings; geodesic bearings are from true north.
+pub fn bearing(from: &Point2d, to: &Point2d, method: Method) -> f64 {
+    let azimuth = match method {
+        Method::StatePlane => {
+            let ends = state_plane(&[*from, *to]);
+            (ends[1].x - ends[0].x)
+                .atan2(ends[1].y - ends[0].y)
+                .to_degrees()
+        }
+        Method::Geodesic => {
+            let ends = lon_lat(&[*from, *to]);
+            geo::Point::from(ends[0]).geodesic_bearing(geo::Point::from(ends[1]))
+        }
+    };
+    azimuth.rem_euclid(360.0)
+}
+
+/// Surveyor's quadrant bearing of an `azimuth` in degrees, such as N 45°12'30" E.
+pub fn quadrant_bearing(azimuth: f64) -> String {
+    let azimuth = azimuth.rem_euclid(360.0);
+    let (from, angle, to) = match azimuth {
+        a if a <= 90.0 => ('N', a, 'E'),
+        a if a <= 180.0 => ('S', 180.0 - a, 'E'),
+        a if a <= 270.0 => ('S', a - 180.0, 'W'),
+        a => ('N', 360.0 - a, 'W'),
+    };
+    let seconds = (angle * 3600.0).round() as u64;
+    format!(
+        "{} {}°{:02}'{:02}\" {}",
+        from,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        to
+    )
+}
+
+/// Kind of feature a measurement point snapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    Vertex,
+    Address,
+}
+
+/// Parcel vertices and address points in Web Mercator, for snapping measurement points.
+#[derive(Debug, Clone)]
+pub struct SnapIndex {
+    pub tree: RTree<GeomWithData<[f64; 2], Snap>>,
+    /// Revision of the parcels the index was built from.
+    pub parcels: Option<Revision>,
+    /// Revision of the address points the index was built from.
+    pub addresses: Option<Revision>,
+}
+
+impl SnapIndex {
+    pub fn new(parcels: Option<&Parcels>, addresses: Option<&AddressPoints>) -> Self {
+        let mut points = Vec::new();
+        if let Some(data) = parcels {
+            for parcel in &data.records {
+                for poly in &parcel.geometry.parts {
+                    let rings = std::iter::once(&poly.outer_contour).chain(&poly.inner_contours);
+                    for point in rings.flat_map(|v| &v.points) {
+                        let p = data.crs.point(&Crs::WebMercator, point);
+                        points.push(GeomWithData::new([p.x(), p.y()], Snap::Vertex));
+                    }
+                }
+            }
+        }
+        if let Some(data) = addresses {
+            for record in &data.records {
+                let p = data.crs.point(&Crs::WebMercator, &record.geometry);
+                points.push(GeomWithData::new([p.x(), p.y()], Snap::Address));
+            }
+        }
+        Self {
+            tree: RTree::bulk_load(points),
+            parcels: parcels.map(|v| v.revision),
+            addresses: addresses.map(|v| v.revision),
+        }
+    }
+
+    /// The vertex or address point nearest `point`, if within `tolerance` map units.
+    pub fn snap(&self, point: &Point2d, tolerance: f64) -> Option<(Point2d, Snap)> {
+        let query = [point.x(), point.y()];
+        self.tree
+            .nearest_neighbor(&query)
+            .filter(|v| v.distance_2(&query) <= tolerance * tolerance)
+            .map(|v| (Point2d::new(v.geom()[0], v.geom()[1]), v.data))
+    }
+}
+
+/// Distance, area and bearing measurement on the map.  Click to add points, right-click or
+/// press Escape to start over.
+#[derive(Debug, Clone)]
+pub struct MeasureTool {
+    pub mode: MeasureMode,
+    pub method: Method,
+    pub length_unit: LengthUnit,
+    pub area_unit: AreaUnit,
+    /// Whether points snap to nearby parcel vertices and address points.
+    pub snap: bool,
+    /// Clicked points in Web Mercator.
+    pub points: Vec<Point2d>,
+    /// Point under the pointer, and what it snapped to.
+    pub hover: Option<(Point2d, Option<Snap>)>,
+    /// Built on first use, and rebuilt when the parcels change or a dataset is replaced.
+    pub index: Option<SnapIndex>,
+    /// Snapping index being built in the background.
+    pub building: Option<Task<SnapIndex>>,
+}
+
+impl Default for MeasureTool {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            method: Default::default(),
+            length_unit: Default::default(),
+            area_unit: Default::default(),
+            snap: true,
+            points: Vec::new(),
+            hover: None,
+            index: None,
+            building: None,
+        }
+    }
+}
+
+impl MeasureTool {
+    /// Measurement of `points` in the current mode and units, or nothing until there are
+    /// enough points.
+    pub fn describe(&self, points: &[Point2d]) -> String {
+        let needed = match self.mode {
+            MeasureMode::Area => 3,
+            _ => 2,
+        };
+        if points.len() < needed {
+            return String::new();
+        }
+        match self.mode {
+            MeasureMode::Off => String::new(),
+            MeasureMode::Distance => {
+                let total = self.length_unit.format(length(points, self.method));
+                match points {
+                    [.., a, b] if points.len() > 2 => format!(
+                        "{} (last segment {})",
+                        total,
+                        self.length_unit.format(length(&[*a, *b], self.method))
+                    ),
+                    _ => total,
+                }
+            }
+            MeasureMode::Area => {
+                let mut ring = points.to_vec();
+                ring.extend(points.first());
+                format!(
+                    "{}, perimeter {}",
+                    self.area_unit.format(area(points, self.method)),
+                    self.length_unit.format(length(&ring, self.method))
+                )
+            }
+            MeasureMode::Bearing => match points {
+                [a, b, ..] => {
+                    let azimuth = bearing(a, b, self.method);
+                    format!(
+                        "{} ({:.4}°), {}",
+                        quadrant_bearing(azimuth),
+                        azimuth,
+                        self.length_unit.format(length(&[*a, *b], self.method))
+                    )
+                }
+                _ => String::new(),
+            },
+        }
+    }
+
+    /// Draw the tool, unit and method choices and the measurement so far.
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let before = self.mode;
+            ui.selectable_value(&mut self.mode, MeasureMode::Off, "Off");
+            ui.selectable_value(&mut self.mode, MeasureMode::Distance, "Distance");
+            ui.selectable_value(&mut self.mode, MeasureMode::Area, "Area");
+            ui.selectable_value(&mut self.mode, MeasureMode::Bearing, "Bearing");
+            if self.mode != before {
+                self.points.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.length_unit, LengthUnit::Feet, "Feet");
+            ui.selectable_value(&mut self.length_unit, LengthUnit::Miles, "Miles");
+            ui.separator();
+            ui.selectable_value(&mut self.area_unit, AreaUnit::SquareFeet, "Sq ft");
+            ui.selectable_value(&mut self.area_unit, AreaUnit::Acres, "Acres");
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.method, Method::StatePlane, "State plane");
+            ui.selectable_value(&mut self.method, Method::Geodesic, "Geodesic");
+            ui.checkbox(&mut self.snap, "Snap");
+            if ui.button("Clear").clicked() {
+                self.points.clear();
+            }
+        });
+        let result = self.describe(&self.points);
+        if result.is_empty() {
+            if self.mode != MeasureMode::Off {
+                ui.label("Click the map to add points.");
+            }
+        } else {
+            ui.horizontal(|ui| {
+                ui.label(&result);
+                if ui.small_button("Copy").clicked() {
+                    ui.output_mut(|o| o.copied_text = result.clone());
+                }
+            });
+        }
+    }
+
+    /// Add clicked points over the map `response`, snapping them if asked.  The snapping index
+    /// is built in the background, and points are not snapped until it is ready.
+    pub fn interact(
+        &mut self,
+        ui: &Ui,
+        response: &Response,
+        map: &MapView,
+        parcels: Option<&Arc<RwLock<Parcels>>>,
+        addresses: Option<&Arc<RwLock<AddressPoints>>>,
+    ) {
+        if self.mode == MeasureMode::Off {
+            self.hover = None;
+            return;
+        }
+        if let Some(index) = self.building.as_ref().and_then(|v| v.poll()) {
+            self.index = Some(index);
+            self.building = None;
+        }
+        if self.snap {
+            self.index_data(ui.ctx(), parcels, addresses);
+        }
+        self.hover = response.hover_pos().map(|pos| self.locate(map, pos));
+        if response.clicked() {
+            let point = response
+                .interact_pointer_pos()
+                .map(|pos| self.locate(map, pos))
+                .or(self.hover);
+            if let Some((point, _)) = point {
+                if self.mode == MeasureMode::Bearing && self.points.len() == 2 {
+                    self.points.clear();
+                }
+                self.points.push(point);
+            }
+        }
+        if response.secondary_clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.points.clear();
+        }
+    }
+
+    /// Drop the snapping index if the parcels or addresses have changed since it was built, and
+    /// start building one for the current data if there is none.
+    fn index_data(
+        &mut self,
+        ctx: &egui::Context,
+        parcels: Option<&Arc<RwLock<Parcels>>>,
+        addresses: Option<&Arc<RwLock<AddressPoints>>>,
+    ) {
+        let revisions = (
+            parcels.and_then(|v| v.read().ok()).map(|v| v.revision),
+            addresses.and_then(|v| v.read().ok()).map(|v| v.revision),
+        );
+        if self
+            .index
+            .as_ref()
+            .is_some_and(|v| (v.parcels, v.addresses) != revisions)
+        {
+            self.index = None;
+        }
+        if self.index.is_none() && self.building.is_none() {
+            let parcels = parcels.cloned();
+            let addresses = addresses.cloned();
+            self.building = Some(Task::spawn(ctx, move || {
+                let parcels = parcels.as_ref().and_then(|v| v.read().ok());
+                let addresses = addresses.as_ref().and_then(|v| v.read().ok());
+                SnapIndex::new(parcels.as_deref(), addresses.as_deref())
+            }));
+        }
+    }
+
+    /// Map position under the screen position `pos`, moved to a feature within reach if
+    /// snapping.
+    fn locate(&self, map: &MapView, pos: Pos2) -> (Point2d, Option<Snap>) {
+        let point = map.to_map(pos);
+        let snapped = self
+            .index
+            .as_ref()
+            .filter(|_| self.snap)
+            .and_then(|v| v.snap(&point, PICK_RADIUS * map.resolution));
+        match snapped {
+            Some((point, snap)) => (point, Some(snap)),
+            None => (point, None),
+        }
+    }
+
+    /// Draw the measured line or polygon, following the pointer to the next point.
+    pub fn overlay(&self, painter: &Painter, map: &MapView, color: Color32) {
+        if self.mode == MeasureMode::Off {
+            return;
+        }
+        let mut points = self.points.clone();
+        let adding = !(self.mode == MeasureMode::Bearing && points.len() == 2);
+        if let Some((point, _)) = self.hover.filter(|_| adding && !points.is_empty()) {
+            points.push(point);
+        }
+        let screen = points
+            .iter()
+            .map(|v| map.point_to_screen(v))
+            .collect::<Vec<Pos2>>();
+        let stroke = Stroke::new(2.0, color);
+        if self.mode == MeasureMode::Area && screen.len() > 2 {
+            painter.add(Shape::closed_line(screen.clone(), stroke));
+        } else {
+            painter.add(Shape::line(screen.clone(), stroke));
+        }
+        for pos in &screen {
+            painter.circle(*pos, 3.0, Color32::WHITE, Stroke::new(1.5, color));
+        }
+        if let Some((point, Some(_))) = self.hover {
+            let pos = map.point_to_screen(&point);
+            let rect = egui::Rect::from_center_size(pos, Vec2::splat(10.0));
+            painter.rect_stroke(rect, 0.0, Stroke::new(1.5, color));
+        }
+        let text = self.describe(&points);
+        if let (Some(last), false) = (screen.last(), text.is_empty()) {
+            let pos = *last + Vec2::new(10.0, -10.0);
+            let font = FontId::proportional(12.0);
+            for d in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+                painter.text(
+                    pos + d,
+                    Align2::LEFT_BOTTOM,
+                    &text,
+                    font.clone(),
+                    Color32::BLACK,
+                );
+            }
+            painter.text(pos, Align2::LEFT_BOTTOM, &text, font, Color32::WHITE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Near Grants Pass, in Oregon South state plane feet.
+    const ORIGIN: (f64, f64) = (4_135_000.0, 240_000.0);
+
+    /// Web Mercator points offset from `ORIGIN` by state plane feet.
+    fn grid(offsets: &[(f64, f64)]) -> Vec<Point2d> {
+        offsets
+            .iter()
+            .map(|(dx, dy)| {
+                Crs::OregonSouth.point(
+                    &Crs::WebMercator,
+                    &Point2d::new(ORIGIN.0 + dx, ORIGIN.1 + dy),
+                )
+            })
+            .collect()
+    }
+
+    /// Difference between two azimuths in degrees, ignoring the turn at north.
+    fn turn(a: f64, b: f64) -> f64 {
+        let d = (a - b).rem_euclid(360.0);
+        d.min(360.0 - d)
+    }
+
+    #[test]
+    fn formats_quadrant_bearings() {
+        let cases = [
+            (0.0, "N 0°00'00\" E"),
+            (45.0 + 12.0 / 60.0 + 30.0 / 3600.0, "N 45°12'30\" E"),
+            (90.0, "N 90°00'00\" E"),
+            (135.0, "S 45°00'00\" E"),
+            (180.0, "S 0°00'00\" E"),
+            (225.0, "S 45°00'00\" W"),
+            (270.0, "S 90°00'00\" W"),
+            (315.0, "N 45°00'00\" W"),
+            (360.0, "N 0°00'00\" E"),
+            (-45.0, "N 45°00'00\" W"),
+            (720.25, "N 0°15'00\" E"),
+            (200.5 + 29.6 / 3600.0, "S 20°30'30\" W"),
+            // Rounding to the nearest second carries into the minutes and degrees.
+            (10.99999, "N 11°00'00\" E"),
+            (12.0 + 59.0 / 60.0 + 59.7 / 3600.0, "N 13°00'00\" E"),
+            (359.99999, "N 0°00'00\" W"),
+        ];
+        for (azimuth, expected) in cases {
+            assert_eq!(quadrant_bearing(azimuth), expected, "{}", azimuth);
+        }
+    }
+
+    #[test]
+    fn measures_state_plane_lengths() {
+        let points = grid(&[(0.0, 0.0), (3000.0, 0.0), (3000.0, 4000.0)]);
+        assert!((length(&points, Method::StatePlane) - 7000.0).abs() < 1e-3);
+        assert!((length(&[points[0], points[2]], Method::StatePlane) - 5000.0).abs() < 1e-3);
+        assert_eq!(length(&points[..1], Method::StatePlane), 0.0);
+        assert_eq!(length(&[], Method::StatePlane), 0.0);
+    }
+
+    #[test]
+    fn measures_geodesic_lengths_near_grid() {
+        // The grid scale is within a part in ten thousand of true between the standard
+        // parallels.
+        let points = grid(&[(0.0, 0.0), (3000.0, 0.0), (3000.0, 4000.0)]);
+        let geodesic = length(&points, Method::Geodesic);
+        assert!((geodesic / 7000.0 - 1.0).abs() < 1e-3, "{}", geodesic);
+    }
+
+    #[test]
+    fn measures_areas() {
+        let square = grid(&[(0.0, 0.0), (1000.0, 0.0), (1000.0, 1000.0), (0.0, 1000.0)]);
+        assert!((area(&square, Method::StatePlane) - 1_000_000.0).abs() < 1.0);
+        let geodesic = area(&square, Method::Geodesic);
+        assert!((geodesic / 1_000_000.0 - 1.0).abs() < 2e-3, "{}", geodesic);
+        // Winding does not change the area.
+        let mut reversed = square.clone();
+        reversed.reverse();
+        assert!((area(&reversed, Method::StatePlane) - 1_000_000.0).abs() < 1.0);
+        assert_eq!(area(&square[..2], Method::StatePlane), 0.0);
+        assert_eq!(area(&square[..2], Method::Geodesic), 0.0);
+    }
+
+    #[test]
+    fn measures_grid_bearings() {
+        let cases = [
+            ((0.0, 1000.0), 0.0),
+            ((1000.0, 1000.0), 45.0),
+            ((1000.0, 0.0), 90.0),
+            ((0.0, -1000.0), 180.0),
+            ((-1000.0, 0.0), 270.0),
+            ((-1000.0, 1000.0), 315.0),
+        ];
+        for (offset, expected) in cases {
+            let points = grid(&[(0.0, 0.0), offset]);
+            let azimuth = bearing(&points[0], &points[1], Method::StatePlane);
+            assert!((0.0..360.0).contains(&azimuth), "{}", azimuth);
+            assert!(turn(azimuth, expected) < 1e-4, "{:?}: {}", offset, azimuth);
+        }
+    }
+
+    #[test]
+    fn measures_true_bearings() {
+        let point =
+            |lon: f64, lat: f64| Crs::Wgs84.point(&Crs::WebMercator, &Point2d::new(lon, lat));
+        let from = point(-123.328, 42.439);
+        let north = point(-123.328, 42.449);
+        let south = point(-123.328, 42.429);
+        for (to, expected) in [(&north, 0.0), (&south, 180.0)] {
+            let azimuth = bearing(&from, to, Method::Geodesic);
+            assert!((0.0..360.0).contains(&azimuth), "{}", azimuth);
+            assert!(turn(azimuth, expected) < 1e-6, "{}", azimuth);
+        }
+        // Away from the central meridian, grid and true north differ by the convergence angle,
+        // about 1.9° at Grants Pass.
+        let grid_north = bearing(&from, &north, Method::StatePlane);
+        assert!(turn(grid_north, 0.0) > 1.0, "{}", grid_north);
+    }
+}
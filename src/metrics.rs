@@ -0,0 +1,142 @@
+use crate::prelude::*;
+use crate::table::preview;
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use geo::{Area, Centroid, EuclideanLength, InteriorPoint};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+use tracing::info;
+
+/// Square feet in an acre.
+pub const SQUARE_FEET_PER_ACRE: f64 = 43_560.0;
+
+/// Size and shape of a parcel, measured in Oregon South state plane feet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParcelMetrics {
+    /// Position of the parcel in the dataset.
+    pub index: usize,
+    pub map_num: String,
+    pub owner: String,
+    pub square_feet: f64,
+    pub acres: f64,
+    /// Length of the outer and inner boundaries, in feet.
+    pub perimeter: f64,
+    /// Center of mass, in the coordinates of the dataset.
+    pub centroid: Point2d,
+    /// Point inside the parcel, for placing labels, in the coordinates of the dataset.
+    pub label_point: Point2d,
+    /// Polsby-Popper score, from near 0 for slivers to 1 for a circle.
+    pub compactness: f64,
+}
+
+impl ParcelMetrics {
+    /// Measure `parcel`, whose geometry is in the system `crs`.
+    pub fn new(index: usize, parcel: &Parcel, crs: &Crs) -> Self {
+        let grid = Convert::new(parcel.geo())
+            .reproject(crs, &Crs::OregonSouth)
+            .into_inner();
+        let square_feet = grid.unsigned_area();
+        let perimeter = grid
+            .iter()
+            .map(|v| {
+                v.exterior().euclidean_length()
+                    + v.interiors().iter().map(|i| i.euclidean_length()).sum::<f64>()
+            })
+            .sum::<f64>();
+        let compactness = if perimeter > 0.0 {
+            4.0 * std::f64::consts::PI * square_feet / perimeter.powi(2)
+        } else {
+            0.0
+        };
+        let back = |point: Option<geo::Point>| match point {
+            Some(p) => Crs::OregonSouth.point(crs, &Point2d::new(p.x(), p.y())),
+            None => Point2d::new(f64::NAN, f64::NAN),
+        };
+        Self {
+            index,
+            map_num: parcel.owner.id.clone(),
+            owner: parcel.owner.name.clone().unwrap_or_default(),
+            square_feet,
+            acres: square_feet / SQUARE_FEET_PER_ACRE,
+            perimeter,
+            centroid: back(grid.centroid()),
+            label_point: back(grid.interior_point()),
+            compactness,
+        }
+    }
+}
+
+impl Columnar for ParcelMetrics {
+    fn headers() -> Vec<String> {
+        [
+            "map_num",
+            "owner",
+            "square_feet",
+            "acres",
+            "perimeter",
+            "centroid_x",
+            "centroid_y",
+            "label_x",
+            "label_y",
+            "compactness",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.map_num.clone(),
+            self.owner.clone(),
+            format!("{:.0}", self.square_feet),
+            format!("{:.3}", self.acres),
+            format!("{:.1}", self.perimeter),
+            format!("{:.1}", self.centroid.x()),
+            format!("{:.1}", self.centroid.y()),
+            format!("{:.1}", self.label_point.x()),
+            format!("{:.1}", self.label_point.y()),
+            format!("{:.3}", self.compactness),
+        ]
+    }
+}
+
+/// Metrics for every parcel in a dataset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParcelReport {
+    pub records: Vec<ParcelMetrics>,
+}
+
+impl ParcelReport {
+    pub fn new(parcels: &Parcels) -> Self {
+        let records = parcels
+            .records
+            .par_iter()
+            .enumerate()
+            .map(|(i, v)| ParcelMetrics::new(i, v, &parcels.crs))
+            .collect::<Vec<ParcelMetrics>>();
+        info!("Measured {} parcels.", records.len());
+        Self { records }
+    }
+}
+
+impl Tabular<ParcelMetrics> for ParcelReport {
+    fn headers() -> Vec<String> {
+        ParcelMetrics::headers()
+    }
+
+    fn rows(&self) -> Vec<ParcelMetrics> {
+        self.records.clone()
+    }
+
+    fn row(&self, index: usize) -> Option<ParcelMetrics> {
+        self.records.get(index).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn table(&self, ui: &mut egui::Ui) {
+        preview(ui, &self.records, 100);
+    }
+}
@@ -0,0 +1,560 @@
+use crate::prelude::*;
+use flate2::{write::GzEncoder, Compression as GzLevel};
+use galileo_types::cartesian::CartesianPoint2d;
+use geo::{BooleanOps, BoundingRect, Simplify};
+use geozero::mvt::{tile, Message, Tile, ToMvt};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{Envelope, RTree, AABB};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Name of the vector tile layer holding parcels.
+pub const PARCEL_LAYER: &str = "parcels";
+/// Name of the vector tile layer holding address points.
+pub const ADDRESS_LAYER: &str = "addresses";
+
+/// Zoom range and encoding settings for a vector tile export.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct MvtOptions {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// Width of a tile in tile units.
+    pub extent: u32,
+    /// Tile units kept past each tile edge, so strokes do not break at tile seams.
+    pub buffer: u32,
+    /// Simplification tolerance in tile units.  One unit is 1/4096 of a tile at the default
+    /// extent.
+    pub tolerance: f64,
+}
+
+impl Default for MvtOptions {
+    fn default() -> Self {
+        Self {
+            min_zoom: 10,
+            max_zoom: 16,
+            extent: 4096,
+            buffer: 64,
+            tolerance: 1.0,
+        }
+    }
+}
+
+impl MvtOptions {
+    /// Set the zoom range from text such as "10-16", or "14" for a single level.
+    pub fn with_zoom(mut self, range: &str) -> Whimsy<Self> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|z| *z <= 24)
+                .ok_or_else(|| WhimsyError::Schema {
+                    field: "zoom".to_string(),
+                    message: format!("{} is not a zoom level from 0 to 24", v),
+                })
+        };
+        let (min, max) = match range.split_once('-') {
+            Some((min, max)) => (parse(min)?, parse(max)?),
+            None => (parse(range)?, parse(range)?),
+        };
+        self.min_zoom = min.min(max);
+        self.max_zoom = min.max(max);
+        Ok(self)
+    }
+}
+
+/// Features of one layer, with keys and values shared through lookup tables.
+#[derive(Debug)]
+struct LayerBuilder {
+    layer: tile::Layer,
+    key_index: HashMap<String, u32>,
+    value_index: HashMap<String, u32>,
+}
+
+impl LayerBuilder {
+    fn new(name: &str, extent: u32) -> Self {
+        Self {
+            layer: tile::Layer {
+                version: 2,
+                name: name.to_string(),
+                extent: Some(extent),
+                ..Default::default()
+            },
+            key_index: HashMap::new(),
+            value_index: HashMap::new(),
+        }
+    }
+
+    /// Add a feature whose `geometry` is already in tile units, leaving out properties with
+    /// empty values.
+    fn add(&mut self, id: usize, geometry: &geo::Geometry, properties: &[(String, String)]) {
+        let mut feature = match geometry.to_mvt_unscaled() {
+            Ok(feature) => feature,
+            Err(e) => {
+                tracing::warn!("Could not encode feature {}: {}", id, e);
+                return;
+            }
+        };
+        feature.id = Some(id as u64);
+        for (key, value) in properties.iter().filter(|(_, v)| !v.is_empty()) {
+            let keys = &mut self.layer.keys;
+            let key = *self.key_index.entry(key.clone()).or_insert_with(|| {
+                keys.push(key.clone());
+                keys.len() as u32 - 1
+            });
+            let values = &mut self.layer.values;
+            let value = *self.value_index.entry(value.clone()).or_insert_with(|| {
+                values.push(tile::Value {
+                    string_value: Some(value.clone()),
+                    ..Default::default()
+                });
+                values.len() as u32 - 1
+            });
+            feature.tags.extend([key, value]);
+        }
+        self.layer.features.push(feature);
+    }
+}
+
+/// Maps Web Mercator meters into the tile units of one tile, y pointing down.
+#[derive(Debug, Clone, Copy)]
+struct TileFrame {
+    x_min: f64,
+    y_max: f64,
+    scale: f64,
+}
+
+impl TileFrame {
+    fn new(tile: &TileId, extent: u32) -> Self {
+        let (x_min, _, x_max, y_max) = tile.bounds();
+        Self {
+            x_min,
+            y_max,
+            scale: f64::from(extent) / (x_max - x_min),
+        }
+    }
+
+    fn to_tile(&self, x: f64, y: f64) -> (i32, i32) {
+        (
+            ((x - self.x_min) * self.scale).round() as i32,
+            ((self.y_max - y) * self.scale).round() as i32,
+        )
+    }
+
+    /// Ring in tile units, without repeated points or the closing point, wound so its area has
+    /// the sign given by `exterior`.  `None` if it collapses at this zoom level.
+    fn ring(&self, ring: &geo::LineString, exterior: bool) -> Option<Vec<(i32, i32)>> {
+        let mut points: Vec<(i32, i32)> = Vec::with_capacity(ring.0.len());
+        for coord in ring.coords() {
+            let point = self.to_tile(coord.x, coord.y);
+            if points.last() != Some(&point) {
+                points.push(point);
+            }
+        }
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 3 {
+            return None;
+        }
+        // Surveyor's formula in tile space, where exterior rings must come out positive.
+        let area = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| i64::from(a.0) * i64::from(b.1) - i64::from(b.0) * i64::from(a.1))
+            .sum::<i64>();
+        if area == 0 {
+            return None;
+        }
+        if (area > 0) != exterior {
+            points.reverse();
+        }
+        Some(points)
+    }
+}
+
+/// The polygons of `shape` in tile units, or `None` if nothing survives.
+fn tile_polygons(shape: &geo::MultiPolygon, frame: &TileFrame) -> Option<geo::MultiPolygon> {
+    let line = |points: Vec<(i32, i32)>| {
+        geo::LineString::from(
+            points
+                .into_iter()
+                .map(|(x, y)| (f64::from(x), f64::from(y)))
+                .collect::<Vec<(f64, f64)>>(),
+        )
+    };
+    let polygons = shape
+        .iter()
+        .filter_map(|polygon| {
+            let exterior = frame.ring(polygon.exterior(), true)?;
+            let interiors = polygon
+                .interiors()
+                .iter()
+                .filter_map(|v| frame.ring(v, false))
+                .map(line)
+                .collect();
+            Some(geo::Polygon::new(line(exterior), interiors))
+        })
+        .collect::<Vec<geo::Polygon>>();
+    (!polygons.is_empty()).then(|| geo::MultiPolygon::new(polygons))
+}
+
+/// Parcels and address points in Web Mercator, indexed for lookup by tile.
+struct Sources {
+    parcels: Vec<(geo::MultiPolygon, Vec<(String, String)>)>,
+    parcel_tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+    addresses: Vec<([f64; 2], Vec<(String, String)>)>,
+    address_tree: RTree<GeomWithData<[f64; 2], usize>>,
+}
+
+impl Sources {
+    fn new(parcels: Option<&Parcels>, addresses: Option<&AddressPoints>) -> Self {
+        let parcels = parcels
+            .map(|v| {
+                let headers = v.attribute_headers();
+                v.records
+                    .par_iter()
+                    .enumerate()
+                    .map(|(i, parcel)| {
+                        let shape = Convert::new(parcel.geo())
+                            .reproject(&v.crs, &Crs::WebMercator)
+                            .into_inner();
+                        let values = v.attribute_values(i, headers);
+                        (shape, headers.iter().cloned().zip(values).collect())
+                    })
+                    .collect::<Vec<(geo::MultiPolygon, Vec<(String, String)>)>>()
+            })
+            .unwrap_or_default();
+        let envelopes = parcels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (shape, _))| {
+                shape.bounding_rect().map(|r| {
+                    let rect =
+                        Rectangle::from_corners([r.min().x, r.min().y], [r.max().x, r.max().y]);
+                    GeomWithData::new(rect, i)
+                })
+            })
+            .collect();
+        let addresses = addresses
+            .map(|v| {
+                v.records
+                    .iter()
+                    .map(|point| {
+                        let (x, y) = v.crs.transform(
+                            &Crs::WebMercator,
+                            point.geometry.x(),
+                            point.geometry.y(),
+                        );
+                        let properties = vec![
+                            ("label".to_string(), point.address.label.clone()),
+                            ("status".to_string(), point.address.status.clone()),
+                        ];
+                        ([x, y], properties)
+                    })
+                    .collect::<Vec<([f64; 2], Vec<(String, String)>)>>()
+            })
+            .unwrap_or_default();
+        let points = addresses
+            .iter()
+            .enumerate()
+            .map(|(i, (point, _))| GeomWithData::new(*point, i))
+            .collect();
+        Self {
+            parcels,
+            parcel_tree: RTree::bulk_load(envelopes),
+            addresses,
+            address_tree: RTree::bulk_load(points),
+        }
+    }
+
+    /// Web Mercator bounds of all features as (x_min, y_min, x_max, y_max).
+    fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        let parcels = (!self.parcels.is_empty()).then(|| self.parcel_tree.root().envelope());
+        let addresses = (!self.addresses.is_empty()).then(|| self.address_tree.root().envelope());
+        let envelope = match (parcels, addresses) {
+            (Some(a), Some(b)) => a.merged(&b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => return None,
+        };
+        let (lower, upper) = (envelope.lower(), envelope.upper());
+        Some((lower[0], lower[1], upper[0], upper[1]))
+    }
+}
+
+/// Encode one tile, or `None` if no feature falls in it.
+fn encode_tile(
+    tile: &TileId,
+    sources: &Sources,
+    shapes: &[geo::MultiPolygon],
+    options: &MvtOptions,
+) -> Option<Vec<u8>> {
+    let frame = TileFrame::new(tile, options.extent);
+    let (x_min, y_min, x_max, y_max) = tile.bounds();
+    let pad = f64::from(options.buffer) / frame.scale;
+    let clip_min = [x_min - pad, y_min - pad];
+    let clip_max = [x_max + pad, y_max + pad];
+    let envelope = AABB::from_corners(clip_min, clip_max);
+    let clip = geo::MultiPolygon::new(vec![geo::Rect::new(
+        geo::coord! { x: clip_min[0], y: clip_min[1] },
+        geo::coord! { x: clip_max[0], y: clip_max[1] },
+    )
+    .to_polygon()]);
+
+    let mut parcels = LayerBuilder::new(PARCEL_LAYER, options.extent);
+    let mut hits = sources
+        .parcel_tree
+        .locate_in_envelope_intersecting(&envelope)
+        .map(|v| v.data)
+        .collect::<Vec<usize>>();
+    // Keep the dataset order, so overlapping parcels draw the same way in every tile.
+    hits.sort_unstable();
+    for i in hits {
+        let shape = &shapes[i];
+        let inside = shape.bounding_rect().is_some_and(|r| {
+            r.min().x >= clip_min[0]
+                && r.min().y >= clip_min[1]
+                && r.max().x <= clip_max[0]
+                && r.max().y <= clip_max[1]
+        });
+        let clipped;
+        let shape = if inside {
+            shape
+        } else {
+            // Boolean operations can panic on polygons made degenerate by simplification.  Such
+            // parcels go out unclipped, which renderers accept past the tile buffer.
+            let intersection = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                shape.intersection(&clip)
+            }));
+            match intersection {
+                Ok(shape) => {
+                    clipped = shape;
+                    &clipped
+                }
+                Err(_) => {
+                    tracing::debug!("Could not clip parcel {} to tile {:?}.", i, tile);
+                    shape
+                }
+            }
+        };
+        if let Some(geometry) = tile_polygons(shape, &frame) {
+            parcels.add(i, &geo::Geometry::from(geometry), &sources.parcels[i].1);
+        }
+    }
+
+    let mut addresses = LayerBuilder::new(ADDRESS_LAYER, options.extent);
+    let mut hits = sources
+        .address_tree
+        .locate_in_envelope(&envelope)
+        .map(|v| v.data)
+        .collect::<Vec<usize>>();
+    hits.sort_unstable();
+    for i in hits {
+        let (point, properties) = &sources.addresses[i];
+        let (x, y) = frame.to_tile(point[0], point[1]);
+        let geometry = geo::Point::new(f64::from(x), f64::from(y));
+        addresses.add(i, &geo::Geometry::from(geometry), properties);
+    }
+
+    let layers = [parcels, addresses]
+        .into_iter()
+        .map(|v| v.layer)
+        .filter(|v| !v.features.is_empty())
+        .collect::<Vec<tile::Layer>>();
+    (!layers.is_empty()).then(|| Tile { layers }.encode_to_vec())
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Destination of the encoded tiles.
+enum TileWriter {
+    /// Uncompressed `z/x/y.pbf` files beside a `metadata.json`.
+    Directory(PathBuf),
+    /// An MBTiles file, with gzipped tiles as the spec requires.
+    Mbtiles(PathBuf, Connection),
+}
+
+impl TileWriter {
+    fn open(target: &TileSource) -> Whimsy<Self> {
+        match target {
+            TileSource::Directory(dir) => {
+                std::fs::create_dir_all(dir).map_err(|e| WhimsyError::io(dir, e))?;
+                Ok(Self::Directory(dir.clone()))
+            }
+            TileSource::Mbtiles(path) => {
+                let connection = Connection::open(path).map_err(|e| WhimsyError::parse(path, e))?;
+                connection
+                    .execute_batch(
+                        "CREATE TABLE IF NOT EXISTS metadata (name TEXT, value TEXT);
+                         CREATE UNIQUE INDEX IF NOT EXISTS metadata_name ON metadata (name);
+                         CREATE TABLE IF NOT EXISTS tiles (zoom_level INTEGER, \
+                         tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                         CREATE UNIQUE INDEX IF NOT EXISTS tile_index \
+                         ON tiles (zoom_level, tile_column, tile_row);",
+                    )
+                    .map_err(|e| WhimsyError::parse(path, e))?;
+                Ok(Self::Mbtiles(path.clone(), connection))
+            }
+        }
+    }
+
+    /// Write the tiles of one zoom level, replacing any already there.
+    fn write(&mut self, tiles: &[(TileId, Vec<u8>)]) -> Whimsy<()> {
+        match self {
+            Self::Directory(dir) => {
+                for (tile, data) in tiles {
+                    let column = dir.join(tile.z.to_string()).join(tile.x.to_string());
+                    std::fs::create_dir_all(&column).map_err(|e| WhimsyError::io(&column, e))?;
+                    let path = column.join(format!("{}.pbf", tile.y));
+                    std::fs::write(&path, data).map_err(|e| WhimsyError::io(&path, e))?;
+                }
+            }
+            Self::Mbtiles(path, connection) => {
+                let transaction = connection
+                    .transaction()
+                    .map_err(|e| WhimsyError::parse(&path, e))?;
+                for (tile, data) in tiles {
+                    let data = gzip(data).map_err(|e| WhimsyError::io(&path, e))?;
+                    // MBTiles number rows from the south, XYZ from the north.
+                    let row = (1u32 << tile.z) - 1 - tile.y;
+                    transaction
+                        .execute(
+                            "INSERT OR REPLACE INTO tiles \
+                             (zoom_level, tile_column, tile_row, tile_data) \
+                             VALUES (?1, ?2, ?3, ?4)",
+                            rusqlite::params![tile.z, tile.x, row, data],
+                        )
+                        .map_err(|e| WhimsyError::parse(&path, e))?;
+                }
+                transaction
+                    .commit()
+                    .map_err(|e| WhimsyError::parse(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the name, zoom range, bounds and layers, so web maps can configure the source.
+    fn finish(self, name: &str, options: &MvtOptions, bounds: (f64, f64, f64, f64)) -> Whimsy<()> {
+        let (west, south) = Crs::WebMercator.transform(&Crs::Wgs84, bounds.0, bounds.1);
+        let (east, north) = Crs::WebMercator.transform(&Crs::Wgs84, bounds.2, bounds.3);
+        let bounds = format!("{:.6},{:.6},{:.6},{:.6}", west, south, east, north);
+        let center = format!(
+            "{:.6},{:.6},{}",
+            (west + east) / 2.0,
+            (south + north) / 2.0,
+            options.min_zoom
+        );
+        let layers = [PARCEL_LAYER, ADDRESS_LAYER]
+            .iter()
+            .map(|v| {
+                json!({
+                    "id": v,
+                    "fields": {},
+                    "minzoom": options.min_zoom,
+                    "maxzoom": options.max_zoom,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+        let metadata = [
+            ("name", name.to_string()),
+            ("format", "pbf".to_string()),
+            ("type", "overlay".to_string()),
+            ("minzoom", options.min_zoom.to_string()),
+            ("maxzoom", options.max_zoom.to_string()),
+            ("bounds", bounds),
+            ("center", center),
+            ("json", json!({ "vector_layers": layers }).to_string()),
+        ];
+        match self {
+            Self::Directory(dir) => {
+                // The directory form spells out the layers instead of nesting them as text.
+                let mut fields = metadata
+                    .iter()
+                    .filter(|(k, _)| *k != "json")
+                    .map(|(k, v)| (k.to_string(), serde_json::Value::from(v.as_str())))
+                    .collect::<serde_json::Map<String, serde_json::Value>>();
+                fields.insert("vector_layers".to_string(), layers.into());
+                let path = dir.join("metadata.json");
+                let text = serde_json::to_string_pretty(&fields)
+                    .map_err(|e| WhimsyError::parse(&path, e))?;
+                std::fs::write(&path, text).map_err(|e| WhimsyError::io(&path, e))?;
+            }
+            Self::Mbtiles(path, connection) => {
+                for (key, value) in metadata {
+                    connection
+                        .execute(
+                            "INSERT OR REPLACE INTO metadata (name, value) VALUES (?1, ?2)",
+                            rusqlite::params![key, value],
+                        )
+                        .map_err(|e| WhimsyError::parse(&path, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write Mapbox Vector Tiles of `parcels` and `addresses` to `target` for the zoom range in
+/// `options`.  Parcels are simplified to the tile resolution of each zoom level and clipped to
+/// each tile plus its buffer.  Returns the number of tiles written.
+pub fn export_mvt(
+    parcels: Option<&Parcels>,
+    addresses: Option<&AddressPoints>,
+    target: &TileSource,
+    options: &MvtOptions,
+) -> Whimsy<usize> {
+    let sources = Sources::new(parcels, addresses);
+    let Some(bounds) = sources.bounds() else {
+        return Err(WhimsyError::Geometry {
+            id: None,
+            message: "no parcels or addresses to export".to_string(),
+        });
+    };
+    let mut writer = TileWriter::open(target)?;
+    let mut count = 0;
+    for z in options.min_zoom..=options.max_zoom {
+        let (x_min, _, x_max, _) = TileId { z, x: 0, y: 0 }.bounds();
+        let epsilon = options.tolerance * (x_max - x_min) / f64::from(options.extent);
+        let shapes = sources
+            .parcels
+            .par_iter()
+            .map(|(shape, _)| shape.simplify(&epsilon))
+            .collect::<Vec<geo::MultiPolygon>>();
+        let tiles = covering_tiles(bounds.0, bounds.1, bounds.2, bounds.3, z);
+        let bar = ProgressBar::new(tiles.len() as u64);
+        if let Ok(style) = ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}") {
+            bar.set_style(style);
+        }
+        bar.set_message(format!("zoom {}", z));
+        let encoded = tiles
+            .par_iter()
+            .filter_map(|tile| {
+                bar.inc(1);
+                encode_tile(tile, &sources, &shapes, options).map(|data| (*tile, data))
+            })
+            .collect::<Vec<(TileId, Vec<u8>)>>();
+        bar.finish_and_clear();
+        writer.write(&encoded)?;
+        info!("Wrote {} tiles at zoom {}.", encoded.len(), z);
+        count += encoded.len();
+    }
+    let name = target
+        .path()
+        .file_stem()
+        .and_then(|v| v.to_str())
+        .unwrap_or("whimsy")
+        .to_string();
+    writer.finish(&name, options, bounds)?;
+    Ok(count)
+}
@@ -1,19 +1,22 @@
+use crate::crs::multipolygon_bounds;
 use crate::prelude::*;
 use galileo::layer::feature_layer::Feature;
 use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
 use galileo_types::geometry::CartesianGeometry2d;
 use galileo_types::impls::{Contour, MultiPolygon};
 use geo::geometry::Geometry;
+use geo::orient::{Direction, Orient};
 use geojson::FeatureReader;
 use indicatif::ParallelProgressIterator;
 use indicatif::ProgressBar;
 use num_traits::Num;
-use polite::{FauxPas, Polite};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use polite::Polite;
+use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use shapefile::record::polygon::Polygon;
 use std::fs::File;
 use std::path::Path;
+use std::sync::OnceLock;
 use tracing::info;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -25,90 +28,89 @@ pub struct Owner {
 }
 
 impl TryFrom<shapefile::dbase::Record> for Owner {
-    type Error = FauxPas;
-    fn try_from(shp: shapefile::dbase::Record) -> Polite<Self> {
-        let mut name = None;
-        match shp.get("NAME") {
-            Some(shapefile::dbase::FieldValue::Character(Some(owner))) => {
-                name = Some(owner.to_owned());
-            }
-            Some(_) => {
-                info!("Owner name missing.");
-            }
-            None => {
-                info!("Unexpected None at name.");
-            }
-        };
-        let mut map_id = None;
-        match shp.get("MapNum") {
-            Some(shapefile::dbase::FieldValue::Character(Some(mapnum))) => {
-                map_id = Some(mapnum.to_owned());
-            }
-            Some(_) => {
-                info!("Map id missing.");
-            }
-            None => {
-                info!("Unexpected None at id.");
-            }
+    type Error = WhimsyError;
+    fn try_from(shp: shapefile::dbase::Record) -> Whimsy<Self> {
+        let (owner, _) = Owner::read(shp, &ParcelSchema::default())?;
+        Ok(owner)
+    }
+}
+
+impl Owner {
+    /// Read the owner and remaining attributes of a parcel using the field names in `schema`.
+    pub fn read(
+        shp: shapefile::dbase::Record,
+        schema: &ParcelSchema,
+    ) -> Whimsy<(Self, ParcelAttributes)> {
+        let (map_id, name, attributes) = schema.read(shp);
+        if name.is_none() {
+            info!("Owner name missing.");
         }
-        if let Some(id) = map_id {
-            Ok(Owner { name, id })
-        } else {
-            Err(FauxPas::Unknown)
+        match map_id {
+            Some(id) => Ok((Owner { name, id }, attributes)),
+            None => Err(WhimsyError::Schema {
+                field: schema.map_num.clone(),
+                message: "missing map number".to_string(),
+            }),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Parcel {
     pub owner: Owner,
+    /// Empty when absent, as in GeoJSON features read by [`Parcels::from_geojson`].
+    #[serde(default)]
+    pub attributes: ParcelAttributes,
     pub geometry: MultiPolygon<Point2d>,
     pub bounds: Rect,
     pub selected: bool,
 }
 
 impl Parcel {
-    // pub fn read_geo(parcel: &Polygon) -> Geometry {
-    //     let geo_poly: geo::MultiPolygon<f64> = parcel.clone().into();
-    //     let geo: Geometry = geo_poly.into();
-    //     geo
-    // }
-
-    //from EPSG:2270
-    // pub fn to_epsg3857(mut geo: Geometry, from: &str) -> Geometry {
-    //     geo.transform_crs_to_crs(from, "EPSG:3857").unwrap();
-    //     geo
-    // }
-
-    pub fn read_record(geo: Geometry, record: shapefile::dbase::Record) -> Polite<Self> {
-        let owner = Owner::try_from(record)?;
-        let mut multipoly = None;
-        let mut boundary = None;
-        match &geo {
-            Geometry::MultiPolygon(polys) => {
-                let (mp, rect) = Convert::new(polys.clone()).bounded_multipolygon();
-                multipoly = Some(mp);
-                boundary = Some(rect);
-            }
-            _ => {
-                info!("Not implemented.");
-            }
-        }
-        if let Some(geometry) = multipoly {
-            if let Some(bounds) = boundary {
-                let parcel = Parcel {
-                    owner,
-                    geometry,
-                    bounds,
-                    selected: false,
-                };
-                Ok(parcel)
-            } else {
-                Err(FauxPas::Unknown)
-            }
-        } else {
-            Err(FauxPas::Unknown)
+    pub fn read_geo(parcel: &Polygon) -> Geometry {
+        let geo_poly: geo::MultiPolygon<f64> = parcel.clone().into();
+        geo_poly.into()
+    }
+
+    /// The parcel geometry as `geo_types`, for use with `geo` algorithms.
+    pub fn geo(&self) -> geo::MultiPolygon {
+        Convert::new(self.geometry.clone()).geo()
+    }
+
+    pub fn read_record(geo: Geometry, record: shapefile::dbase::Record) -> Whimsy<Self> {
+        Self::read_record_with(geo, record, &ParcelSchema::default())
+    }
+
+    /// Read a parcel, taking its attributes from the fields named in `schema`.
+    pub fn read_record_with(
+        geo: Geometry,
+        record: shapefile::dbase::Record,
+        schema: &ParcelSchema,
+    ) -> Whimsy<Self> {
+        let (owner, attributes) = Owner::read(record, schema)?;
+        let Some(polys) = Convert::new(geo).multipolygon() else {
+            return Err(WhimsyError::Geometry {
+                id: Some(owner.id),
+                message: "no polygons in the geometry".to_string(),
+            });
+        };
+        if polys.0.iter().all(|v| v.exterior().0.is_empty()) {
+            return Err(WhimsyError::Geometry {
+                id: Some(owner.id),
+                message: "polygons have no vertices, so no bounds".to_string(),
+            });
         }
+        // Shapefiles wind shells clockwise, so turn them counter-clockwise with clockwise holes,
+        // as the rest of whimsy and the validator expect.
+        let polys = polys.orient(Direction::Default);
+        let (geometry, bounds) = Convert::new(polys).bounded_multipolygon();
+        Ok(Parcel {
+            owner,
+            attributes,
+            geometry,
+            bounds,
+            selected: false,
+        })
     }
 }
 
@@ -149,63 +151,227 @@ impl Feature for Parcel {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Parcels {
     pub records: Vec<Parcel>,
+    /// Coordinate system of the parcel geometries.  Web Mercator for parcels in use, which the
+    /// map, overlays and comparisons draw and match in.  Other systems only appear in copies from
+    /// [`Parcels::reprojected`] for export.
+    pub crs: Crs,
+    /// Attribute table headers, collected from the records on first use.
+    #[serde(skip)]
+    pub headers: OnceLock<Vec<String>>,
+    /// Changes whenever the records are replaced or their geometry changes.
+    #[serde(skip)]
+    pub revision: Revision,
 }
 
 impl Parcels {
-    pub fn from_geojson<P: AsRef<Path>>(path: P) -> Polite<Self> {
-        let file = File::open(path)?;
+    pub fn from_geojson<P: AsRef<Path>>(path: P) -> Whimsy<(Self, LoadReport)> {
+        let mut report = LoadReport::new(&path);
+        let file = File::open(&path).map_err(|e| WhimsyError::io(&path, e))?;
         let reader = FeatureReader::from_reader(file);
 
         let mut records = Vec::new();
-        let mut dropped = 0;
         let spinner = ProgressBar::new_spinner();
-        for parcel in reader.deserialize()? {
+        let features = reader
+            .deserialize()
+            .map_err(|e| WhimsyError::parse(&path, e))?;
+        for (i, parcel) in features.enumerate() {
             match parcel {
                 Ok(lot) => records.push(lot),
                 Err(e) => {
-                    info!("Record dropped: {}.", e.to_string());
-                    dropped += 1;
+                    let mut reason = WhimsyError::parse(&path, e);
+                    if let WhimsyError::Parse { row, .. } = &mut reason {
+                        *row = Some(i + 1);
+                    }
+                    report.skip(i + 1, reason);
                 }
             }
             spinner.tick();
         }
-        info!("Records dropped: {}.", dropped);
-
-        Ok(Parcels { records })
-    }
-
-    // pub fn from_shp<P: AsRef<Path>>(path: P, transform: Option<&str>) -> Polite<Self> {
-    //     let polygons = shapefile::read_as::<_, shapefile::Polygon, shapefile::dbase::Record>(path).unwrap();
-    //     let records = polygons
-    //         .par_iter()
-    //         .progress()
-    //         .map(|v| {
-    //             let mut geo = Parcel::read_geo(&v.0);
-    //             if let Some(crs) = transform {
-    //                 geo = Parcel::to_epsg3857(geo, crs);
-    //             }
-    //             let record = v.1.clone();
-    //             Parcel::read_record(geo, record).unwrap()
-    //         })
-    //         .collect::<Vec<Parcel>>();
-    //     Ok(Self { records })
-    // }
+        report.loaded = records.len();
+        info!("{}", report.summary());
+
+        Ok((
+            Parcels {
+                records,
+                crs: Crs::WebMercator,
+                headers: Default::default(),
+                revision: Default::default(),
+            },
+            report,
+        ))
+    }
+
+    /// Read a GeoJSON source file, or a bincode cache for any other extension.
+    pub fn read<P: AsRef<Path>>(path: P) -> Whimsy<(Self, LoadReport)> {
+        match path.as_ref().extension().and_then(|v| v.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("geojson") || ext.eq_ignore_ascii_case("json") => {
+                Self::from_geojson(path)
+            }
+            _ => {
+                let parcels = Self::load(&path)?;
+                let mut report = LoadReport::new(&path);
+                report.loaded = parcels.records.len();
+                Ok((parcels, report))
+            }
+        }
+    }
+
+    /// Read parcels from a polygon shapefile in the system `crs`, reprojecting them to Web
+    /// Mercator for display.
+    pub fn from_shp<P: AsRef<Path>>(path: P, crs: &Crs) -> Whimsy<(Self, LoadReport)> {
+        Self::from_shp_with(path, crs, &ParcelSchema::default())
+    }
+
+    /// Read parcels from a polygon shapefile in the system `crs`, with attributes from the fields
+    /// named in `schema`.
+    pub fn from_shp_with<P: AsRef<Path>>(
+        path: P,
+        crs: &Crs,
+        schema: &ParcelSchema,
+    ) -> Whimsy<(Self, LoadReport)> {
+        let mut report = LoadReport::new(&path);
+        let polygons = shapefile::read_as::<_, Polygon, shapefile::dbase::Record>(&path)
+            .map_err(|e| WhimsyError::parse(&path, e))?;
+        let results = polygons
+            .par_iter()
+            .progress()
+            .map(|(polygon, record)| {
+                let geo = Convert::new(Parcel::read_geo(polygon))
+                    .reproject(crs, &Crs::WebMercator)
+                    .into_inner();
+                Parcel::read_record_with(geo, record.clone(), schema)
+            })
+            .collect::<Vec<Whimsy<Parcel>>>();
+        let mut records = Vec::new();
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(parcel) => records.push(parcel),
+                Err(e) => report.skip(i + 1, e),
+            }
+        }
+        report.loaded = records.len();
+        info!("{}", report.summary());
+        Ok((
+            Self {
+                records,
+                crs: Crs::WebMercator,
+                headers: Default::default(),
+                revision: Default::default(),
+            },
+            report,
+        ))
+    }
+
+    /// Table headers for the owner, attributes and every extra field found in any parcel.  The
+    /// headers are collected once and kept, so set `headers` to its default after changing the
+    /// attributes of the records.
+    pub fn attribute_headers(&self) -> &[String] {
+        self.headers.get_or_init(|| {
+            let mut headers = vec!["map_num".to_string(), "owner".to_string()];
+            headers.extend(ParcelAttributes::headers());
+            let extra = self
+                .records
+                .iter()
+                .flat_map(|v| v.attributes.extra.keys())
+                .collect::<std::collections::BTreeSet<&String>>();
+            headers.extend(extra.into_iter().cloned());
+            headers
+        })
+    }
+
+    /// Values of the parcel at `index` in the order of [`Parcels::attribute_headers`].
+    pub fn attribute_values(&self, index: usize, headers: &[String]) -> Vec<String> {
+        let parcel = &self.records[index];
+        let mut values = vec![
+            parcel.owner.id.clone(),
+            parcel.owner.name.clone().unwrap_or_default(),
+        ];
+        values.extend(parcel.attributes.values());
+        let fixed = values.len();
+        values.extend(headers.iter().skip(fixed).map(|k| {
+            parcel
+                .attributes
+                .extra
+                .get(k)
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        }));
+        values
+    }
+
+    /// Transform the parcel geometries and bounds to the system `to`.
+    fn reproject(&mut self, to: &Crs) {
+        if self.crs == *to {
+            return;
+        }
+        self.revision = Default::default();
+        let from = self.crs;
+        self.records.par_iter_mut().for_each(|parcel| {
+            parcel.geometry = from.multipolygon(to, &parcel.geometry);
+            parcel.bounds = multipolygon_bounds(&parcel.geometry);
+        });
+        self.crs = *to;
+    }
 
+    /// Copy of the parcels in the system `to`, for export.
+    pub fn reprojected(&self, to: &Crs) -> Self {
+        let mut parcels = self.clone();
+        parcels.reproject(to);
+        parcels
+    }
+
+    /// Write the parcels to a versioned bincode cache.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Polite<()> {
-        info!("Serializing to binary.");
-        let encode = bincode::serialize(self)?;
-        info!("Writing to file.");
-        std::fs::write(path, encode)?;
-        Ok(())
+        write_cache(self, path)
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Polite<Self> {
-        info!("Deserializing from binary.");
-        let vec: Vec<u8> = std::fs::read(path)?;
-        let parcels: Parcels = bincode::deserialize(&vec[..])?;
+    /// Read parcels from a cache written by [`Parcels::save`], or from an unversioned cache
+    /// written before parcels recorded their CRS and attributes.  Parcels are returned in Web
+    /// Mercator whatever system the cache was written in.
+    pub fn load<P: AsRef<Path>>(path: P) -> Whimsy<Self> {
+        let mut parcels = read_cache::<Self, UnversionedParcels, P>(path)?;
+        parcels.reproject(&Crs::WebMercator);
         Ok(parcels)
     }
 }
+
+/// Parcel layout of caches written before the cache format was versioned.
+#[derive(Debug, Clone, Deserialize)]
+struct UnversionedParcel {
+    owner: Owner,
+    geometry: MultiPolygon<Point2d>,
+    bounds: Rect,
+    selected: bool,
+}
+
+/// Parcels layout of caches written before the cache format was versioned, always in Web
+/// Mercator.
+#[derive(Debug, Clone, Deserialize)]
+struct UnversionedParcels {
+    records: Vec<UnversionedParcel>,
+}
+
+impl From<UnversionedParcels> for Parcels {
+    fn from(parcels: UnversionedParcels) -> Self {
+        let records = parcels
+            .records
+            .into_iter()
+            .map(|v| Parcel {
+                owner: v.owner,
+                attributes: Default::default(),
+                geometry: v.geometry,
+                bounds: v.bounds,
+                selected: v.selected,
+            })
+            .collect();
+        Self {
+            records,
+            crs: Crs::WebMercator,
+            headers: Default::default(),
+            revision: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::abbreviations::{direction, street_type, unit_type};
+use serde::{Deserialize, Serialize};
+
+/// Components of an address label, after the NENA/FGDC address standards.  Directionals, street
+/// types and unit types hold their standard abbreviations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct AddressParts {
+    pub number: u64,
+    /// Text following the house number, such as "A" in "123A" or "1/2" in "123 1/2".
+    pub number_suffix: Option<String>,
+    pub pre_directional: Option<String>,
+    pub street_name: String,
+    pub street_type: Option<String>,
+    pub post_directional: Option<String>,
+    pub unit_type: Option<String>,
+    pub unit_id: Option<String>,
+}
+
+impl AddressParts {
+    /// House number with its suffix, such as "123A" or "123 1/2".
+    pub fn house_number(&self) -> String {
+        let mut number = self.number.to_string();
+        if let Some(value) = &self.number_suffix {
+            if value.contains('/') {
+                number.push(' ');
+            }
+            number.push_str(value);
+        }
+        number
+    }
+
+    /// Recompose the label from its standardized components.
+    pub fn label(&self) -> String {
+        let mut label = self.house_number();
+        label.push(' ');
+        label.push_str(&self.street());
+        if let Some(unit_id) = &self.unit_id {
+            match self.unit_type.as_deref() {
+                Some("#") => label.push_str(" #"),
+                Some(value) => {
+                    label.push(' ');
+                    label.push_str(value);
+                    label.push(' ');
+                }
+                None => label.push(' '),
+            }
+            label.push_str(unit_id);
+        }
+        label
+    }
+
+    /// Street name with directionals and type, without the house number or unit.
+    pub fn street(&self) -> String {
+        [
+            &self.pre_directional,
+            &Some(self.street_name.clone()),
+            &self.street_type,
+            &self.post_directional,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(" ")
+    }
+
+    pub fn headers() -> Vec<String> {
+        [
+            "number",
+            "number_suffix",
+            "pre_directional",
+            "street_name",
+            "street_type",
+            "post_directional",
+            "unit_type",
+            "unit_id",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        vec![
+            self.number.to_string(),
+            text(&self.number_suffix),
+            text(&self.pre_directional),
+            self.street_name.clone(),
+            text(&self.street_type),
+            text(&self.post_directional),
+            text(&self.unit_type),
+            text(&self.unit_id),
+        ]
+    }
+}
+
+/// Reason an address label could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    Empty,
+    /// The label does not begin with a house number.
+    MissingNumber(String),
+    /// The house number is too large to store.
+    NumberTooLarge(String),
+    /// No street name remains after removing the other components.
+    MissingStreet(String),
+    /// A unit designator is not followed by a unit id.
+    MissingUnit(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty address label."),
+            Self::MissingNumber(label) => write!(f, "No house number in {}.", label),
+            Self::NumberTooLarge(label) => write!(f, "House number out of range in {}.", label),
+            Self::MissingStreet(label) => write!(f, "No street name in {}.", label),
+            Self::MissingUnit(label) => write!(f, "Unit designator without an id in {}.", label),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Split an address label into its components.
+pub fn parse(label: &str) -> Result<AddressParts, ParseError> {
+    let mut tokens = label
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.trim_end_matches('.').to_uppercase())
+        .collect::<Vec<String>>();
+    if tokens.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let mut parts = AddressParts::default();
+
+    // House number, with any suffix attached ("123A", "123-B") or following ("123 1/2").
+    let first = tokens.remove(0);
+    let digits = first
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    if digits.is_empty() {
+        return Err(ParseError::MissingNumber(label.to_string()));
+    }
+    parts.number = match digits.parse() {
+        Ok(value) => value,
+        Err(_) => return Err(ParseError::NumberTooLarge(label.to_string())),
+    };
+    let suffix = first[digits.len()..].trim_start_matches('-');
+    if !suffix.is_empty() {
+        parts.number_suffix = Some(suffix.to_string());
+    } else if tokens.first().map(|v| is_fraction(v)).unwrap_or(false) {
+        parts.number_suffix = Some(tokens.remove(0));
+    }
+
+    // Unit, searching from the end for a designator ("APT 4", "STE B", "#12").
+    if let Some(position) = tokens
+        .iter()
+        .rposition(|v| unit_type(v).is_some() || (v.starts_with('#') && v.len() > 1))
+    {
+        if position > 0 {
+            let unit = tokens.split_off(position);
+            match unit_type(&unit[0]) {
+                Some(designator) => {
+                    if unit.len() < 2 {
+                        return Err(ParseError::MissingUnit(label.to_string()));
+                    }
+                    parts.unit_type = Some(designator.to_string());
+                    parts.unit_id = Some(unit[1..].join(" "));
+                }
+                None => {
+                    parts.unit_type = Some("#".to_string());
+                    parts.unit_id = Some(unit.join(" ").trim_start_matches('#').to_string());
+                }
+            }
+        }
+    }
+
+    // Pre-directional, unless it is the whole street name, as in "100 E ST".
+    if tokens.len() > 1 {
+        if let Some(value) = direction(&tokens[0]) {
+            let rest = &tokens[1..];
+            if rest.len() > 1 || street_type(&rest[0]).is_none() {
+                parts.pre_directional = Some(value.to_string());
+                tokens.remove(0);
+            }
+        }
+    }
+
+    // Post-directional, when a name and type precede it.
+    if tokens.len() > 2 {
+        if let Some(value) = direction(&tokens[tokens.len() - 1]) {
+            if street_type(&tokens[tokens.len() - 2]).is_some() {
+                parts.post_directional = Some(value.to_string());
+                tokens.pop();
+            }
+        }
+    }
+
+    // Street type, when a name precedes it.  Route numbers keep their type in the name, as in
+    // "HWY 199".
+    if tokens.len() > 1 {
+        if let Some(value) = street_type(&tokens[tokens.len() - 1]) {
+            parts.street_type = Some(value.to_string());
+            tokens.pop();
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(ParseError::MissingStreet(label.to_string()));
+    }
+    parts.street_name = tokens.join(" ");
+    Ok(parts)
+}
+
+fn is_fraction(token: &str) -> bool {
+    let mut split = token.split('/');
+    matches!(
+        (split.next(), split.next(), split.next()),
+        (Some(a), Some(b), None) if !a.is_empty() && !b.is_empty()
+            && a.chars().all(|c| c.is_ascii_digit())
+            && b.chars().all(|c| c.is_ascii_digit())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{Address, Addresses};
+
+    #[test]
+    fn parses_components() {
+        // Label, then number, suffix, pre-directional, name, type, post-directional, unit type
+        // and unit id, with "" for a missing component.
+        let cases: &[(&str, [&str; 8])] = &[
+            ("100 Main St", ["100", "", "", "MAIN", "ST", "", "", ""]),
+            ("742 evergreen terrace, apt 2", ["742", "", "", "EVERGREEN", "TER", "", "APT", "2"]),
+            ("1234 NE Foothill Bl", ["1234", "", "NE", "FOOTHILL", "BLVD", "", "", ""]),
+            ("850 SW Rogue River Hy", ["850", "", "SW", "ROGUE RIVER", "HWY", "", "", ""]),
+            ("410 Oak Crt", ["410", "", "", "OAK", "CT", "", "", ""]),
+            ("77 Ridge Terr", ["77", "", "", "RIDGE", "TER", "", "", ""]),
+            ("123 1/2 N 6th St", ["123", "1/2", "N", "6TH", "ST", "", "", ""]),
+            ("123-B Main St", ["123", "B", "", "MAIN", "ST", "", "", ""]),
+            ("123A Main St", ["123", "A", "", "MAIN", "ST", "", "", ""]),
+            ("2300 Williams Hwy N", ["2300", "", "", "WILLIAMS", "HWY", "N", "", ""]),
+            ("100 E St", ["100", "", "", "E", "ST", "", "", ""]),
+            ("1500 Hwy 199", ["1500", "", "", "HWY 199", "", "", "", ""]),
+            ("500 Union Ave Apt 4", ["500", "", "", "UNION", "AVE", "", "APT", "4"]),
+            ("200 NW F St #12", ["200", "", "NW", "F", "ST", "", "#", "12"]),
+            ("900 Parkdale Dr Sp 5", ["900", "", "", "PARKDALE", "DR", "", "SPC", "5"]),
+            ("31 Elm St Suite B 2", ["31", "", "", "ELM", "ST", "", "STE", "B 2"]),
+        ];
+        for (label, expected) in cases {
+            let parts = parse(label).unwrap_or_else(|e| panic!("{}: {}", label, e));
+            assert_eq!(parts.values(), expected.to_vec(), "{}", label);
+            assert_eq!(parse(&parts.label()).as_ref(), Ok(&parts), "{}", label);
+        }
+    }
+
+    #[test]
+    fn reports_failures() {
+        let long = "99999999999999999999999 Main St";
+        let cases = [
+            ("", ParseError::Empty),
+            (" , ", ParseError::Empty),
+            ("Main St", ParseError::MissingNumber("Main St".to_string())),
+            ("A12 Main St", ParseError::MissingNumber("A12 Main St".to_string())),
+            ("123", ParseError::MissingStreet("123".to_string())),
+            ("123 Main St Apt", ParseError::MissingUnit("123 Main St Apt".to_string())),
+            (long, ParseError::NumberTooLarge(long.to_string())),
+        ];
+        for (label, expected) in cases {
+            assert_eq!(parse(label), Err(expected), "{:?}", label);
+        }
+    }
+
+    #[test]
+    fn lists_parse_failures() {
+        let address = |label: &str| Address {
+            label: label.to_string(),
+            status: "Current".to_string(),
+            lat: 0.0,
+            lon: 0.0,
+            x: 0.0,
+            y: 0.0,
+            parsed: Default::default(),
+        };
+        let addresses = Addresses {
+            records: vec![address("100 Main St"), address("Main St"), address("123")],
+        };
+        let failures = addresses
+            .parse_failures()
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        assert_eq!(failures, vec![1, 2]);
+    }
+}
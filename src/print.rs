@@ -0,0 +1,1154 @@
+use crate::crs::{FEET_PER_MILE, FOOT};
+use crate::prelude::*;
+use crate::state::headless::save_png;
+use egui::epaint::Mesh;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Shape, Stroke, Ui, Vec2};
+use flate2::{write::ZlibEncoder, Compression as ZlibLevel};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use chrono::Datelike;
+use geo::TriangulateEarcut;
+use pdf_writer::{Filter, Name, Pdf, Ref, TextStr};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+/// Page margin in points.
+const MARGIN: f32 = 36.0;
+/// Height of the title band above the map, in points.
+const TITLE_BAND: f32 = 40.0;
+/// Height of the band below the map holding the legend, scale bar, north arrow and date.
+const FOOTER_BAND: f32 = 96.0;
+/// Height of one legend entry, in points.
+const LEGEND_ROW: f32 = 14.0;
+/// Width of one legend column, in points.
+const LEGEND_COLUMN: f32 = 150.0;
+/// Category entries listed per layer before the rest are counted.
+const LEGEND_CATEGORIES: usize = 8;
+/// Meters in one printer's point, 1/72 of an inch.
+const METERS_PER_POINT: f64 = 0.0254 / 72.0;
+
+/// Paper size of a printed map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PageSize {
+    #[default]
+    Letter,
+    Tabloid,
+}
+
+impl PageSize {
+    /// Width and height of the page in portrait, in inches.
+    pub fn inches(&self) -> (f32, f32) {
+        match self {
+            Self::Letter => (8.5, 11.0),
+            Self::Tabloid => (11.0, 17.0),
+        }
+    }
+}
+
+impl std::fmt::Display for PageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::str::FromStr for PageSize {
+    type Err = WhimsyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "letter" => Ok(Self::Letter),
+            "tabloid" | "ledger" => Ok(Self::Tabloid),
+            _ => Err(WhimsyError::Schema {
+                field: "page".to_string(),
+                message: format!("{} is not letter or tabloid", s),
+            }),
+        }
+    }
+}
+
+/// Page settings and marginal text of a printed map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintLayout {
+    pub title: String,
+    pub page: PageSize,
+    pub landscape: bool,
+    /// Resolution of PNG output.  PDF output is vector and ignores it.
+    pub dpi: u32,
+    pub date: String,
+}
+
+impl Default for PrintLayout {
+    fn default() -> Self {
+        Self {
+            title: "City of Grants Pass".to_string(),
+            page: Default::default(),
+            landscape: true,
+            dpi: 300,
+            date: today(),
+        }
+    }
+}
+
+impl PrintLayout {
+    /// Width and height of the page in points.
+    pub fn size(&self) -> Vec2 {
+        let (width, height) = self.page.inches();
+        let size = Vec2::new(width, height) * 72.0;
+        if self.landscape {
+            Vec2::new(size.y, size.x)
+        } else {
+            size
+        }
+    }
+
+    /// Width and height of PNG output in pixels.
+    pub fn pixels(&self) -> (u32, u32) {
+        let size = self.size() * self.dpi as f32 / 72.0;
+        (size.x.round() as u32, size.y.round() as u32)
+    }
+}
+
+/// Today's local date as YYYY-MM-DD.
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// Something drawn on the page, in points from the top left corner.
+#[derive(Debug, Clone)]
+pub enum Mark {
+    /// Basemap tiles under the map frame, with their opacity.
+    Basemap(Basemap, f32),
+    /// A polygon whose first ring is the exterior and the rest holes.
+    Polygon {
+        rings: Vec<Vec<Pos2>>,
+        fill: Option<Color32>,
+        stroke: Option<Stroke>,
+    },
+    /// An open line through `points`.
+    Line {
+        points: Vec<Pos2>,
+        stroke: Stroke,
+    },
+    Circle {
+        center: Pos2,
+        radius: f32,
+        fill: Color32,
+        stroke: Option<Stroke>,
+    },
+    Rect {
+        rect: Rect,
+        fill: Option<Color32>,
+        stroke: Option<Stroke>,
+    },
+    Text {
+        pos: Pos2,
+        align: Align2,
+        text: String,
+        size: f32,
+        color: Color32,
+        bold: bool,
+        halo: Option<Color32>,
+    },
+    /// Clip the marks that follow to a rectangle, or stop clipping.
+    Clip(Option<Rect>),
+}
+
+/// Approximate size of `text` in points, from the average glyph width of Helvetica.
+fn text_size(text: &str, size: f32) -> Vec2 {
+    Vec2::new(0.55 * size * text.chars().count() as f32, size)
+}
+
+/// A laid out page, ready to write as PDF or PNG.
+#[derive(Debug, Clone)]
+pub struct Sheet {
+    pub layout: PrintLayout,
+    /// The map as drawn on the page, with `screen` set to the map frame.
+    pub map: MapView,
+    pub marks: Vec<Mark>,
+}
+
+impl Sheet {
+    /// Lay out the area shown in `view` on a page, with the visible layers of `layers` drawn in
+    /// their styles on a light background.
+    pub fn new(
+        layout: &PrintLayout,
+        view: &MapView,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+        layers: &Layers,
+    ) -> Self {
+        let size = layout.size();
+        let frame = Rect::from_min_max(
+            Pos2::new(MARGIN, MARGIN + TITLE_BAND),
+            Pos2::new(size.x - MARGIN, size.y - MARGIN - FOOTER_BAND),
+        );
+        let extent = view.extent();
+        let resolution = ((extent.x_max() - extent.x_min()) / frame.width() as f64)
+            .max((extent.y_max() - extent.y_min()) / frame.height() as f64);
+        let map = MapView {
+            center: view.center,
+            resolution,
+            screen: frame,
+            pan: false,
+        };
+        let palette = Appearance {
+            mode: ThemeMode::Light,
+            ..Default::default()
+        }
+        .palette();
+        let mut sheet = Self {
+            layout: layout.clone(),
+            map,
+            marks: Vec::new(),
+        };
+        sheet.marks.push(Mark::Clip(Some(frame)));
+        sheet.marks.push(Mark::Rect {
+            rect: frame,
+            fill: Some(palette.background),
+            stroke: None,
+        });
+        if let Some(basemap) = layers.active_basemap() {
+            sheet
+                .marks
+                .push(Mark::Basemap(basemap.clone(), layers.basemap_opacity));
+        }
+        for layer in layers.layers.iter().filter(|v| v.visible) {
+            match (layer.kind, parcels, addresses) {
+                (LayerKind::Parcels, Some(data), _) => sheet.parcels(data, layer, &palette),
+                (LayerKind::Addresses, _, Some(data)) => sheet.addresses(data, layer, &palette),
+                _ => {}
+            }
+        }
+        let mut placer = LabelPlacer::default();
+        for layer in layers
+            .layers
+            .iter()
+            .rev()
+            .filter(|v| v.visible && v.labels.visible && resolution <= v.labels.max_resolution)
+        {
+            sheet.labels(layer, &mut placer, &palette);
+        }
+        sheet.marks.push(Mark::Clip(None));
+        sheet.marks.push(Mark::Rect {
+            rect: frame,
+            fill: None,
+            stroke: Some(Stroke::new(1.0, Color32::BLACK)),
+        });
+        sheet.title();
+        sheet.legend(layers, &palette);
+        sheet.scale_bar();
+        sheet.north_arrow();
+        sheet
+    }
+
+    fn to_page(&self, points: &[Point2d]) -> Vec<Pos2> {
+        points.iter().map(|v| self.map.point_to_screen(v)).collect()
+    }
+
+    fn parcels(&mut self, data: &Parcels, layer: &Layer, palette: &MapPalette) {
+        let extent = self.map.extent();
+        let stroke = Stroke::new(layer.style.stroke_width, layer.stroke(palette));
+        for (i, parcel) in data
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| rect_intersects(&v.bounds, &extent))
+        {
+            let fill = Some(layer.feature_fill(i, palette)).filter(|v| v.a() > 0);
+            for poly in &parcel.geometry.parts {
+                let mut rings = vec![self.to_page(&poly.outer_contour.points)];
+                rings.extend(poly.inner_contours.iter().map(|v| self.to_page(&v.points)));
+                self.marks.push(Mark::Polygon {
+                    rings,
+                    fill,
+                    stroke: Some(stroke),
+                });
+            }
+        }
+    }
+
+    fn addresses(&mut self, data: &AddressPoints, layer: &Layer, palette: &MapPalette) {
+        let extent = self.map.extent();
+        let stroke = layer
+            .style
+            .stroke
+            .map(|_| Stroke::new(layer.style.stroke_width, layer.stroke(palette)));
+        for (i, point) in data
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| extent.contains(&v.geometry))
+        {
+            self.marks.push(Mark::Circle {
+                center: self.map.point_to_screen(&point.geometry),
+                radius: layer.style.size,
+                fill: layer.feature_fill(i, palette),
+                stroke,
+            });
+        }
+    }
+
+    /// Place the labels of `layer` greedily, as on screen, using estimated text sizes so the
+    /// PDF and PNG output agree.
+    fn labels(&mut self, layer: &Layer, placer: &mut LabelPlacer, palette: &MapPalette) {
+        let extent = self.map.extent();
+        let style = &layer.labels;
+        let color = style.color.unwrap_or(palette.text);
+        let halo = style.halo.then_some(palette.background);
+        let candidates = match layer.kind {
+            LayerKind::Parcels => &AREA_CANDIDATES[..],
+            LayerKind::Addresses => &POINT_CANDIDATES[..],
+        };
+        for (anchor, text) in layer.anchors.iter().flatten() {
+            if !extent.contains(anchor) {
+                continue;
+            }
+            let pos = self.map.point_to_screen(anchor);
+            let size = text_size(text, style.size);
+            let placed = candidates.iter().find_map(|(align, offset)| {
+                let rect = align.anchor_size(pos + *offset, size);
+                (self.map.screen.contains_rect(rect) && placer.claim(rect.expand(1.0)))
+                    .then_some(rect)
+            });
+            if let Some(rect) = placed {
+                self.marks.push(Mark::Text {
+                    pos: rect.min,
+                    align: Align2::LEFT_TOP,
+                    text: text.clone(),
+                    size: style.size,
+                    color,
+                    bold: false,
+                    halo,
+                });
+            }
+        }
+    }
+
+    fn title(&mut self) {
+        let size = self.layout.size();
+        self.marks.push(Mark::Text {
+            pos: Pos2::new(size.x / 2.0, MARGIN + TITLE_BAND / 2.0),
+            align: Align2::CENTER_CENTER,
+            text: self.layout.title.clone(),
+            size: 20.0,
+            color: Color32::BLACK,
+            bold: true,
+            halo: None,
+        });
+    }
+
+    /// Swatches and names of the visible layers, top layer first, with the colors of any
+    /// category field.  Entries fill columns left to right and stop when the space runs out.
+    fn legend(&mut self, layers: &Layers, palette: &MapPalette) {
+        let top = self.map.screen.bottom() + 12.0;
+        let bottom = self.layout.size().y - MARGIN;
+        let right = self.map.screen.center().x - 80.0;
+        let mut entries = Vec::new();
+        for layer in layers.layers.iter().rev().filter(|v| v.visible) {
+            entries.push((
+                Some(layer.fill(palette)),
+                layer,
+                layer.kind.to_string(),
+                true,
+            ));
+            if let Some(field) = &layer.category {
+                for (value, _) in layer.categories.iter().take(LEGEND_CATEGORIES) {
+                    let color = layer.category_fill(Some(value), palette);
+                    entries.push((Some(color), layer, value.clone(), false));
+                }
+                let more = layer.categories.len().saturating_sub(LEGEND_CATEGORIES);
+                if more > 0 {
+                    entries.push((
+                        None,
+                        layer,
+                        format!("{} more {} values", more, field),
+                        false,
+                    ));
+                }
+            }
+        }
+        self.marks.push(Mark::Text {
+            pos: Pos2::new(MARGIN, top),
+            align: Align2::LEFT_TOP,
+            text: "Legend".to_string(),
+            size: 11.0,
+            color: Color32::BLACK,
+            bold: true,
+            halo: None,
+        });
+        let mut pos = Pos2::new(MARGIN, top + LEGEND_ROW + 2.0);
+        for (fill, layer, name, heading) in entries {
+            if pos.y + LEGEND_ROW > bottom {
+                pos = Pos2::new(pos.x + LEGEND_COLUMN, top + LEGEND_ROW + 2.0);
+            }
+            if pos.x + LEGEND_COLUMN > right {
+                break;
+            }
+            let swatch = Rect::from_min_size(pos + Vec2::new(0.0, 2.0), Vec2::new(16.0, 9.0));
+            let stroke = Stroke::new(layer.style.stroke_width.min(1.5), layer.stroke(palette));
+            match (fill, layer.kind) {
+                (None, _) => {}
+                (Some(fill), LayerKind::Parcels) => self.marks.push(Mark::Rect {
+                    rect: swatch,
+                    fill: Some(fill),
+                    stroke: Some(stroke),
+                }),
+                (Some(fill), LayerKind::Addresses) => self.marks.push(Mark::Circle {
+                    center: swatch.center(),
+                    radius: layer.style.size.min(4.5),
+                    fill,
+                    stroke: layer.style.stroke.map(|_| stroke),
+                }),
+            }
+            let indent = if heading { 22.0 } else { 30.0 };
+            self.marks.push(Mark::Text {
+                pos: pos + Vec2::new(indent, 6.5),
+                align: Align2::LEFT_CENTER,
+                text: name,
+                size: if heading { 9.0 } else { 8.0 },
+                color: Color32::BLACK,
+                bold: heading,
+                halo: None,
+            });
+            pos.y += LEGEND_ROW;
+        }
+    }
+
+    /// Ground distance per point at the map centre, correcting Web Mercator for latitude.
+    fn ground_resolution(&self) -> f64 {
+        let (_, lat) =
+            Crs::WebMercator.transform(&Crs::Wgs84, self.map.center.x(), self.map.center.y());
+        self.map.resolution * lat.to_radians().cos()
+    }
+
+    /// A scale bar of about two inches in feet or miles, with the representative fraction.
+    fn scale_bar(&mut self) {
+        let ground = self.ground_resolution();
+        let target = 144.0 * ground / FOOT;
+        let (unit, feet_per_unit) = if target >= FEET_PER_MILE {
+            ("mi", FEET_PER_MILE)
+        } else {
+            ("ft", 1.0)
+        };
+        let value = nice_number(target / feet_per_unit);
+        let length = (value * feet_per_unit * FOOT / ground) as f32;
+        let left = Pos2::new(
+            self.map.screen.center().x - length / 2.0,
+            self.map.screen.bottom() + 30.0,
+        );
+        for i in 0..4 {
+            let rect = Rect::from_min_size(
+                left + Vec2::new(length * i as f32 / 4.0, 0.0),
+                Vec2::new(length / 4.0, 6.0),
+            );
+            let fill = if i % 2 == 0 {
+                Color32::BLACK
+            } else {
+                Color32::WHITE
+            };
+            self.marks.push(Mark::Rect {
+                rect,
+                fill: Some(fill),
+                stroke: Some(Stroke::new(0.75, Color32::BLACK)),
+            });
+        }
+        // Ticks above the ends and divisions of the bar.
+        for i in 0..=4 {
+            let x = left.x + length * i as f32 / 4.0;
+            self.marks.push(Mark::Line {
+                points: vec![Pos2::new(x, left.y - 3.0), Pos2::new(x, left.y)],
+                stroke: Stroke::new(0.75, Color32::BLACK),
+            });
+        }
+        let label = |pos: Pos2, text: String, align: Align2| Mark::Text {
+            pos,
+            align,
+            text,
+            size: 8.0,
+            color: Color32::BLACK,
+            bold: false,
+            halo: None,
+        };
+        self.marks.push(label(
+            left + Vec2::new(0.0, 9.0),
+            "0".to_string(),
+            Align2::CENTER_TOP,
+        ));
+        self.marks.push(label(
+            left + Vec2::new(length, 9.0),
+            format!("{} {}", value, unit),
+            Align2::CENTER_TOP,
+        ));
+        self.marks.push(label(
+            left + Vec2::new(length / 2.0, 24.0),
+            format!("Scale 1:{:.0}", ground / METERS_PER_POINT),
+            Align2::CENTER_TOP,
+        ));
+    }
+
+    /// North arrow and print date in the lower right.  Web Mercator north is straight up.
+    fn north_arrow(&mut self) {
+        let size = self.layout.size();
+        let tip = Pos2::new(size.x - MARGIN - 20.0, self.map.screen.bottom() + 22.0);
+        let black = Color32::BLACK;
+        self.marks.push(Mark::Polygon {
+            rings: vec![vec![
+                tip,
+                tip + Vec2::new(9.0, 30.0),
+                tip + Vec2::new(0.0, 22.0),
+                tip + Vec2::new(-9.0, 30.0),
+            ]],
+            fill: Some(black),
+            stroke: Some(Stroke::new(0.75, black)),
+        });
+        self.marks.push(Mark::Text {
+            pos: tip - Vec2::new(0.0, 3.0),
+            align: Align2::CENTER_BOTTOM,
+            text: "N".to_string(),
+            size: 12.0,
+            color: black,
+            bold: true,
+            halo: None,
+        });
+        self.marks.push(Mark::Text {
+            pos: Pos2::new(size.x - MARGIN, size.y - MARGIN),
+            align: Align2::RIGHT_BOTTOM,
+            text: self.layout.date.clone(),
+            size: 8.0,
+            color: black,
+            bold: false,
+            halo: None,
+        });
+    }
+
+    /// Encode the page as a single page PDF with vector linework, the standard Helvetica fonts
+    /// and the basemap tiles as images.
+    pub fn to_pdf(&self) -> std::io::Result<Vec<u8>> {
+        let size = self.layout.size();
+        let mut content = PdfContent {
+            ops: String::new(),
+            height: size.y,
+            map: self.map,
+            fills: BTreeSet::new(),
+            strokes: BTreeSet::new(),
+            tiles: TileCache::default(),
+            images: Vec::new(),
+        };
+        for mark in &self.marks {
+            content.mark(mark);
+        }
+
+        let mut next = Ref::new(1);
+        let catalog = next.bump();
+        let tree = next.bump();
+        let page = next.bump();
+        let contents = next.bump();
+        let info = next.bump();
+        let fonts = [("F1", "Helvetica"), ("F2", "Helvetica-Bold")].map(|(name, font)| {
+            (next.bump(), name, font)
+        });
+        let states = content
+            .fills
+            .iter()
+            .map(|a| (format!("F{}", a), *a, false))
+            .chain(content.strokes.iter().map(|a| (format!("S{}", a), *a, true)))
+            .map(|(name, a, stroke)| (next.bump(), name, f32::from(a) / 255.0, stroke))
+            .collect::<Vec<(Ref, String, f32, bool)>>();
+        let images = content
+            .images
+            .iter()
+            .map(|image| (next.bump(), image.alpha.as_ref().map(|_| next.bump()), image))
+            .collect::<Vec<(Ref, Option<Ref>, &PdfImage)>>();
+
+        let mut pdf = Pdf::new();
+        pdf.catalog(catalog).pages(tree);
+        pdf.pages(tree).kids([page]).count(1);
+        {
+            let mut page = pdf.page(page);
+            page.media_box(pdf_writer::Rect::new(0.0, 0.0, size.x, size.y));
+            page.parent(tree);
+            page.contents(contents);
+            let mut resources = page.resources();
+            // Each writer finishes its dictionary when dropped, before the next begins.
+            {
+                let mut dict = resources.fonts();
+                for (id, name, _) in &fonts {
+                    dict.pair(Name(name.as_bytes()), *id);
+                }
+            }
+            {
+                let mut dict = resources.ext_g_states();
+                for (id, name, ..) in &states {
+                    dict.pair(Name(name.as_bytes()), *id);
+                }
+            }
+            let mut dict = resources.x_objects();
+            for (i, (id, ..)) in images.iter().enumerate() {
+                dict.pair(Name(PdfImage::name(i).as_bytes()), *id);
+            }
+        }
+        pdf.stream(contents, &deflate(content.ops.as_bytes())?)
+            .filter(Filter::FlateDecode);
+        for (id, _, font) in fonts {
+            pdf.type1_font(id)
+                .base_font(Name(font.as_bytes()))
+                .encoding_predefined(Name(b"WinAnsiEncoding"));
+        }
+        for (id, _, alpha, stroke) in states {
+            let mut state = pdf.ext_graphics(id);
+            if stroke {
+                state.stroking_alpha(alpha);
+            } else {
+                state.non_stroking_alpha(alpha);
+            }
+        }
+        for (id, mask, image) in images {
+            let data = deflate(&image.rgb)?;
+            {
+                let mut xobject = pdf.image_xobject(id, &data);
+                xobject
+                    .filter(Filter::FlateDecode)
+                    .width(image.width as i32)
+                    .height(image.height as i32)
+                    .bits_per_component(8);
+                xobject.color_space().device_rgb();
+                if let Some(mask) = mask {
+                    xobject.s_mask(mask);
+                }
+            }
+            if let (Some(mask), Some(alpha)) = (mask, &image.alpha) {
+                let data = deflate(alpha)?;
+                let mut xobject = pdf.image_xobject(mask, &data);
+                xobject
+                    .filter(Filter::FlateDecode)
+                    .width(image.width as i32)
+                    .height(image.height as i32)
+                    .bits_per_component(8);
+                xobject.color_space().device_gray();
+            }
+        }
+        {
+            let mut document = pdf.document_info(info);
+            document
+                .title(TextStr(&self.layout.title))
+                .producer(TextStr("Whimsy"));
+            if let Ok(date) = chrono::NaiveDate::parse_from_str(&self.layout.date, "%Y-%m-%d") {
+                document.creation_date(
+                    pdf_writer::Date::new(date.year() as u16)
+                        .month(date.month() as u8)
+                        .day(date.day() as u8),
+                );
+            }
+        }
+        Ok(pdf.finish())
+    }
+
+    /// Draw the page with egui, one point to a PDF point, loading basemap tiles into `tiles`.
+    pub fn paint(&self, painter: &Painter, tiles: &mut TileCache) {
+        let page = Rect::from_min_size(Pos2::ZERO, self.layout.size());
+        painter.rect_filled(page, 0.0, Color32::WHITE);
+        let mut clipped = painter.clone();
+        for mark in &self.marks {
+            match mark {
+                Mark::Clip(rect) => clipped = painter.with_clip_rect(rect.unwrap_or(page)),
+                Mark::Basemap(basemap, opacity) => {
+                    tiles.draw_now(&clipped, &self.map, basemap, *opacity);
+                }
+                Mark::Polygon {
+                    rings,
+                    fill,
+                    stroke,
+                } => {
+                    if let Some(fill) = fill {
+                        clipped.add(fill_mesh(rings, *fill));
+                    }
+                    if let Some(stroke) = stroke {
+                        for ring in rings {
+                            clipped.add(Shape::closed_line(ring.clone(), *stroke));
+                        }
+                    }
+                }
+                Mark::Line { points, stroke } => {
+                    clipped.add(Shape::line(points.clone(), *stroke));
+                }
+                Mark::Circle {
+                    center,
+                    radius,
+                    fill,
+                    stroke,
+                } => {
+                    clipped.circle(*center, *radius, *fill, stroke.unwrap_or(Stroke::NONE));
+                }
+                Mark::Rect { rect, fill, stroke } => {
+                    clipped.rect(
+                        *rect,
+                        0.0,
+                        fill.unwrap_or(Color32::TRANSPARENT),
+                        stroke.unwrap_or(Stroke::NONE),
+                    );
+                }
+                Mark::Text {
+                    pos,
+                    align,
+                    text,
+                    size,
+                    color,
+                    halo,
+                    ..
+                } => {
+                    let font = FontId::proportional(*size);
+                    if let Some(halo) = halo {
+                        for d in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+                            clipped.text(*pos + d * 0.75, *align, text, font.clone(), *halo);
+                        }
+                    }
+                    clipped.text(*pos, *align, text, font, *color);
+                }
+            }
+        }
+    }
+
+    /// Render the page offscreen at the layout DPI, without a window.
+    pub async fn to_png(&self) -> Whimsy<image::RgbaImage> {
+        let (width, height) = self.layout.pixels();
+        let mut headless = Headless::new(width, height).await?;
+        let limit = headless.device.limits().max_texture_dimension_2d;
+        if width > limit || height > limit {
+            return Err(WhimsyError::Schema {
+                field: "dpi".to_string(),
+                message: format!(
+                    "{} x {} pixels is larger than the {} pixel limit of the graphics adapter; \
+                     lower the DPI",
+                    width, height, limit
+                ),
+            });
+        }
+        headless.pixels_per_point = self.layout.dpi as f32 / 72.0;
+        let mut tiles = TileCache::default();
+        Ok(headless.render(2, |ctx| {
+            let painter = ctx.layer_painter(egui::LayerId::background());
+            self.paint(&painter, &mut tiles);
+        }))
+    }
+
+    /// Write the page to `path` as PDF or PNG, by extension.
+    pub async fn save<P: AsRef<Path>>(&self, path: P) -> Whimsy<()> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|v| v.to_str())
+            .map(|v| v.to_lowercase());
+        match ext.as_deref() {
+            Some("pdf") => {
+                let pdf = self.to_pdf().map_err(|e| WhimsyError::io(path, e))?;
+                std::fs::write(path, pdf).map_err(|e| WhimsyError::io(path, e))?
+            }
+            Some("png") => save_png(&self.to_png().await?, path)?,
+            _ => {
+                return Err(WhimsyError::Schema {
+                    field: "path".to_string(),
+                    message: format!("{} is not a .pdf or .png file", path.display()),
+                })
+            }
+        }
+        tracing::info!("Printed map to {}.", path.display());
+        Ok(())
+    }
+}
+
+/// Largest of 1, 2 or 5 times a power of ten not above `value`.
+fn nice_number(value: f64) -> f64 {
+    if value <= 0.0 {
+        return 1.0;
+    }
+    let power = 10f64.powf(value.log10().floor());
+    [5.0, 2.0, 1.0]
+        .iter()
+        .map(|v| v * power)
+        .find(|v| *v <= value)
+        .unwrap_or(power)
+}
+
+/// Triangulated fill of a polygon in page points, holes included.
+fn fill_mesh(rings: &[Vec<Pos2>], color: Color32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let Some((exterior, holes)) = rings.split_first() else {
+        return mesh;
+    };
+    let line = |ring: &Vec<Pos2>| {
+        geo::LineString::from(
+            ring.iter()
+                .map(|v| (v.x as f64, v.y as f64))
+                .collect::<Vec<(f64, f64)>>(),
+        )
+    };
+    let polygon = geo::Polygon::new(line(exterior), holes.iter().map(line).collect());
+    let raw = polygon.earcut_triangles_raw();
+    for xy in raw.vertices.chunks_exact(2) {
+        mesh.colored_vertex(Pos2::new(xy[0] as f32, xy[1] as f32), color);
+    }
+    for tri in raw.triangle_indices.chunks_exact(3) {
+        mesh.add_triangle(tri[0] as u32, tri[1] as u32, tri[2] as u32);
+    }
+    mesh
+}
+
+fn deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), ZlibLevel::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Escape `text` for a PDF string in WinAnsi encoding, replacing characters it lacks.
+fn pdf_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '\u{a0}'..='\u{ff}' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+/// A basemap tile decoded for embedding in a PDF.
+struct PdfImage {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+    /// Soft mask of the tile, if any pixel is not opaque.
+    alpha: Option<Vec<u8>>,
+}
+
+impl PdfImage {
+    fn new(image: &image::RgbaImage) -> Self {
+        let rgb = image
+            .pixels()
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect::<Vec<u8>>();
+        let alpha = image.pixels().map(|p| p[3]).collect::<Vec<u8>>();
+        Self {
+            width: image.width(),
+            height: image.height(),
+            rgb,
+            alpha: alpha.iter().any(|a| *a < 255).then_some(alpha),
+        }
+    }
+
+    /// Resource name of the image at `index` in the page.
+    fn name(index: usize) -> String {
+        format!("Im{}", index + 1)
+    }
+}
+
+/// Drawing operators of a PDF page, with the transparency levels and images they use.
+struct PdfContent {
+    ops: String,
+    /// Page height in points, to flip the y axis up.
+    height: f32,
+    /// The map as drawn on the page, to place basemap tiles.
+    map: MapView,
+    fills: BTreeSet<u8>,
+    strokes: BTreeSet<u8>,
+    tiles: TileCache,
+    images: Vec<PdfImage>,
+}
+
+impl PdfContent {
+    fn op(&mut self, op: String) {
+        self.ops.push_str(&op);
+        self.ops.push('\n');
+    }
+
+    fn point(&self, pos: Pos2) -> String {
+        format!("{:.2} {:.2}", pos.x, self.height - pos.y)
+    }
+
+    /// Set the fill or stroke color, with a graphics state for any transparency.
+    fn color(&mut self, color: Color32, stroke: bool) {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        let rgb = format!(
+            "{:.3} {:.3} {:.3}",
+            f32::from(r) / 255.0,
+            f32::from(g) / 255.0,
+            f32::from(b) / 255.0
+        );
+        if stroke {
+            self.op(format!("{} RG", rgb));
+        } else {
+            self.op(format!("{} rg", rgb));
+        }
+        if a < 255 {
+            let set = if stroke {
+                &mut self.strokes
+            } else {
+                &mut self.fills
+            };
+            set.insert(a);
+            let name = if stroke { 'S' } else { 'F' };
+            self.op(format!("/{}{} gs", name, a));
+        }
+    }
+
+    /// Fill and stroke the path drawn by `path`, using the even-odd rule so holes stay empty.
+    fn paint(
+        &mut self,
+        fill: Option<Color32>,
+        stroke: Option<Stroke>,
+        path: impl FnOnce(&mut Self),
+    ) {
+        let fill = fill.filter(|v| v.a() > 0);
+        let stroke = stroke.filter(|v| v.width > 0.0 && v.color.a() > 0);
+        let op = match (fill, stroke) {
+            (Some(_), Some(_)) => "B*",
+            (Some(_), None) => "f*",
+            (None, Some(_)) => "S",
+            (None, None) => return,
+        };
+        self.op("q".to_string());
+        if let Some(fill) = fill {
+            self.color(fill, false);
+        }
+        if let Some(stroke) = stroke {
+            self.color(stroke.color, true);
+            self.op(format!("{:.2} w 1 j", stroke.width));
+        }
+        path(self);
+        self.op(op.to_string());
+        self.op("Q".to_string());
+    }
+
+    fn ring(&mut self, ring: &[Pos2], close: bool) {
+        for (i, pos) in ring.iter().enumerate() {
+            let op = if i == 0 { "m" } else { "l" };
+            self.op(format!("{} {}", self.point(*pos), op));
+        }
+        if close {
+            self.op("h".to_string());
+        }
+    }
+
+    /// Draw the tiles of `basemap` covering the map frame as images, with `opacity` from 0 to 1.
+    /// Tiles that are missing or unreadable are left out.
+    fn basemap(&mut self, basemap: &Basemap, opacity: f32) {
+        let z = basemap.zoom(self.map.resolution);
+        let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self.op("q".to_string());
+        if alpha < 255 {
+            self.fills.insert(alpha);
+            self.op(format!("/F{} gs", alpha));
+        }
+        for tile in visible_tiles(&self.map, z) {
+            let image = match self.tiles.read(&basemap.source, &tile) {
+                Ok(Some(bytes)) => match image::load_from_memory(&bytes) {
+                    Ok(decoded) => decoded.to_rgba8(),
+                    Err(e) => {
+                        tracing::warn!("Could not decode tile {:?}: {}", tile, e);
+                        continue;
+                    }
+                },
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("Could not read tile {:?}: {}", tile, e);
+                    continue;
+                }
+            };
+            let (x_min, y_min, x_max, y_max) = tile.bounds();
+            let top_left = self.map.to_screen(x_min, y_max);
+            let bottom_right = self.map.to_screen(x_max, y_min);
+            // Images fill the unit square, scaled and moved onto the tile by the matrix.
+            self.op(format!(
+                "q {:.2} 0 0 {:.2} {} cm /{} Do Q",
+                bottom_right.x - top_left.x,
+                bottom_right.y - top_left.y,
+                self.point(Pos2::new(top_left.x, bottom_right.y)),
+                PdfImage::name(self.images.len())
+            ));
+            self.images.push(PdfImage::new(&image));
+        }
+        self.op("Q".to_string());
+    }
+
+    fn mark(&mut self, mark: &Mark) {
+        match mark {
+            Mark::Basemap(basemap, opacity) => self.basemap(basemap, *opacity),
+            Mark::Clip(Some(rect)) => {
+                self.op("q".to_string());
+                self.op(format!(
+                    "{} {:.2} {:.2} re W n",
+                    self.point(rect.left_bottom()),
+                    rect.width(),
+                    rect.height()
+                ));
+            }
+            Mark::Clip(None) => self.op("Q".to_string()),
+            Mark::Polygon {
+                rings,
+                fill,
+                stroke,
+            } => self.paint(*fill, *stroke, |pdf| {
+                for ring in rings.iter().filter(|v| v.len() > 2) {
+                    pdf.ring(ring, true);
+                }
+            }),
+            Mark::Line { points, stroke } => {
+                self.paint(None, Some(*stroke), |pdf| pdf.ring(points, false))
+            }
+            Mark::Circle {
+                center,
+                radius,
+                fill,
+                stroke,
+            } => self.paint(Some(*fill), *stroke, |pdf| {
+                // Four cubic Béziers, each a quarter circle.
+                let k = radius * 0.552_284_8;
+                let (x, y, r) = (center.x, center.y, *radius);
+                let p = |dx: f32, dy: f32| pdf.point(Pos2::new(x + dx, y + dy));
+                let ops = [
+                    format!("{} m", p(r, 0.0)),
+                    format!("{} {} {} c", p(r, k), p(k, r), p(0.0, r)),
+                    format!("{} {} {} c", p(-k, r), p(-r, k), p(-r, 0.0)),
+                    format!("{} {} {} c", p(-r, -k), p(-k, -r), p(0.0, -r)),
+                    format!("{} {} {} c h", p(k, -r), p(r, -k), p(r, 0.0)),
+                ];
+                for op in ops {
+                    pdf.op(op);
+                }
+            }),
+            Mark::Rect { rect, fill, stroke } => self.paint(*fill, *stroke, |pdf| {
+                let corner = pdf.point(rect.left_bottom());
+                pdf.op(format!(
+                    "{} {:.2} {:.2} re",
+                    corner,
+                    rect.width(),
+                    rect.height()
+                ));
+            }),
+            Mark::Text {
+                pos,
+                align,
+                text,
+                size,
+                color,
+                bold,
+                halo,
+            } => {
+                let rect = align.anchor_size(*pos, text_size(text, *size));
+                // Baseline sits about a fifth of the font size above the bottom of the box.
+                let origin = self.point(Pos2::new(rect.min.x, rect.max.y - 0.2 * size));
+                let font = if *bold { "F2" } else { "F1" };
+                let show = format!(
+                    "BT /{} {:.1} Tf {} Td ({}) Tj ET",
+                    font,
+                    size,
+                    origin,
+                    pdf_text(text)
+                );
+                self.op("q".to_string());
+                if let Some(halo) = halo {
+                    self.op("q 1 Tr 1 j 1.5 w".to_string());
+                    self.color(*halo, true);
+                    self.op(show.clone());
+                    self.op("Q".to_string());
+                }
+                self.color(*color, false);
+                self.op(show);
+                self.op("Q".to_string());
+            }
+        }
+    }
+}
+
+/// Page and output settings for printing the current view, from the Print window.
+#[derive(Debug, Clone)]
+pub struct PrintPanel {
+    pub layout: PrintLayout,
+    pub path: String,
+    pub status: String,
+    /// Export running in the background, with the status to show when it is done.
+    pub export: Option<Task<String>>,
+}
+
+impl Default for PrintPanel {
+    fn default() -> Self {
+        Self {
+            layout: Default::default(),
+            path: "map.pdf".to_string(),
+            status: Default::default(),
+            export: None,
+        }
+    }
+}
+
+impl PrintPanel {
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        map: &MapView,
+        parcels: Option<&Parcels>,
+        addresses: Option<&AddressPoints>,
+        layers: &Layers,
+    ) {
+        egui::Grid::new("print_layout")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Title");
+                ui.text_edit_singleline(&mut self.layout.title);
+                ui.end_row();
+                ui.label("Page");
+                ui.horizontal(|ui| {
+                    for page in [PageSize::Letter, PageSize::Tabloid] {
+                        ui.selectable_value(&mut self.layout.page, page, page.to_string());
+                    }
+                    ui.checkbox(&mut self.layout.landscape, "Landscape");
+                });
+                ui.end_row();
+                ui.label("DPI");
+                ui.add(egui::DragValue::new(&mut self.layout.dpi).clamp_range(72..=600));
+                ui.end_row();
+                ui.label("Date");
+                ui.text_edit_singleline(&mut self.layout.date);
+                ui.end_row();
+                ui.label("File");
+                ui.text_edit_singleline(&mut self.path);
+                ui.end_row();
+            });
+        let (width, height) = self.layout.pixels();
+        ui.label(format!("PNG output is {} x {} pixels.", width, height));
+        if let Some(status) = self.export.as_ref().and_then(|v| v.poll()) {
+            self.status = status;
+            self.export = None;
+        }
+        let idle = self.export.is_none();
+        if ui.add_enabled(idle, egui::Button::new("Export")).clicked() {
+            let sheet = Sheet::new(&self.layout, map, parcels, addresses, layers);
+            let path = self.path.clone();
+            self.status = format!("Printing to {}...", path);
+            self.export = Some(Task::spawn(ui.ctx(), move || export(&sheet, &path)));
+        }
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+}
+
+/// Write `sheet` to `path` from a background thread, with a runtime of its own, and describe
+/// the outcome for the panel.
+fn export(sheet: &Sheet, path: &str) -> String {
+    let result = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime.block_on(sheet.save(path)),
+        Err(e) => Err(WhimsyError::io(path, e)),
+    };
+    match result {
+        Ok(_) => format!("Printed to {}.", path),
+        Err(e) => e.to_string(),
+    }
+}
@@ -1,4 +1,5 @@
-use crate::prelude::{Action, App};
+use crate::prelude::{Action, App, GpuError};
+use std::collections::HashMap;
 use std::sync::Arc;
 use winit::{
     event::{Event, KeyEvent, WindowEvent},
@@ -8,22 +9,31 @@ use winit::{
 };
 use wgpu::SurfaceError;
 
-pub async fn run(window: Window, event_loop: EventLoop<()>) {
+pub async fn run(window: Window, event_loop: EventLoop<()>) -> Result<(), GpuError> {
     let window = Arc::new(window);
 
-    let mut state = App::new(Arc::clone(&window)).await;
+    let state = App::new(Arc::clone(&window)).await?;
+    let mut windows = HashMap::new();
+    windows.insert(window.id(), state);
 
-    let _ = event_loop.run(move |event, ewlt| {
+    event_loop.run(move |event, ewlt| {
         ewlt.set_control_flow(ControlFlow::Wait);
 
         match event {
             Event::AboutToWait => {
-                state.about_to_wait();
+                for state in windows.values_mut() {
+                    state.about_to_wait();
+                }
             }
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window.id() => {
+            } => {
+                let Some(state) = windows.get_mut(&window_id) else {
+                    return;
+                };
+                let mut close = false;
+                let mut spawn = None;
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -34,7 +44,7 @@ pub async fn run(window: Window, event_loop: EventLoop<()>) {
                                 ..
                             },
                         ..
-                    } => ewlt.exit(),
+                    } => close = true,
                     WindowEvent::ModifiersChanged(modifiers) => {
                         state.modifiers = modifiers.state();
                         tracing::info!("Modifiers changed to {:?}", state.modifiers);
@@ -58,18 +68,27 @@ pub async fn run(window: Window, event_loop: EventLoop<()>) {
                             if let Some(action) = action {
                                 tracing::info!("{:#?}", &action);
                                 match action {
+                                    Action::CloseWindow => close = true,
+                                    Action::CreateNewWindow => match state.spawn(ewlt) {
+                                        Ok(app) => spawn = Some(app),
+                                        Err(e) => e.report(),
+                                    },
                                     Action::Minimize => state.minimize(),
                                     Action::PrintHelp => state.print_help(),
                                     Action::ShowWindowMenu => state.show_menu(),
                                     Action::ToggleDecorations => state.toggle_decorations(),
                                     Action::ToggleFullscreen => state.toggle_fullscreen(),
                                     Action::ToggleMaximize => state.toggle_maximize(),
+                                    Action::CycleTheme => state.cycle_theme(),
                                     _ => tracing::info!("Other action."),
                                 }
                                 // state.handle_action(&event_loop, window_id, action);
                             }
                         }
                     }
+                    WindowEvent::ThemeChanged(theme) => {
+                        state.set_theme(*theme);
+                    }
                     WindowEvent::Resized(physical_size) => {
                         state.resize(*physical_size);
                     }
@@ -78,21 +97,33 @@ pub async fn run(window: Window, event_loop: EventLoop<()>) {
                         Err(SurfaceError::Lost | SurfaceError::Outdated) => {
                             state.resize(state.size)
                         }
-                        Err(SurfaceError::OutOfMemory) => ewlt.exit(),
+                        Err(SurfaceError::OutOfMemory) => {
+                            tracing::error!("The graphics device ran out of memory, exiting.");
+                            ewlt.exit()
+                        }
                         Err(SurfaceError::Timeout) => {
                             // Ignore timeouts.
                         }
                     },
                     other => {
                         state.handle_event(other);
-                        window.request_redraw();
                         return;
                     }
                 };
+                if close {
+                    windows.remove(&window_id);
+                    if windows.is_empty() {
+                        ewlt.exit();
+                    }
+                    return;
+                }
                 state.handle_event(event);
-                window.request_redraw();
+                if let Some(app) = spawn {
+                    windows.insert(app.window.id(), app);
+                }
             }
             _ => {}
         }
-    });
+    })?;
+    Ok(())
 }
@@ -1,21 +1,52 @@
-use crate::prelude::{Addresses, Parcels, TableView};
+use crate::prelude::{
+    Address, AddressPoints, AddressSearch, Addresses, Appearance,
+    AuditPanel, Columnar, DiffPanel, IdentifyPopup, ImportPanel, Imported, Layers, MapView,
+    MeasureMode, MeasureTool, NoticePanel, ParcelMetrics, ParcelReport, Parcels, PrintPanel,
+    SelectMode, SelectTarget, SelectTool, Session, TableView, Tabular, TileCache, ValidationPanel,
+    SESSION_PATH,
+};
+use crate::table::virtual_table;
 use egui::{Align, Color32, Context, DragValue, Layout, ScrollArea, Sense, Slider, TextStyle, Ui};
 use egui_extras::{Column, TableBuilder};
 use itertools::{sorted, Itertools};
 use spreadsheet::prelude::{BeaDatum, BeaData};
 use std::collections::HashMap;
 use std::collections::{BTreeMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
+/// State of the user interface in a single window.  Loaded datasets are held behind an [`Arc`]
+/// so that every window can share them, while the remaining fields are specific to the window.
+/// Datasets with selectable features are behind a [`RwLock`] so selections show in every window.
 #[derive(Clone, Debug)]
 pub struct UiState {
-    pub addresses: Option<Addresses>,
-    pub bea: Option<BeaData>,
+    pub address_search: AddressSearch,
+    pub address_table: Option<TableView<Addresses, Address>>,
+    pub addresses: Option<Arc<RwLock<AddressPoints>>>,
+    pub appearance: Appearance,
+    pub audit: AuditPanel,
+    pub bea: Option<Arc<BeaData>>,
     pub bea_panel: Option<BeaPanel>,
     pub bea_select: BeaSelect,
     pub bea_table: Option<TableView<BeaData, BeaDatum>>,
     pub counter: i32,
-    pub parcels: Option<Arc<Parcels>>,
+    /// Pointer position over the window in egui points, reported by the window events.
+    pub cursor: Option<egui::Pos2>,
+    pub diff: DiffPanel,
+    pub identify: IdentifyPopup,
+    pub import: ImportPanel,
+    pub layers: Layers,
+    pub map: MapView,
+    pub measure: MeasureTool,
+    pub notices: NoticePanel,
+    pub parcel_metrics: Option<TableView<ParcelReport, ParcelMetrics>>,
+    pub parcels: Option<Arc<RwLock<Parcels>>>,
+    pub print: PrintPanel,
+    pub select: SelectTool,
+    pub session_status: String,
+    pub show_map: bool,
+    /// Basemap tiles loaded for this window.
+    pub tiles: TileCache,
+    pub validation: ValidationPanel,
 }
 
 impl UiState {
@@ -29,7 +60,7 @@ impl UiState {
         //     }
         // };
         let addresses = match Addresses::load("data/addresses.data") {
-            Ok(data) => Some(data),
+            Ok(data) => Some(Arc::new(RwLock::new(AddressPoints::from(data)))),
             Err(_) => None,
         };
 
@@ -40,7 +71,7 @@ impl UiState {
             Ok(data) => {
                 bea_panel = Some(BeaPanel::new(&data));
                 bea_table = Some(TableView::new(data.clone()));
-                Some(data)
+                Some(Arc::new(data))
             },
             Err(e) => {
                 tracing::info!("Error loading BEA data: {}", e.to_string());
@@ -49,20 +80,105 @@ impl UiState {
         };
 
         let parcels = match Parcels::load("data/parcels.data") {
-            Ok(data) => Some(Arc::new(data)),
+            Ok(data) => Some(Arc::new(RwLock::new(data))),
             Err(_) => None,
         };
 
+        let mut map = MapView::default();
+        let mut layers = Layers::default();
+        if let Ok(session) = Session::load(SESSION_PATH) {
+            session.apply(&mut map, &mut layers);
+        }
+
         Self {
             addresses,
             bea,
             bea_panel,
-            bea_select: Default::default(),
             bea_table,
-            counter: Default::default(),
+            layers,
+            map,
             parcels,
+            ..Self::empty()
+        }
+    }
+
+    /// State with no datasets and the default map view and layers, without reading `data` or the
+    /// saved session.
+    pub fn empty() -> Self {
+        Self {
+            address_search: Default::default(),
+            address_table: None,
+            addresses: None,
+            appearance: Default::default(),
+            audit: Default::default(),
+            bea: None,
+            bea_panel: None,
+            bea_select: Default::default(),
+            bea_table: None,
+            counter: Default::default(),
+            cursor: None,
+            diff: Default::default(),
+            identify: Default::default(),
+            import: Default::default(),
+            layers: Default::default(),
+            map: Default::default(),
+            measure: Default::default(),
+            notices: Default::default(),
+            parcel_metrics: None,
+            parcels: None,
+            print: Default::default(),
+            select: Default::default(),
+            session_status: Default::default(),
+            show_map: true,
+            tiles: Default::default(),
+            validation: Default::default(),
+        }
+    }
+
+    /// State for a new window, sharing the datasets loaded by this one.  The appearance and map
+    /// view start from the current values, other window state starts fresh.
+    pub fn share(&self) -> Self {
+        Self {
+            address_search: Default::default(),
+            address_table: None,
+            addresses: self.addresses.clone(),
+            appearance: self.appearance,
+            audit: Default::default(),
+            bea: self.bea.clone(),
+            bea_panel: self.bea.as_ref().map(|data| BeaPanel::new(data)),
+            bea_select: Default::default(),
+            bea_table: self.bea.as_ref().map(|data| TableView::new((**data).clone())),
+            counter: Default::default(),
+            cursor: None,
+            diff: Default::default(),
+            identify: Default::default(),
+            import: Default::default(),
+            layers: self.layers.clone(),
+            map: self.map,
+            measure: Default::default(),
+            notices: Default::default(),
+            parcel_metrics: None,
+            parcels: self.parcels.clone(),
+            print: Default::default(),
+            select: Default::default(),
+            session_status: Default::default(),
+            show_map: self.show_map,
+            tiles: Default::default(),
+            validation: Default::default(),
+        }
+    }
+
+    /// Select the address point at `index` and centre the map on it.
+    pub fn zoom_to_address(&mut self, index: usize) {
+        if let Some(Ok(mut points)) = self.addresses.as_ref().map(|v| v.write()) {
+            if let Some(point) = points.records.get(index) {
+                self.map.centre_on(&point.geometry);
+                self.map.resolution = self.map.resolution.min(0.5);
+                points.select_only(index);
+            }
         }
     }
+
     pub fn run(&mut self, ui: &Context) {
         egui::Window::new("Whimsy UI").show(ui, |ui| {
             ui.heading("Window");
@@ -72,7 +188,7 @@ impl UiState {
             ui.label(format!("{}", self.counter));
 
             let mut address_ct = 0;
-            if let Some(data) = &self.addresses {
+            if let Some(Ok(data)) = self.addresses.as_ref().map(|v| v.read()) {
                 address_ct = data.records.len();
             }
             ui.label(format!("Addresses: {}", address_ct));
@@ -84,11 +200,239 @@ impl UiState {
             ui.label(format!("Bea: {}", bea_ct));
 
             let mut parcel_ct = 0;
-            if let Some(data) = &self.parcels {
+            if let Some(Ok(data)) = self.parcels.as_ref().map(|v| v.read()) {
                 parcel_ct = data.records.len();
             }
             ui.label(format!("Parcels: {}", parcel_ct));
+            ui.checkbox(&mut self.show_map, "Show map");
+        });
+
+        let picked = egui::Window::new("Address Search").show(ui, |ui| {
+            match &self.addresses {
+                Some(data) => self.address_search.show(ui, data),
+                None => {
+                    ui.label("No addresses loaded.");
+                    None
+                }
+            }
         });
+        if let Some(index) = picked.and_then(|v| v.inner).flatten() {
+            self.zoom_to_address(index);
+        }
+
+        let picked = egui::Window::new("Address Audit")
+            .default_open(false)
+            .show(ui, |ui| match self.addresses.as_ref().map(|v| v.read()) {
+                Some(Ok(points)) => self.audit.show(ui, &points),
+                _ => {
+                    ui.label("No addresses loaded.");
+                    None
+                }
+            });
+        if let Some(index) = picked.and_then(|v| v.inner).flatten() {
+            self.zoom_to_address(index);
+        }
+
+        let picked = egui::Window::new("Snapshot Changes")
+            .default_open(false)
+            .show(ui, |ui| self.diff.show(ui));
+        if let Some(point) = picked.and_then(|v| v.inner).flatten() {
+            self.map.centre_on(&point);
+            self.map.resolution = self.map.resolution.min(0.5);
+        }
+
+        let imported = egui::Window::new("Load Data")
+            .default_open(false)
+            .show(ui, |ui| self.import.show(ui));
+        match imported.and_then(|v| v.inner).flatten() {
+            // Write into the shared lock, so that windows sharing the dataset see the import.  Each
+            // panel rebuilds or drops what it derived from the old dataset on seeing the new
+            // revision.
+            Some(Imported::Addresses(points)) => {
+                match &self.addresses {
+                    Some(lock) => match lock.write() {
+                        Ok(mut data) => *data = points,
+                        Err(e) => tracing::warn!("Address points lock poisoned: {}", e),
+                    },
+                    None => self.addresses = Some(Arc::new(RwLock::new(points))),
+                }
+            }
+            Some(Imported::Parcels(parcels)) => {
+                match &self.parcels {
+                    Some(lock) => match lock.write() {
+                        Ok(mut data) => *data = parcels,
+                        Err(e) => tracing::warn!("Parcels lock poisoned: {}", e),
+                    },
+                    None => self.parcels = Some(Arc::new(RwLock::new(parcels))),
+                }
+            }
+            None => {}
+        }
+
+        egui::Window::new("Parcel Metrics")
+            .default_open(false)
+            .show(ui, |ui| {
+                if let Some(data) = &self.parcels {
+                    if ui.button("Measure parcels").clicked() {
+                        if let Ok(parcels) = data.read() {
+                            let report = ParcelReport::new(&parcels);
+                            match &mut self.parcel_metrics {
+                                Some(table) => table.replace(report, parcels.revision),
+                                None => {
+                                    self.parcel_metrics =
+                                        Some(TableView::with_revision(report, parcels.revision))
+                                }
+                            }
+                        }
+                    }
+                } else {
+                    ui.label("No parcels loaded.");
+                }
+                // Rows refer to parcels by position, so a table of other parcels is not shown.
+                let revision = self
+                    .parcels
+                    .as_ref()
+                    .and_then(|v| v.read().ok())
+                    .map(|v| v.revision);
+                match (&mut self.parcel_metrics, &self.parcels) {
+                    (Some(table), Some(data)) if Some(table.revision()) == revision => {
+                        ui.label("Areas and lengths in Oregon South state plane feet.");
+                        egui::ScrollArea::both().max_height(400.).show(ui, |ui| {
+                            sync_table(ui, table, data)
+                        });
+                    }
+                    (Some(_), Some(_)) => {
+                        ui.label("Parcels changed since they were measured.");
+                    }
+                    _ => {}
+                }
+            });
+
+        egui::Window::new("Address Table")
+            .default_open(false)
+            .show(ui, |ui| match &self.addresses {
+                Some(data) => {
+                    if ui.button("Tabulate addresses").clicked() {
+                        if let Ok(points) = data.read() {
+                            let records =
+                                points.records.iter().map(|v| v.address.clone()).collect();
+                            let addresses = Addresses { records };
+                            match &mut self.address_table {
+                                Some(table) => table.replace(addresses, points.revision),
+                                None => {
+                                    self.address_table =
+                                        Some(TableView::with_revision(addresses, points.revision))
+                                }
+                            }
+                        }
+                    }
+                    let revision = data.read().ok().map(|v| v.revision);
+                    match &mut self.address_table {
+                        Some(table) if Some(table.revision()) == revision => {
+                            egui::ScrollArea::both().max_height(400.).show(ui, |ui| {
+                                sync_table(ui, table, data)
+                            });
+                        }
+                        Some(_) => {
+                            ui.label("Addresses changed since they were tabulated.");
+                        }
+                        None => {}
+                    }
+                }
+                None => {
+                    ui.label("No addresses loaded.");
+                }
+            });
+
+        egui::Window::new("Select")
+            .default_open(false)
+            .show(ui, |ui| {
+                self.select.show(ui, self.parcels.as_deref(), self.addresses.as_deref());
+            });
+
+        egui::Window::new("Hearing Notices")
+            .default_open(false)
+            .show(ui, |ui| match &self.parcels {
+                Some(data) => self.notices.show(ui, data),
+                None => {
+                    ui.label("No parcels loaded.");
+                }
+            });
+
+        let picked = egui::Window::new("Parcel Validation")
+            .default_open(false)
+            .show(ui, |ui| match &self.parcels {
+                Some(data) => self.validation.show(ui, data),
+                None => {
+                    ui.label("No parcels loaded.");
+                    None
+                }
+            });
+        if let Some(point) = picked.and_then(|v| v.inner).flatten() {
+            self.map.centre_on(&point);
+            self.map.resolution = self.map.resolution.min(0.5);
+        }
+
+        egui::Window::new("Parcel Attributes")
+            .default_open(false)
+            .show(ui, |ui| match self.parcels.as_ref().map(|v| v.read()) {
+                Some(Ok(parcels)) => {
+                    let headers = parcels.attribute_headers();
+                    egui::ScrollArea::horizontal().show(ui, |ui| {
+                        virtual_table(ui, headers, parcels.records.len(), |i| {
+                            parcels.attribute_values(i, headers)
+                        });
+                    });
+                }
+                _ => {
+                    ui.label("No parcels loaded.");
+                }
+            });
+
+        egui::Window::new("Layers")
+            .default_open(false)
+            .show(ui, |ui| {
+                let palette = self.appearance.palette();
+                let parcels = self.parcels.as_ref().and_then(|v| v.read().ok());
+                let addresses = self.addresses.as_ref().and_then(|v| v.read().ok());
+                self.layers
+                    .show(ui, parcels.as_deref(), addresses.as_deref(), &palette);
+                ui.separator();
+                if ui.button("Save session").clicked() {
+                    let session = Session::new(&self.map, &self.layers);
+                    self.session_status = match session.save(SESSION_PATH) {
+                        Ok(_) => format!("Saved {}.", SESSION_PATH),
+                        Err(e) => e.to_string(),
+                    };
+                }
+                if !self.session_status.is_empty() {
+                    ui.label(&self.session_status);
+                }
+            });
+
+        egui::Window::new("Measure")
+            .default_open(false)
+            .show(ui, |ui| self.measure.show(ui));
+
+        egui::Window::new("Print")
+            .default_open(false)
+            .show(ui, |ui| {
+                let parcels = self.parcels.as_ref().and_then(|v| v.read().ok());
+                let addresses = self.addresses.as_ref().and_then(|v| v.read().ok());
+                self.print.show(
+                    ui,
+                    &self.map,
+                    parcels.as_deref(),
+                    addresses.as_deref(),
+                    &self.layers,
+                );
+            });
+
+        egui::Window::new("Appearance")
+            .default_open(false)
+            .show(ui, |ui| {
+                self.appearance.show(ui);
+            });
         
         let text_style = TextStyle::Body;
 
@@ -176,7 +520,7 @@ impl UiState {
                         let fips = panel.fips.selected.iter().cloned().collect::<Vec<String>>();
                         let codes = panel.codes.selected.iter().cloned().collect::<Vec<String>>();
                         if let Some(data) = &self.bea {
-                            let mut search = data.clone();
+                            let mut search = (**data).clone();
                             tracing::info!("Starting data: {}", search.records_ref().len());
                             search = search.filter_many("year", &times);
                             tracing::info!("Data at year: {}", search.records_ref().len());
@@ -210,10 +554,108 @@ impl UiState {
             // }
         // });
 
+        if self.show_map {
+            let palette = self.appearance.palette();
+            egui::CentralPanel::default()
+                .frame(egui::Frame::none())
+                .show(ui, |ui| {
+                    self.map.pan = self.select.mode == SelectMode::Pan;
+                    let painter;
+                    let response = {
+                        let parcels = self.parcels.as_ref().and_then(|v| v.read().ok());
+                        let addresses = self.addresses.as_ref().and_then(|v| v.read().ok());
+                        self.layers.refresh(parcels.as_deref(), addresses.as_deref());
+                        let response = self.map.show(
+                            ui,
+                            parcels.as_deref(),
+                            addresses.as_deref(),
+                            &self.layers,
+                            &mut self.tiles,
+                            &palette,
+                        );
+                        painter = ui.painter_at(self.map.screen);
+                        if let Some(points) = addresses.as_deref() {
+                            self.audit.overlay(&painter, &self.map, points);
+                        }
+                        if let Some(parcels) = parcels.as_deref() {
+                            self.validation.overlay(&painter, &self.map, parcels);
+                        }
+                        response
+                    };
+                    self.diff.overlay(&painter, &self.map);
+                    // While measuring, clicks add measurement points instead of selecting.
+                    let measuring = self.measure.mode != MeasureMode::Off;
+                    if !measuring {
+                        self.select.interact(
+                            ui,
+                            &response,
+                            &self.map,
+                            self.parcels.as_deref(),
+                            self.addresses.as_deref(),
+                        );
+                    }
+                    self.measure.interact(
+                        ui,
+                        &response,
+                        &self.map,
+                        self.parcels.as_ref(),
+                        self.addresses.as_ref(),
+                    );
+                    let parcels = self.parcels.as_ref().and_then(|v| v.read().ok());
+                    let addresses = self.addresses.as_ref().and_then(|v| v.read().ok());
+                    self.select.overlay(&painter, &self.map, palette.selected);
+                    self.measure.overlay(&painter, &self.map, palette.selected);
+                    if response.clicked() && !measuring {
+                        if let Some(pos) = self.cursor.or(response.interact_pointer_pos()) {
+                            self.identify.pick(
+                                pos,
+                                &self.map,
+                                parcels.as_deref(),
+                                addresses.as_deref(),
+                            );
+                        }
+                    }
+                });
+            let zoom = {
+                let parcels = self.parcels.as_ref().and_then(|v| v.read().ok());
+                let addresses = self.addresses.as_ref().and_then(|v| v.read().ok());
+                self.identify.show(ui, parcels.as_deref(), addresses.as_deref())
+            };
+            if let Some(bounds) = zoom {
+                self.map.fit(&bounds);
+                self.map.resolution = self.map.resolution.max(0.1);
+            }
+        }
     }
 
 }
 
+/// Draw `table` with its rows selected to match the features of `data`, then copy any rows
+/// picked in the table back to the features, so the map and table show the same selection.  The
+/// selection is rebuilt, and `data` locked for writing, only when one side has changed.
+fn sync_table<T, U, S>(ui: &mut Ui, table: &mut TableView<T, U>, data: &RwLock<S>)
+where
+    T: Tabular<U>,
+    U: Columnar,
+    S: SelectTarget,
+{
+    if let Ok(target) = data.read() {
+        if !target.is_selection(table.selection()) {
+            table.set_selection(target.selection());
+        }
+    }
+    table.table(ui);
+    let changed = data
+        .read()
+        .map(|v| !v.is_selection(table.selection()))
+        .unwrap_or_default();
+    if changed {
+        if let Ok(mut target) = data.write() {
+            target.set_selection(table.selection());
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct BeaSelect {
     codes: bool,
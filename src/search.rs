@@ -0,0 +1,272 @@
+use crate::abbreviations::standardize;
+use crate::prelude::{AddressPoints, Revision, Task};
+use egui::{Context, Ui};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Pause in typing before the query is searched.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+/// Most candidates listed by the search box.
+const SEARCH_LIMIT: usize = 25;
+
+/// A candidate returned by an address search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressMatch {
+    /// Position of the matching record in the dataset.
+    pub index: usize,
+    pub label: String,
+    /// Similarity between the query and the label, from 0 to 1.
+    pub score: f64,
+}
+
+/// Split `text` into upper case words with standard abbreviations, so that "123 North Main
+/// Street" and "123 N MAIN ST" produce the same tokens.
+pub fn normalize(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|v| !v.is_empty())
+        .map(standardize)
+        .collect()
+}
+
+/// Edit distance between `a` and `b`, counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let width = b.len() + 1;
+    let mut table = vec![0; (a.len() + 1) * width];
+    for i in 0..=a.len() {
+        table[i * width] = i;
+    }
+    for j in 0..=b.len() {
+        table[j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut value = (table[(i - 1) * width + j - 1] + cost)
+                .min(table[(i - 1) * width + j] + 1)
+                .min(table[i * width + j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                value = value.min(table[(i - 2) * width + j - 2] + 1);
+            }
+            table[i * width + j] = value;
+        }
+    }
+    table[a.len() * width + b.len()]
+}
+
+/// Similarity of a query token to a label token, from 0 to 1.  House numbers must match exactly,
+/// words tolerate typos in proportion to their length.  When `partial` is true the query token
+/// may be an incomplete prefix of the label token, as when the user is still typing.
+fn token_similarity(query: &str, label: &str, partial: bool) -> f64 {
+    if query == label {
+        return 1.0;
+    }
+    if partial && label.starts_with(query) {
+        return 0.9;
+    }
+    if query.chars().any(|c| c.is_ascii_digit()) || label.chars().any(|c| c.is_ascii_digit()) {
+        return 0.0;
+    }
+    let length = query.chars().count().max(label.chars().count());
+    let similarity = 1.0 - edit_distance(query, label) as f64 / length as f64;
+    if similarity < 0.5 {
+        0.0
+    } else {
+        similarity
+    }
+}
+
+/// Score how well normalized `query` tokens match normalized `label` tokens.  Every query token
+/// is paired with its most similar label token, with a small penalty for label tokens left over.
+pub fn similarity(query: &[String], label: &[String]) -> f64 {
+    if query.is_empty() || label.is_empty() {
+        return 0.0;
+    }
+    let last = query.len() - 1;
+    let mut matched = 0;
+    let mut total = 0.0;
+    for (i, token) in query.iter().enumerate() {
+        let best = label
+            .iter()
+            .map(|v| token_similarity(token, v, i == last))
+            .fold(0.0, f64::max);
+        if best > 0.0 {
+            matched += 1;
+        }
+        total += best;
+    }
+    let coverage = total / query.len() as f64;
+    let extra = (label.len().saturating_sub(matched)) as f64 / label.len() as f64;
+    coverage * (1.0 - 0.15 * extra)
+}
+
+/// Address labels with their normalized tokens.  Normalizing is the costly part of a search, so
+/// it is done once for a dataset and each search only scores the query against the tokens.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    pub labels: Vec<String>,
+    pub tokens: Vec<Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn new<'a>(labels: impl Iterator<Item = &'a str>) -> Self {
+        let labels = labels.map(|v| v.to_string()).collect::<Vec<String>>();
+        let tokens = labels.par_iter().map(|v| normalize(v)).collect();
+        Self { labels, tokens }
+    }
+
+    /// Rank the labels by similarity to `query`, returning at most `limit` candidates scoring
+    /// at least `threshold`, best first.
+    pub fn rank(&self, query: &str, limit: usize, threshold: f64) -> Vec<AddressMatch> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut matches = self
+            .tokens
+            .par_iter()
+            .enumerate()
+            .filter_map(|(index, tokens)| {
+                let score = similarity(&query, tokens);
+                if score >= threshold {
+                    Some(AddressMatch {
+                        index,
+                        label: self.labels[index].clone(),
+                        score,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<AddressMatch>>();
+        matches.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.label.cmp(&b.label))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
+
+/// Revision of the points searched, and the candidates found with the status of each.
+type Found = (Option<Revision>, Vec<(AddressMatch, String)>);
+
+/// Search box for address labels.  Holds the query and the candidates from the last search.
+/// Searches run on a background thread once typing pauses for [`DEBOUNCE`].
+#[derive(Debug, Default, Clone)]
+pub struct AddressSearch {
+    pub query: String,
+    pub results: Vec<AddressMatch>,
+    /// Status of each result, shown next to the label.
+    pub statuses: Vec<String>,
+    pub chosen: Option<usize>,
+    /// Revision of the address points the results were found in.
+    pub revision: Option<Revision>,
+    /// When the query last changed, if it has not been searched yet.
+    pub pending: Option<Instant>,
+    /// Search running in the background.
+    pub task: Option<Task<Found>>,
+}
+
+impl AddressSearch {
+    /// Draw the search box and candidates.  Returns the dataset index of a candidate when the
+    /// user picks one.  The query is searched in `points` after it stops changing, and searched
+    /// again when the points change.
+    pub fn show(&mut self, ui: &mut Ui, points: &Arc<RwLock<AddressPoints>>) -> Option<usize> {
+        let mut picked = None;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut self.query).hint_text("Address"))
+                .changed();
+            if ui.button("X").clicked() {
+                self.query = Default::default();
+                changed = true;
+            }
+        });
+        if changed {
+            self.pending = Some(Instant::now());
+        }
+        if let Some((revision, found)) = self.task.as_ref().and_then(|v| v.poll()) {
+            (self.results, self.statuses) = found.into_iter().unzip();
+            self.revision = revision;
+            self.task = None;
+        }
+        let revision = points.read().ok().map(|v| v.revision);
+        if !self.results.is_empty() && self.revision != revision {
+            self.results.clear();
+            self.statuses.clear();
+            self.chosen = None;
+            self.pending = Some(Instant::now());
+        }
+        self.search(ui.ctx(), points);
+
+        if self.query.is_empty() {
+            ui.label("Enter an address to search.");
+        } else if self.pending.is_some() || self.task.is_some() {
+            ui.label("Searching...");
+        } else if self.results.is_empty() {
+            ui.label("No matches.");
+        } else {
+            ui.label(format!("{} candidates.", self.results.len()));
+        }
+
+        egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+            for (result, status) in self.results.iter().zip(&self.statuses) {
+                let text = format!("{} ({}, {:.0}%)", result.label, status, result.score * 100.);
+                if ui
+                    .selectable_label(self.chosen == Some(result.index), text)
+                    .clicked()
+                {
+                    self.chosen = Some(result.index);
+                    picked = Some(result.index);
+                }
+            }
+        });
+        picked
+    }
+
+    /// Start a search for a pending query once the debounce delay has passed and no other search
+    /// is running.
+    fn search(&mut self, ctx: &Context, points: &Arc<RwLock<AddressPoints>>) {
+        let Some(changed) = self.pending else {
+            return;
+        };
+        if self.query.is_empty() {
+            self.pending = None;
+            self.results.clear();
+            self.statuses.clear();
+            return;
+        }
+        let wait = DEBOUNCE.saturating_sub(changed.elapsed());
+        if !wait.is_zero() {
+            ctx.request_repaint_after(wait);
+            return;
+        }
+        if self.task.is_some() {
+            // Polled again when the running search repaints.
+            return;
+        }
+        self.pending = None;
+        let query = self.query.clone();
+        let points = points.clone();
+        self.task = Some(Task::spawn(ctx, move || match points.read() {
+            Ok(points) => {
+                let found = points
+                    .search(&query, SEARCH_LIMIT)
+                    .into_iter()
+                    .map(|v| {
+                        let status = points.records[v.index].address.status.clone();
+                        (v, status)
+                    })
+                    .collect();
+                (Some(points.revision), found)
+            }
+            Err(_) => (None, Vec::new()),
+        }));
+    }
+}
@@ -0,0 +1,378 @@
+use crate::prelude::*;
+use egui::{Color32, Painter, Pos2, Response, Shape, Stroke, Ui};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+use galileo_types::geometry::CartesianGeometry2d;
+use geo::{Contains, Intersects};
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+/// Screen distance in points within which a click picks an address point.
+pub const PICK_RADIUS: f64 = 6.0;
+
+/// A map feature that can be picked with the selection tools.
+pub trait Selectable {
+    fn is_selected(&self) -> bool;
+    fn set_selected(&mut self, selected: bool);
+    /// True if the feature is under `point`, within `tolerance` map units.
+    fn hit(&self, point: &Point2d, tolerance: f64) -> bool;
+    /// True if the feature falls inside or crosses `area`.  Only called for features whose bounds
+    /// meet the bounds of `area`.
+    fn within(&self, area: &geo::Polygon) -> bool;
+    fn bounds(&self) -> Rect;
+}
+
+impl Selectable for Parcel {
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+
+    fn hit(&self, point: &Point2d, tolerance: f64) -> bool {
+        self.is_point_inside(point, tolerance)
+    }
+
+    fn within(&self, area: &geo::Polygon) -> bool {
+        area.intersects(&self.geo())
+    }
+
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+}
+
+impl Selectable for AddressPoint {
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+
+    fn hit(&self, point: &Point2d, tolerance: f64) -> bool {
+        rect_intersects(&self.bounds, &point_bounds(point, tolerance))
+            && (self.geometry.x() - point.x()).hypot(self.geometry.y() - point.y()) <= tolerance
+    }
+
+    fn within(&self, area: &geo::Polygon) -> bool {
+        area.contains(&geo::Point::new(self.geometry.x(), self.geometry.y()))
+    }
+
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+}
+
+/// Positions of the selected features in `records`.
+pub fn selected<T: Selectable>(records: &[T]) -> HashSet<usize> {
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.is_selected())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Select exactly the features of `records` at the positions in `selection`.
+pub fn set_selected<T: Selectable>(records: &mut [T], selection: &HashSet<usize>) {
+    for (i, record) in records.iter_mut().enumerate() {
+        record.set_selected(selection.contains(&i));
+    }
+}
+
+/// True if exactly the features of `records` at the positions in `selection` are selected.
+pub fn is_selection<T: Selectable>(records: &[T], selection: &HashSet<usize>) -> bool {
+    records
+        .iter()
+        .enumerate()
+        .all(|(i, v)| v.is_selected() == selection.contains(&i))
+        && records.iter().filter(|v| v.is_selected()).count() == selection.len()
+}
+
+/// Select the feature under `point`, taking the last one drawn where features overlap.  Other
+/// features are cleared unless `add` is true, in which case the feature hit is toggled.
+pub fn select_at<T: Selectable>(
+    records: &mut [T],
+    point: &Point2d,
+    tolerance: f64,
+    add: bool,
+) -> Option<usize> {
+    let hit = records.iter().rposition(|v| v.hit(point, tolerance));
+    if add {
+        if let Some(i) = hit {
+            let selected = records[i].is_selected();
+            records[i].set_selected(!selected);
+        }
+    } else {
+        for (i, record) in records.iter_mut().enumerate() {
+            record.set_selected(Some(i) == hit);
+        }
+    }
+    hit
+}
+
+/// Select the features inside or crossing the polygon with vertices `ring`.  Other features are
+/// cleared unless `add` is true.
+pub fn select_in<T: Selectable>(records: &mut [T], ring: &[Point2d], add: bool) -> usize {
+    if ring.len() < 3 {
+        return 0;
+    }
+    let area = geo::Polygon::new(
+        ring.iter()
+            .map(|v| geo::coord! { x: v.x(), y: v.y() })
+            .collect::<Vec<geo::Coord>>()
+            .into(),
+        Vec::new(),
+    );
+    let xs = ring.iter().map(|v| v.x());
+    let ys = ring.iter().map(|v| v.y());
+    let bounds = Rect::new(
+        xs.clone().fold(f64::MAX, f64::min),
+        ys.clone().fold(f64::MAX, f64::min),
+        xs.fold(f64::MIN, f64::max),
+        ys.fold(f64::MIN, f64::max),
+    );
+    let mut count = 0;
+    for record in records.iter_mut() {
+        let hit = rect_intersects(&record.bounds(), &bounds) && record.within(&area);
+        if hit {
+            count += 1;
+            record.set_selected(true);
+        } else if !add {
+            record.set_selected(false);
+        }
+    }
+    count
+}
+
+/// What a drag on the map does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Dragging pans the map.  Clicking still selects.
+    #[default]
+    Pan,
+    Box,
+    Lasso,
+}
+
+/// Which dataset the selection tools pick from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectLayer {
+    #[default]
+    Parcels,
+    Addresses,
+}
+
+/// Click, box and lasso selection on the map.  Click selects the feature under the pointer and
+/// shift-click adds to or removes from the selection; shift also extends box and lasso selections.
+#[derive(Debug, Clone, Default)]
+pub struct SelectTool {
+    pub mode: SelectMode,
+    pub layer: SelectLayer,
+    /// Screen positions of the box corners or lasso vertices while dragging.
+    pub drag: Vec<Pos2>,
+    pub status: String,
+}
+
+impl SelectTool {
+    /// Draw the tool and layer choices.  The datasets are locked for writing only to clear the
+    /// selection.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        parcels: Option<&RwLock<Parcels>>,
+        addresses: Option<&RwLock<AddressPoints>>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, SelectMode::Pan, "Pan");
+            ui.selectable_value(&mut self.mode, SelectMode::Box, "Box");
+            ui.selectable_value(&mut self.mode, SelectMode::Lasso, "Lasso");
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.layer, SelectLayer::Parcels, "Parcels");
+            ui.selectable_value(&mut self.layer, SelectLayer::Addresses, "Addresses");
+        });
+        let count = self.target(parcels, addresses).map(|target| {
+            if ui.button("Clear selection").clicked() {
+                if let Ok(mut target) = target.write() {
+                    target.set_selection(&HashSet::new());
+                }
+            }
+            target.read().map(|v| v.selected_count()).unwrap_or_default()
+        });
+        match count {
+            Some(n) => ui.label(format!("{} selected.", n)),
+            None => ui.label("Layer not loaded."),
+        };
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+
+    /// The dataset of the chosen layer.
+    fn target<'a>(
+        &self,
+        parcels: Option<&'a RwLock<Parcels>>,
+        addresses: Option<&'a RwLock<AddressPoints>>,
+    ) -> Option<&'a RwLock<dyn SelectTarget>> {
+        match self.layer {
+            SelectLayer::Parcels => parcels.map(|v| v as &RwLock<dyn SelectTarget>),
+            SelectLayer::Addresses => addresses.map(|v| v as &RwLock<dyn SelectTarget>),
+        }
+    }
+
+    /// Apply clicks and drags over the map `response` to the chosen layer.  The dataset is locked
+    /// for writing only when a click or drag selects features.
+    pub fn interact(
+        &mut self,
+        ui: &Ui,
+        response: &Response,
+        map: &MapView,
+        parcels: Option<&RwLock<Parcels>>,
+        addresses: Option<&RwLock<AddressPoints>>,
+    ) {
+        let add = ui.input(|i| i.modifiers.shift);
+        let target = self.target(parcels, addresses);
+        if response.clicked() {
+            let pos = response.interact_pointer_pos();
+            if let (Some(pos), Some(Ok(mut target))) = (pos, target.map(|v| v.write())) {
+                let point = map.to_map(pos);
+                self.status = match target.select_at(&point, PICK_RADIUS * map.resolution, add) {
+                    Some(i) => format!("Picked feature {}.", i),
+                    None => "Nothing here.".to_string(),
+                };
+            }
+        }
+        if self.mode == SelectMode::Pan {
+            self.drag.clear();
+            return;
+        }
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            self.drag.clear();
+        }
+        if response.dragged_by(egui::PointerButton::Primary) {
+            if let Some(pos) = response.interact_pointer_pos() {
+                match self.mode {
+                    SelectMode::Box => {
+                        if self.drag.is_empty() {
+                            self.drag.push(pos);
+                        }
+                        self.drag.truncate(1);
+                        self.drag.push(pos);
+                    }
+                    _ => {
+                        if self.drag.last().is_none_or(|v| v.distance(pos) > 2.0) {
+                            self.drag.push(pos);
+                        }
+                    }
+                }
+            }
+        }
+        if response.drag_released() {
+            let ring = self.ring(map);
+            self.drag.clear();
+            if let Some(Ok(mut target)) = target.map(|v| v.write()) {
+                let n = target.select_in(&ring, add);
+                self.status = format!("{} in area.", n);
+            }
+        }
+    }
+
+    /// Vertices of the box or lasso in map coordinates.
+    fn ring(&self, map: &MapView) -> Vec<Point2d> {
+        match (self.mode, self.drag.first(), self.drag.last()) {
+            (SelectMode::Box, Some(a), Some(b)) => [
+                Pos2::new(a.x, a.y),
+                Pos2::new(b.x, a.y),
+                Pos2::new(b.x, b.y),
+                Pos2::new(a.x, b.y),
+            ]
+            .iter()
+            .map(|v| map.to_map(*v))
+            .collect(),
+            _ => self.drag.iter().map(|v| map.to_map(*v)).collect(),
+        }
+    }
+
+    /// Draw the box or lasso being dragged.
+    pub fn overlay(&self, painter: &Painter, map: &MapView, color: Color32) {
+        if self.drag.len() < 2 {
+            return;
+        }
+        let points = self
+            .ring(map)
+            .iter()
+            .map(|v| map.point_to_screen(v))
+            .collect::<Vec<Pos2>>();
+        painter.add(Shape::closed_line(points, Stroke::new(1.5, color)));
+    }
+}
+
+/// A dataset the selection tools can pick from.
+pub trait SelectTarget {
+    fn select_at(&mut self, point: &Point2d, tolerance: f64, add: bool) -> Option<usize>;
+    fn select_in(&mut self, ring: &[Point2d], add: bool) -> usize;
+    /// Positions of the selected features.
+    fn selection(&self) -> HashSet<usize>;
+    /// True if exactly the features at the positions in `selection` are selected.
+    fn is_selection(&self, selection: &HashSet<usize>) -> bool;
+    /// Select exactly the features at the positions in `selection`.
+    fn set_selection(&mut self, selection: &HashSet<usize>);
+    fn selected_count(&self) -> usize;
+}
+
+impl SelectTarget for Parcels {
+    /// Parcels are hit only from inside, whatever the zoom level.
+    fn select_at(&mut self, point: &Point2d, _tolerance: f64, add: bool) -> Option<usize> {
+        select_at(&mut self.records, point, 0.0, add)
+    }
+
+    fn select_in(&mut self, ring: &[Point2d], add: bool) -> usize {
+        select_in(&mut self.records, ring, add)
+    }
+
+    fn selection(&self) -> HashSet<usize> {
+        selected(&self.records)
+    }
+
+    fn is_selection(&self, selection: &HashSet<usize>) -> bool {
+        is_selection(&self.records, selection)
+    }
+
+    fn set_selection(&mut self, selection: &HashSet<usize>) {
+        set_selected(&mut self.records, selection)
+    }
+
+    fn selected_count(&self) -> usize {
+        self.records.iter().filter(|v| v.selected).count()
+    }
+}
+
+impl SelectTarget for AddressPoints {
+    fn select_at(&mut self, point: &Point2d, tolerance: f64, add: bool) -> Option<usize> {
+        select_at(&mut self.records, point, tolerance, add)
+    }
+
+    fn select_in(&mut self, ring: &[Point2d], add: bool) -> usize {
+        select_in(&mut self.records, ring, add)
+    }
+
+    fn selection(&self) -> HashSet<usize> {
+        selected(&self.records)
+    }
+
+    fn is_selection(&self, selection: &HashSet<usize>) -> bool {
+        is_selection(&self.records, selection)
+    }
+
+    fn set_selection(&mut self, selection: &HashSet<usize>) {
+        set_selected(&mut self.records, selection)
+    }
+
+    fn selected_count(&self) -> usize {
+        self.records.iter().filter(|v| v.selected).count()
+    }
+}
@@ -0,0 +1,44 @@
+use crate::prelude::*;
+use galileo_types::cartesian::Point2d;
+use polite::Polite;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Default location of the saved session.
+pub const SESSION_PATH: &str = "data/session.data";
+
+/// Settings restored when the application starts: the map view and layer configuration.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Session {
+    pub center: Point2d,
+    pub resolution: f64,
+    pub layers: Layers,
+}
+
+impl Session {
+    pub fn new(map: &MapView, layers: &Layers) -> Self {
+        Self {
+            center: map.center,
+            resolution: map.resolution,
+            layers: layers.clone(),
+        }
+    }
+
+    /// Restore the view and layers to `map` and `layers`.
+    pub fn apply(&self, map: &mut MapView, layers: &mut Layers) {
+        map.center = self.center;
+        map.resolution = self.resolution;
+        *layers = self.layers.clone();
+    }
+
+    /// Write the session to a versioned bincode cache.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Polite<()> {
+        write_cache(self, path)
+    }
+
+    /// Read a session written by [`Session::save`], or by earlier versions without the cache
+    /// header.
+    pub fn load<P: AsRef<Path>>(path: P) -> Whimsy<Self> {
+        read_cache::<Self, Self, P>(path)
+    }
+}
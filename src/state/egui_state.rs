@@ -7,7 +7,7 @@ use wgpu::{Device, TextureFormat};
 use winit::event::WindowEvent;
 use winit::window::Window;
 
-use super::WgpuFrame;
+use super::{Appearance, WgpuFrame};
 
 pub struct EguiState {
     pub context: Context,
@@ -22,12 +22,12 @@ impl EguiState {
         output_depth_format: Option<TextureFormat>,
         msaa_samples: u32,
         window: &Window,
+        appearance: &Appearance,
     ) -> EguiState {
         let egui_context = Context::default();
         let id = egui_context.viewport_id();
 
-        let visuals = Default::default();
-        egui_context.set_visuals(visuals);
+        appearance.apply(&egui_context);
         egui_context.set_pixels_per_point(window.scale_factor() as f32);
 
         let egui_state = State::new(egui_context.clone(), id, &window, None, None);
@@ -1,12 +1,18 @@
+use crate::error::{AdapterAttempt, GpuError};
 use crate::prelude::{Action, EguiState, KEY_BINDINGS, MOUSE_BINDINGS, UiState, WgpuFrame};
+use crate::state::Gpu;
 use std::{iter, sync::Arc};
 use winit::dpi::{PhysicalPosition, PhysicalSize};
-use winit::event_loop::EventLoop;
+use winit::event_loop::{EventLoop, EventLoopWindowTarget};
 use winit::event::*;
-use winit::window::{Fullscreen, Theme, Window, WindowId};
+use winit::window::{Fullscreen, Theme, Window, WindowBuilder, WindowId};
 use winit::keyboard::ModifiersState;
 
+/// State of a single application window.  The wgpu instance, adapter, device and queue are
+/// shared by every window, the surface and egui state belong to this window alone.
 pub struct App {
+    pub instance: Arc<wgpu::Instance>,
+    pub adapter: Arc<wgpu::Adapter>,
     pub surface: Arc<wgpu::Surface<'static>>,
     pub device: Arc<wgpu::Device>,
     pub queue: Arc<wgpu::Queue>,
@@ -22,47 +28,64 @@ pub struct App {
 }
 
 impl App {
-    pub async fn new(window: Arc<Window>) -> Self {
-        let size = window.inner_size();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window.clone()).unwrap();
+    /// Set up rendering for `window`.  Returns an error listing every backend and adapter tried
+    /// if no device can draw to the window.
+    pub async fn new(window: Arc<Window>) -> Result<Self, GpuError> {
+        let (gpu, surface) = Gpu::for_window(window.clone()).await?;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        Self::attach(
+            window,
+            surface,
+            Arc::new(gpu.instance),
+            Arc::new(gpu.adapter),
+            Arc::new(gpu.device),
+            Arc::new(gpu.queue),
+            UiState::new(),
+        )
+    }
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits {
-                            // NOTE(alexkirsz) These are the limits on my GPU w/ WebGPU,
-                            // but your mileage may vary.
-                            max_texture_dimension_2d: 16384,
-                            ..wgpu::Limits::downlevel_webgl2_defaults()
-                        }
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                },
-                None,
-            )
-            .await
-            .unwrap();
+    /// Open a new window sharing the GPU device and loaded datasets of this one.
+    pub fn spawn(&self, target: &EventLoopWindowTarget<()>) -> Result<Self, GpuError> {
+        let window = WindowBuilder::new().with_title("Whimsy").build(target)?;
+        let window = Arc::new(window);
+        let surface = match self.instance.create_surface(window.clone()) {
+            Ok(surface) => surface,
+            Err(e) => {
+                let info = self.adapter.get_info();
+                return Err(GpuError::NoAdapter(vec![AdapterAttempt {
+                    backends: info.backend.into(),
+                    force_fallback_adapter: false,
+                    adapter: Some(info),
+                    failure: Some(format!("surface creation failed: {}", e)),
+                }]));
+            }
+        };
+        Self::attach(
+            window,
+            surface,
+            self.instance.clone(),
+            self.adapter.clone(),
+            self.device.clone(),
+            self.queue.clone(),
+            self.ui_state.share(),
+        )
+    }
 
+    /// Configure `surface` for `window` and set up the window egui state.
+    fn attach(
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+        instance: Arc<wgpu::Instance>,
+        adapter: Arc<wgpu::Adapter>,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        mut ui_state: UiState,
+    ) -> Result<Self, GpuError> {
+        let size = window.inner_size();
         let surface_caps = surface.get_capabilities(&adapter);
+        if surface_caps.formats.is_empty() {
+            return Err(GpuError::Surface(adapter.get_info()));
+        }
         let surface_format = surface_caps
             .formats
             .iter()
@@ -81,27 +104,27 @@ impl App {
         };
         surface.configure(&device, &config);
 
-        let egui_state = EguiState::new(&device, config.format, None, 1, &window);
-
-        let surface = Arc::new(surface);
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
-
         let theme = window.theme().unwrap_or(Theme::Dark);
+        ui_state.appearance.system = theme;
 
-        Self {
-            surface,
+        let egui_state =
+            EguiState::new(&device, config.format, None, 1, &window, &ui_state.appearance);
+
+        Ok(Self {
+            instance,
+            adapter,
+            surface: Arc::new(surface),
             device,
             queue,
             config,
             size,
             window,
             egui_state,
-            ui_state: UiState::new(),
+            ui_state,
             modifiers: Default::default(),
             theme,
             cursor_position: Default::default(),
-        }
+        })
     }
 
     pub fn about_to_wait(&mut self) {
@@ -119,6 +142,21 @@ impl App {
 
     pub fn handle_event(&mut self, event: &WindowEvent) {
         let _ = self.egui_state.handle_event(&self.window, event);
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(*position);
+                let scale = self.egui_state.context.pixels_per_point() as f64;
+                self.ui_state.cursor = Some(egui::pos2(
+                    (position.x / scale) as f32,
+                    (position.y / scale) as f32,
+                ));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+                self.ui_state.cursor = None;
+            }
+            _ => {}
+        }
 
         self.window.request_redraw();
     }
@@ -208,9 +246,21 @@ impl App {
         self.window.set_minimized(true);
     }
 
-    /// Change the theme.
+    /// Change the theme reported by the window.  Takes effect when the theme mode follows the
+    /// system.
     pub fn set_theme(&mut self, theme: Theme) {
         self.theme = theme;
+        self.ui_state.appearance.system = theme;
+        self.ui_state.appearance.apply(&self.egui_state.context);
+        self.window.request_redraw();
+    }
+
+    /// Cycle through the light, dark and system theme modes.
+    pub fn cycle_theme(&mut self) {
+        let appearance = &mut self.ui_state.appearance;
+        appearance.mode = appearance.mode.next();
+        tracing::info!("Theme mode set to {}.", appearance.mode);
+        appearance.apply(&self.egui_state.context);
         self.window.request_redraw();
     }
 
@@ -1,3 +1,5 @@
+use crate::error::{AdapterAttempt, GpuError};
+use std::sync::Arc;
 use wgpu::TextureView;
 use winit::window::Window;
 
@@ -9,3 +11,150 @@ pub struct WgpuFrame<'frame> {
     pub texture_view: &'frame TextureView,
     pub size: winit::dpi::PhysicalSize<u32>,
 }
+
+/// Backends tried in order, unless overridden with the `WGPU_BACKEND` environment variable.
+const BACKEND_PREFERENCE: [wgpu::Backends; 2] = [wgpu::Backends::PRIMARY, wgpu::Backends::SECONDARY];
+
+/// The wgpu instance, adapter, device and queue used for rendering.
+pub struct Gpu {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl Gpu {
+    /// Find a device able to present to `window`, returning it with the window surface.
+    pub async fn for_window(window: Arc<Window>) -> Result<(Self, wgpu::Surface<'static>), GpuError> {
+        let (gpu, surface) = Self::probe(Some(window)).await?;
+        match surface {
+            Some(surface) => Ok((gpu, surface)),
+            None => Err(GpuError::Surface(gpu.adapter.get_info())),
+        }
+    }
+
+    /// Find a device for offscreen rendering.
+    pub async fn headless() -> Result<Self, GpuError> {
+        let (gpu, _) = Self::probe(None).await?;
+        Ok(gpu)
+    }
+
+    /// Try each backend, first with hardware adapters and then with the software fallback
+    /// adapter, until one produces a device.  Every failed attempt is recorded in the error.
+    async fn probe(
+        window: Option<Arc<Window>>,
+    ) -> Result<(Self, Option<wgpu::Surface<'static>>), GpuError> {
+        let preference = match wgpu::util::backend_bits_from_env() {
+            Some(backends) => vec![backends],
+            None => BACKEND_PREFERENCE.to_vec(),
+        };
+
+        let mut attempts = Vec::new();
+        for backends in preference {
+            let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+                backends,
+                ..Default::default()
+            });
+
+            let surface = match &window {
+                Some(window) => match instance.create_surface(window.clone()) {
+                    Ok(surface) => Some(surface),
+                    Err(e) => {
+                        attempts.push(AdapterAttempt {
+                            backends,
+                            force_fallback_adapter: false,
+                            adapter: None,
+                            failure: Some(format!("surface creation failed: {}", e)),
+                        });
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            for force_fallback_adapter in [false, true] {
+                let mut attempt = AdapterAttempt {
+                    backends,
+                    force_fallback_adapter,
+                    adapter: None,
+                    failure: None,
+                };
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: wgpu::PowerPreference::HighPerformance,
+                        compatible_surface: surface.as_ref(),
+                        force_fallback_adapter,
+                    })
+                    .await;
+                let Some(adapter) = adapter else {
+                    attempt.failure = Some("no compatible adapter".to_string());
+                    tracing::info!("Adapter attempt failed: {}", attempt);
+                    attempts.push(attempt);
+                    continue;
+                };
+                attempt.adapter = Some(adapter.get_info());
+
+                match Self::request_device(&adapter).await {
+                    Ok((device, queue)) => {
+                        tracing::info!("Rendering with {}", attempt);
+                        let gpu = Self {
+                            instance,
+                            adapter,
+                            device,
+                            queue,
+                        };
+                        return Ok((gpu, surface));
+                    }
+                    Err(e) => {
+                        attempt.failure = Some(format!("device request failed: {}", e));
+                        tracing::info!("Adapter attempt failed: {}", attempt);
+                        attempts.push(attempt);
+                    }
+                }
+            }
+        }
+
+        Err(GpuError::NoAdapter(attempts))
+    }
+
+    /// Request a device with the default limits, falling back to downlevel limits for older
+    /// hardware and software renderers.
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        let result = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: if cfg!(target_arch = "wasm32") {
+                        wgpu::Limits {
+                            // NOTE(alexkirsz) These are the limits on my GPU w/ WebGPU,
+                            // but your mileage may vary.
+                            max_texture_dimension_2d: 16384,
+                            ..wgpu::Limits::downlevel_webgl2_defaults()
+                        }
+                    } else {
+                        wgpu::Limits::default()
+                    },
+                },
+                None,
+            )
+            .await;
+        if result.is_ok() {
+            return result;
+        }
+
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_webgl2_defaults()
+                        .using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await
+    }
+}
@@ -0,0 +1,240 @@
+use crate::error::{GpuError, Whimsy, WhimsyError};
+use crate::state::Gpu;
+use egui::Context;
+use egui_wgpu::{Renderer, ScreenDescriptor};
+use polite::Polite;
+use std::path::Path;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Renders egui output into an offscreen texture, without a window or surface.  Uses the
+/// software fallback adapter when no hardware adapter is available, so it runs on machines
+/// without a GPU.
+pub struct Headless {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub context: Context,
+    pub renderer: Renderer,
+    pub width: u32,
+    pub height: u32,
+    pub pixels_per_point: f32,
+    pub adapter_info: wgpu::AdapterInfo,
+}
+
+impl Headless {
+    pub async fn new(width: u32, height: u32) -> Result<Self, GpuError> {
+        let gpu = Gpu::headless().await?;
+        let adapter_info = gpu.adapter.get_info();
+        let device = gpu.device;
+        let queue = gpu.queue;
+
+        let renderer = Renderer::new(&device, FORMAT, None, 1);
+
+        Ok(Self {
+            device,
+            queue,
+            context: Context::default(),
+            renderer,
+            width,
+            height,
+            pixels_per_point: 1.0,
+            adapter_info,
+        })
+    }
+
+    /// Run `run_ui` and return the rendered frame as RGBA pixels.  The ui runs `passes` times
+    /// before painting, so that windows sized from their content have settled.
+    pub fn render(&mut self, passes: usize, mut run_ui: impl FnMut(&Context)) -> image::RgbaImage {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("headless target"),
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [self.width, self.height],
+            pixels_per_point: self.pixels_per_point,
+        };
+        self.context.set_pixels_per_point(self.pixels_per_point);
+
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                Default::default(),
+                egui::vec2(self.width as f32, self.height as f32) / self.pixels_per_point,
+            )),
+            ..Default::default()
+        };
+
+        let mut full_output = self.context.run(raw_input.clone(), &mut run_ui);
+        for _ in 1..passes.max(1) {
+            for (id, image_delta) in &full_output.textures_delta.set {
+                self.renderer
+                    .update_texture(&self.device, &self.queue, *id, image_delta);
+            }
+            full_output = self.context.run(raw_input.clone(), &mut run_ui);
+        }
+
+        let paint_jobs = self
+            .context
+            .tessellate(full_output.shapes, full_output.pixels_per_point);
+
+        for (id, image_delta) in &full_output.textures_delta.set {
+            self.renderer
+                .update_texture(&self.device, &self.queue, *id, image_delta);
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless Encoder"),
+            });
+
+        self.renderer.update_buffers(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &paint_jobs,
+            &screen_descriptor,
+        );
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                label: Some("headless render pass"),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.renderer
+                .render(&mut render_pass, &paint_jobs, &screen_descriptor);
+        }
+
+        // Rows copied out of a texture must be aligned to 256 bytes.
+        let unpadded = self.width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded = unpadded.div_ceil(align) * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("headless readback"),
+            size: (padded * self.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            texture.size(),
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        for x in &full_output.textures_delta.free {
+            self.renderer.free_texture(x)
+        }
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::Maintain::Wait);
+        let mut pixels = Vec::with_capacity((unpadded * self.height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded as usize) {
+                pixels.extend_from_slice(&row[..unpadded as usize]);
+            }
+        }
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("Buffer holds width * height pixels.")
+    }
+
+    /// Render a frame of `run_ui` and write it to `path` as a PNG.
+    pub fn save_png<P: AsRef<Path>>(&mut self, path: P, run_ui: impl FnMut(&Context)) -> Whimsy<()> {
+        let frame = self.render(2, run_ui);
+        save_png(&frame, path)
+    }
+}
+
+pub fn save_png<P: AsRef<Path>>(frame: &image::RgbaImage, path: P) -> Whimsy<()> {
+    frame
+        .save_with_format(&path, image::ImageFormat::Png)
+        .map_err(|e| match e {
+            image::ImageError::IoError(e) => WhimsyError::io(&path, e),
+            e => WhimsyError::io(&path, std::io::Error::other(e)),
+        })
+}
+
+/// Environment variable that, when set to `1`, writes frames over the stored snapshots.
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// Compare `frame` against the PNG stored at `path`.  Returns true if the images have the same
+/// size and the mean difference per channel is within `tolerance` (0-255).  A missing snapshot
+/// fails the comparison, unless `UPDATE_SNAPSHOTS=1` is set, in which case `frame` is written to
+/// `path` and the comparison passes.
+pub fn matches_snapshot<P: AsRef<Path>>(
+    frame: &image::RgbaImage,
+    path: P,
+    tolerance: f64,
+) -> Polite<bool> {
+    let path = path.as_ref();
+    if std::env::var(UPDATE_SNAPSHOTS).is_ok_and(|v| v == "1") {
+        tracing::info!("Writing snapshot to {}.", path.display());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        save_png(frame, path)?;
+        return Ok(true);
+    }
+    if !path.exists() {
+        tracing::warn!(
+            "No snapshot at {}, run with {}=1 to create it.",
+            path.display(),
+            UPDATE_SNAPSHOTS
+        );
+        return Ok(false);
+    }
+    let snapshot = image::open(path)
+        .map_err(std::io::Error::other)?
+        .to_rgba8();
+    if snapshot.dimensions() != frame.dimensions() {
+        tracing::info!(
+            "Snapshot size {:?} does not match frame size {:?}.",
+            snapshot.dimensions(),
+            frame.dimensions()
+        );
+        return Ok(false);
+    }
+    let total = snapshot
+        .as_raw()
+        .iter()
+        .zip(frame.as_raw())
+        .map(|(a, b)| (*a as f64 - *b as f64).abs())
+        .sum::<f64>();
+    let difference = total / frame.as_raw().len().max(1) as f64;
+    tracing::info!("Mean difference from snapshot: {:.3}.", difference);
+    Ok(difference <= tolerance)
+}
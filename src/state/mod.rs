@@ -1,8 +1,12 @@
 pub mod egui_state;
 pub mod eponym;
 pub mod gpu;
+pub mod headless;
+pub mod theme;
 
 pub use egui_state::EguiState;
 pub use eponym::App;
-pub use gpu::WgpuFrame;
+pub use gpu::{Gpu, WgpuFrame};
+pub use headless::Headless;
+pub use theme::{Appearance, MapPalette, ThemeMode};
 
@@ -0,0 +1,147 @@
+use egui::{Color32, Context, Stroke, Ui, Visuals};
+use winit::window::Theme;
+
+/// Which theme to display.  `System` follows the theme reported by the window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThemeMode {
+    Light,
+    Dark,
+    #[default]
+    System,
+}
+
+impl ThemeMode {
+    /// Advance to the next mode, wrapping from `System` back to `Light`.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Light => Self::Dark,
+            Self::Dark => Self::System,
+            Self::System => Self::Light,
+        }
+    }
+}
+
+impl std::fmt::Display for ThemeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// Colors used to draw features on the map, derived from the active [`Appearance`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapPalette {
+    pub background: Color32,
+    pub parcel_fill: Color32,
+    pub parcel_stroke: Color32,
+    pub address: Color32,
+    pub selected: Color32,
+    pub text: Color32,
+}
+
+/// User-configurable look of the application, applied to both egui and the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Appearance {
+    pub mode: ThemeMode,
+    /// Theme reported by the window, used when `mode` is [`ThemeMode::System`].
+    pub system: Theme,
+    pub accent: Color32,
+    pub font_scale: f32,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            system: Theme::Dark,
+            accent: Color32::from_rgb(90, 170, 255),
+            font_scale: 1.0,
+        }
+    }
+}
+
+impl Appearance {
+    pub fn new(system: Theme) -> Self {
+        Self {
+            system,
+            ..Default::default()
+        }
+    }
+
+    /// The light or dark theme in effect after resolving `System`.
+    pub fn theme(&self) -> Theme {
+        match self.mode {
+            ThemeMode::Light => Theme::Light,
+            ThemeMode::Dark => Theme::Dark,
+            ThemeMode::System => self.system,
+        }
+    }
+
+    pub fn visuals(&self) -> Visuals {
+        let mut visuals = match self.theme() {
+            Theme::Light => Visuals::light(),
+            Theme::Dark => Visuals::dark(),
+        };
+        visuals.selection.bg_fill = self.accent;
+        visuals.selection.stroke = Stroke::new(1.0, visuals.strong_text_color());
+        visuals.hyperlink_color = self.accent;
+        visuals
+    }
+
+    pub fn palette(&self) -> MapPalette {
+        match self.theme() {
+            Theme::Light => MapPalette {
+                background: Color32::from_rgb(242, 239, 233),
+                parcel_fill: Color32::from_rgba_unmultiplied(200, 190, 170, 90),
+                parcel_stroke: Color32::from_rgb(120, 110, 95),
+                address: Color32::from_rgb(40, 40, 40),
+                selected: self.accent,
+                text: Color32::BLACK,
+            },
+            Theme::Dark => MapPalette {
+                background: Color32::from_rgb(27, 30, 34),
+                parcel_fill: Color32::from_rgba_unmultiplied(80, 90, 100, 90),
+                parcel_stroke: Color32::from_rgb(150, 160, 170),
+                address: Color32::from_rgb(230, 230, 230),
+                selected: self.accent,
+                text: Color32::WHITE,
+            },
+        }
+    }
+
+    /// Set the visuals and scale the default text styles of `context` to match.
+    pub fn apply(&self, context: &Context) {
+        let mut style = (*context.style()).clone();
+        style.visuals = self.visuals();
+        style.text_styles = egui::Style::default()
+            .text_styles
+            .into_iter()
+            .map(|(text_style, mut font)| {
+                font.size *= self.font_scale;
+                (text_style, font)
+            })
+            .collect();
+        context.set_style(style);
+    }
+
+    /// Controls for the theme mode, accent color and font scale.  Returns true if any value
+    /// changed, after applying the change to the context of `ui`.
+    pub fn show(&mut self, ui: &mut Ui) -> bool {
+        let before = *self;
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            for mode in [ThemeMode::Light, ThemeMode::Dark, ThemeMode::System] {
+                ui.selectable_value(&mut self.mode, mode, format!("{}", mode));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Accent");
+            ui.color_edit_button_srgba(&mut self.accent);
+        });
+        ui.add(egui::Slider::new(&mut self.font_scale, 0.5..=2.0).text("Font scale"));
+        let changed = before != *self;
+        if changed {
+            self.apply(ui.ctx());
+        }
+        changed
+    }
+}
@@ -1,6 +1,8 @@
+use crate::prelude::Revision;
 use egui::{Align, Layout, Sense, Slider, Ui};
 use egui_extras::{Column, TableBuilder};
 use spreadsheet::prelude::{BeaDatum, BeaData};
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::marker::PhantomData;
 
@@ -10,20 +12,84 @@ pub struct TableView<T: Tabular<U>, U: Columnar> {
     search: String,
     selection: HashSet<usize>,
     target: usize,
+    /// Column to sort by, and whether the order is ascending.
+    sort: Option<(usize, bool)>,
+    /// Revision of the dataset the rows were taken from.
+    revision: Revision,
+    /// Positions in the data of the rows in display order, for the sort in `sorted`.
+    order: Vec<usize>,
+    /// Revision and sort that `order` was computed for, or `None` before the first frame.
+    sorted: Option<(Revision, Option<(usize, bool)>)>,
     phantom: PhantomData<U>,
 }
 
 impl<T: Tabular<U>, U: Columnar> TableView<T, U> {
     pub fn new(data: T) -> Self {
+        Self::with_revision(data, Default::default())
+    }
+
+    /// Table of `data` taken from the dataset at `revision`.
+    pub fn with_revision(data: T, revision: Revision) -> Self {
         Self {
             data,
             search: Default::default(),
             selection: Default::default(),
             target: Default::default(),
+            sort: None,
+            revision,
+            order: Vec::new(),
+            sorted: None,
             phantom: Default::default(),
         }
     }
 
+    /// Revision of the dataset the rows were taken from.
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// Replace the rows with `data` taken from the dataset at `revision`, keeping the sort.
+    pub fn replace(&mut self, data: T, revision: Revision) {
+        self.data = data;
+        self.revision = revision;
+        self.selection.clear();
+    }
+
+    /// Recompute the display order when the rows or the sort have changed since the last frame.
+    fn refresh_order(&mut self) {
+        if self.sorted == Some((self.revision, self.sort)) {
+            return;
+        }
+        self.order = (0..self.data.len()).collect();
+        if let Some((column, ascending)) = self.sort {
+            let keys = self
+                .data
+                .rows()
+                .iter()
+                .map(|v| v.values().get(column).cloned().unwrap_or_default())
+                .collect::<Vec<String>>();
+            self.order.sort_by(|a, b| {
+                let order = compare(&keys[*a], &keys[*b]);
+                if ascending {
+                    order
+                } else {
+                    order.reverse()
+                }
+            });
+        }
+        self.sorted = Some((self.revision, self.sort));
+    }
+
+    /// Positions of the selected rows in the data.
+    pub fn selection(&self) -> &HashSet<usize> {
+        &self.selection
+    }
+
+    /// Replace the selected rows, such as to follow features picked on the map.
+    pub fn set_selection(&mut self, selection: HashSet<usize>) {
+        self.selection = selection;
+    }
+
     fn toggle_row_selection(&mut self, row_index: usize, row_response: &egui::Response) {
         if row_response.clicked() {
             if self.selection.contains(&row_index) {
@@ -66,39 +132,53 @@ impl<T: Tabular<U>, U: Columnar> TableView<T, U> {
             .resizable(true)
             .sense(Sense::click())
             .cell_layout(Layout::left_to_right(Align::Center))
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto());
+            .columns(Column::auto(), U::headers().len());
+        self.refresh_order();
         if track_item {
-            table = table.scroll_to_row(self.target, Some(Align::Center));
+            // The slider picks a row of the data, which may be elsewhere in the sorted table.
+            let position = self
+                .order
+                .iter()
+                .position(|v| *v == self.target)
+                .unwrap_or(self.target);
+            table = table.scroll_to_row(position, Some(Align::Center));
         }
 
-
+        let mut clicked = None;
         table
             .header(20.0, |mut header| {
-                let names = BeaData::names();
-                names.iter().map(|v| header.col(|ui| {
-                    ui.strong(v);
+                let names = U::headers();
+                names.iter().enumerate().map(|(i, v)| header.col(|ui| {
+                    let text = match self.sort {
+                        Some((column, true)) if column == i => format!("{} ^", v),
+                        Some((column, false)) if column == i => format!("{} v", v),
+                        _ => v.to_string(),
+                    };
+                    if ui.add(egui::Label::new(egui::RichText::new(text).strong()).sense(Sense::click())).clicked() {
+                        clicked = Some(i);
+                    }
                 })).for_each(drop);
             })
-            .body(|mut body| {
-                for (i, record) in self.data.rows().iter().enumerate() {
-                    let columns = record.values();
-                    body.row(18.0, |mut row| {
-                        columns.iter().map(|v| {
-                            row.set_selected(self.selection.contains(&i));
-                            row.col(|ui| {
-                                ui.label(v);
-                            });
-                            self.toggle_row_selection(i, &row.response());
-                        }).for_each(drop);
-                    });
-                }
+            .body(|body| {
+                body.rows(18.0, self.order.len(), |mut row| {
+                    let i = self.order[row.index()];
+                    let columns = self.data.row(i).map(|v| v.values()).unwrap_or_default();
+                    row.set_selected(self.selection.contains(&i));
+                    columns.iter().map(|v| {
+                        row.col(|ui| {
+                            ui.label(v);
+                        });
+                    }).for_each(drop);
+                    self.toggle_row_selection(i, &row.response());
+                });
             });
+        if let Some(column) = clicked {
+            self.sort = match self.sort {
+                Some((current, true)) if current == column => Some((column, false)),
+                Some((current, false)) if current == column => None,
+                _ => Some((column, true)),
+            };
+        }
     }
 
     pub fn show(&self, ui: &mut Ui) {
@@ -114,6 +194,11 @@ pub trait Tabular<T: Columnar> {
     fn len(&self) -> usize {
         self.rows().len()
     }
+    /// The row at `index`.  Implementors holding their rows in a `Vec` should override this to
+    /// avoid copying every row.
+    fn row(&self, index: usize) -> Option<T> {
+        self.rows().into_iter().nth(index)
+    }
 }
 
 impl Tabular<BeaDatum> for BeaData {
@@ -125,6 +210,10 @@ impl Tabular<BeaDatum> for BeaData {
         self.records()
     }
 
+    fn row(&self, index: usize) -> Option<BeaDatum> {
+        self.records_ref().get(index).cloned()
+    }
+
     fn table(&self, ui: &mut Ui) {
         let mut track_item = false;
         let mut scroll_top = false;
@@ -188,6 +277,44 @@ pub trait Columnar {
     fn values(&self) -> Vec<String>;
 }
 
+/// Order table values with numbers before text, numbers by value and text alphabetically.  This
+/// is a total order, so sorting gives the same result whatever order the rows start in.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.total_cmp(&y).then_with(|| a.cmp(b)),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Show the first `limit` of `rows` in a plain table, with a header from [`Columnar::headers`].
+pub fn preview<U: Columnar>(ui: &mut Ui, rows: &[U], limit: usize) {
+    let names = U::headers();
+    TableBuilder::new(ui)
+        .striped(true)
+        .resizable(true)
+        .cell_layout(Layout::left_to_right(Align::Center))
+        .columns(Column::auto(), names.len())
+        .header(20.0, |mut header| {
+            names.iter().map(|v| header.col(|ui| {
+                ui.strong(v);
+            })).for_each(drop);
+        })
+        .body(|mut body| {
+            for record in rows.iter().take(limit) {
+                let columns = record.values();
+                body.row(18.0, |mut row| {
+                    columns.iter().map(|v| {
+                        row.col(|ui| {
+                            ui.label(v);
+                        });
+                    }).for_each(drop);
+                });
+            }
+        });
+}
+
 impl Columnar for BeaDatum {
     fn headers() -> Vec<String> {
         Self::names()
@@ -197,3 +324,28 @@ impl Columnar for BeaDatum {
         Self::columns(self)
     }
 }
+
+/// Show `len` rows under the column `names`, building the values of only the rows in view, so
+/// that large datasets with columns known only at run time stay responsive.
+pub fn virtual_table(ui: &mut Ui, names: &[String], len: usize, values: impl Fn(usize) -> Vec<String>) {
+    TableBuilder::new(ui)
+        .striped(true)
+        .resizable(true)
+        .cell_layout(Layout::left_to_right(Align::Center))
+        .columns(Column::auto(), names.len())
+        .header(20.0, |mut header| {
+            names.iter().map(|v| header.col(|ui| {
+                ui.strong(v);
+            })).for_each(drop);
+        })
+        .body(|body| {
+            body.rows(18.0, len, |mut row| {
+                let columns = values(row.index());
+                columns.iter().map(|v| {
+                    row.col(|ui| {
+                        ui.label(v);
+                    });
+                }).for_each(drop);
+            });
+        });
+}
@@ -0,0 +1,47 @@
+use egui::Context;
+use std::sync::{Arc, Mutex};
+
+/// Work running on a background thread, so that the ui keeps drawing while it runs.  The ui polls
+/// the task each frame, and is repainted when the result arrives.
+pub struct Task<T> {
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Start `work` on a new thread, repainting `ctx` when it finishes.
+    pub fn spawn(ctx: &Context, work: impl FnOnce() -> T + Send + 'static) -> Self {
+        let result = Arc::new(Mutex::new(None));
+        let slot = result.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let value = work();
+            if let Ok(mut slot) = slot.lock() {
+                *slot = Some(value);
+            }
+            ctx.request_repaint();
+        });
+        Self { result }
+    }
+}
+
+impl<T> Task<T> {
+    /// The result of the work once it has finished.  Returns `None` while the work is running,
+    /// and after the result has been taken.
+    pub fn poll(&self) -> Option<T> {
+        self.result.lock().ok().and_then(|mut v| v.take())
+    }
+}
+
+impl<T> Clone for Task<T> {
+    fn clone(&self) -> Self {
+        Self {
+            result: self.result.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Task").finish_non_exhaustive()
+    }
+}
@@ -1,28 +1,101 @@
+use crate::error::{LoadReport, Whimsy, WhimsyError};
+use crate::ingest::{from_csv_with, CsvOptions};
 use galileo_types::cartesian::{CartesianPoint2d, Point2d, Rect};
+use polite::Polite;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
-/// Generic function to deserialize data types from a CSV file.  Called by methods to avoid code
-/// duplication.
-pub fn from_csv<T: DeserializeOwned + Clone, P: AsRef<std::path::Path>>(
+/// Identifies one version of a dataset.  Every new value is unique, so data derived from a
+/// dataset can be rebuilt when the revision of the dataset no longer matches.  Assign a new
+/// revision with [`Revision::default`] after changing the records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Revision(u64);
+
+impl Default for Revision {
+    fn default() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, AtomicOrdering::Relaxed))
+    }
+}
+
+/// Leading bytes of a whimsy cache file, followed by the format version as a little endian u32.
+pub const CACHE_MAGIC: [u8; 8] = *b"WHIMSYDB";
+/// Version of the cache format written by [`write_cache`].  Increment it whenever the layout of
+/// a cached type changes, so that old caches are reported instead of misread.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Generic function to deserialize data types from a CSV file, which may be gzip or zstd
+/// compressed.  Called by methods to avoid code duplication.  Rows that fail to deserialize are
+/// listed in the report with the failing field.  See [`from_csv_with`] for other delimiters and
+/// encodings.
+pub fn from_csv<T: DeserializeOwned + Send, P: AsRef<std::path::Path>>(
     path: P,
-) -> Result<Vec<T>, std::io::Error> {
-    let mut records = Vec::new();
-    let file = std::fs::File::open(path)?;
-    let mut rdr = csv::Reader::from_reader(file);
-
-    let mut dropped = 0;
-    for result in rdr.deserialize() {
-        match result {
-            Ok(record) => records.push(record),
-            Err(e) => {
-                tracing::info!("Dropping: {:#?}.", e.to_string());
-                dropped += 1;
+) -> Whimsy<(Vec<T>, LoadReport)> {
+    from_csv_with(path, &CsvOptions::default())
+}
+
+/// Write `value` to `path` in bincode, behind the cache header and [`CACHE_VERSION`].
+pub fn write_cache<T: Serialize, P: AsRef<std::path::Path>>(value: &T, path: P) -> Polite<()> {
+    tracing::info!("Serializing to binary.");
+    let mut encode = CACHE_MAGIC.to_vec();
+    encode.extend(CACHE_VERSION.to_le_bytes());
+    encode.extend(bincode::serialize(value)?);
+    tracing::info!("Writing to file.");
+    std::fs::write(path, encode)?;
+    Ok(())
+}
+
+/// Read a cache written by [`write_cache`].  Files without the cache header were written before
+/// caches were versioned, and are read with the layout `L` of that time.  A cache from another
+/// version is an error, and should be regenerated from the source data.
+pub fn read_cache<T, L, P>(path: P) -> Whimsy<T>
+where
+    T: DeserializeOwned,
+    L: DeserializeOwned + Into<T>,
+    P: AsRef<std::path::Path>,
+{
+    tracing::info!("Deserializing from binary.");
+    let bytes = std::fs::read(&path).map_err(|e| WhimsyError::io(&path, e))?;
+    match bytes.strip_prefix(&CACHE_MAGIC[..]) {
+        Some(rest) if rest.len() >= 4 => {
+            let version = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            if version != CACHE_VERSION {
+                return Err(WhimsyError::parse(
+                    &path,
+                    format!(
+                        "cache format version {} is not supported, expected version {}; \
+                         regenerate the cache from the source data",
+                        version, CACHE_VERSION
+                    ),
+                ));
             }
+            bincode::deserialize(&rest[4..]).map_err(|e| WhimsyError::parse(&path, e))
+        }
+        _ => {
+            tracing::info!(
+                "No cache header in {}, reading the unversioned layout.",
+                path.as_ref().display()
+            );
+            let legacy: L =
+                bincode::deserialize(&bytes[..]).map_err(|e| WhimsyError::parse(&path, e))?;
+            Ok(legacy.into())
         }
     }
-    tracing::info!("{} records dropped.", dropped);
+}
 
-    Ok(records)
+/// Generic function to serialize data types to a CSV file.  Called by methods to avoid code
+/// duplication.
+pub fn to_csv<T: Serialize, P: AsRef<std::path::Path>>(
+    records: &[T],
+    path: P,
+) -> Result<(), std::io::Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
 }
 
 pub fn point_bounds(point: &Point2d, buffer: f64) -> Rect {
@@ -32,3 +105,25 @@ pub fn point_bounds(point: &Point2d, buffer: f64) -> Rect {
     let ymax = point.y() + buffer;
     Rect::new(xmin, ymin, xmax, ymax)
 }
+
+/// Returns true if the rectangles `a` and `b` overlap or share an edge.
+pub fn rect_intersects(a: &Rect, b: &Rect) -> bool {
+    a.x_min() <= b.x_max() && b.x_min() <= a.x_max() && a.y_min() <= b.y_max() && b.y_min() <= a.y_max()
+}
+
+/// Radius of the WGS84 ellipsoid used by the Web Mercator projection, in meters.
+pub const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Project WGS84 longitude and latitude in degrees to Web Mercator (EPSG:3857) meters.
+pub fn wgs84_to_web_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let x = EARTH_RADIUS * lon.to_radians();
+    let y = EARTH_RADIUS * (std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln();
+    (x, y)
+}
+
+/// Unproject Web Mercator (EPSG:3857) meters to WGS84 longitude and latitude in degrees.
+pub fn web_mercator_to_wgs84(x: f64, y: f64) -> (f64, f64) {
+    let lon = (x / EARTH_RADIUS).to_degrees();
+    let lat = (2.0 * (y / EARTH_RADIUS).exp().atan() - std::f64::consts::FRAC_PI_2).to_degrees();
+    (lon, lat)
+}
@@ -0,0 +1,469 @@
+use crate::prelude::*;
+use egui::{Color32, Painter, Ui};
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use geo::line_intersection::{line_intersection, LineIntersection};
+use geo::orient::Direction;
+use geo::{Area, BooleanOps, Contains, EuclideanLength, Orient, RemoveRepeatedPoints, Winding};
+use geo_types::{Coord, LineString, Polygon};
+use polite::Polite;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// Kinds of topology problem found by [`Validation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum Defect {
+    /// A ring crosses itself.
+    SelfIntersection,
+    /// The shell is not counter-clockwise or a hole is not clockwise.
+    WrongWinding,
+    /// A hole is not inside its shell.
+    HoleOutsideShell,
+    /// A ring repeats a vertex in succession.
+    DuplicateVertex,
+    /// Two parcels cover the same ground.
+    Overlap,
+    /// The parcel is long and thin, with little area.
+    Sliver,
+    /// Two parcels nearly touch, leaving a thin strip between them.
+    Gap,
+}
+
+impl Defect {
+    pub const ALL: [Defect; 7] = [
+        Defect::SelfIntersection,
+        Defect::WrongWinding,
+        Defect::HoleOutsideShell,
+        Defect::DuplicateVertex,
+        Defect::Overlap,
+        Defect::Sliver,
+        Defect::Gap,
+    ];
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            Defect::SelfIntersection => Color32::from_rgb(213, 94, 0),
+            Defect::WrongWinding => Color32::from_rgb(86, 180, 233),
+            Defect::HoleOutsideShell => Color32::from_rgb(204, 121, 167),
+            Defect::DuplicateVertex => Color32::from_rgb(240, 228, 66),
+            Defect::Overlap => Color32::from_rgb(230, 159, 0),
+            Defect::Sliver => Color32::from_rgb(0, 158, 115),
+            Defect::Gap => Color32::from_rgb(0, 114, 178),
+        }
+    }
+
+    /// Whether [`Validation::fix`] repairs the defect.
+    pub fn fixable(&self) -> bool {
+        matches!(self, Defect::WrongWinding | Defect::DuplicateVertex)
+    }
+}
+
+impl std::fmt::Display for Defect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+/// A topology problem with a parcel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    /// Position of the parcel in the dataset.
+    pub index: usize,
+    pub map_num: String,
+    pub defect: Defect,
+    pub detail: String,
+    /// Position of the problem, in the coordinates of the dataset.
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Settings for a [`Validation`], in state plane feet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValidationConfig {
+    /// Smallest overlap area reported, in square feet.
+    pub overlap_area: f64,
+    /// Widest gap reported between neighbouring parcels, in feet.
+    pub gap_width: f64,
+    /// Parcels below this Polsby-Popper compactness and `sliver_area` are slivers.
+    pub sliver_compactness: f64,
+    pub sliver_area: f64,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            overlap_area: 1.0,
+            gap_width: 1.0,
+            sliver_compactness: 0.05,
+            sliver_area: 2_000.0,
+        }
+    }
+}
+
+/// Topology problems in a set of parcels.
+#[derive(Debug, Clone, Default)]
+pub struct Validation {
+    pub findings: Vec<Finding>,
+    /// Revision of the parcels validated.  Findings refer to parcels by position, so they only
+    /// apply to this revision.
+    pub revision: Revision,
+}
+
+impl Validation {
+    pub fn new(parcels: &Parcels, config: &ValidationConfig) -> Self {
+        let topology = Topology::new(parcels, config.gap_width);
+        let back = |c: Coord| {
+            let (x, y) = Crs::OregonSouth.transform(&parcels.crs, c.x, c.y);
+            (x, y)
+        };
+        let mut findings = topology
+            .grid
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(i, grid)| {
+                let map_num = &parcels.records[i].owner.id;
+                let mut found = Vec::new();
+                let mut push = |defect: Defect, detail: String, at: Coord| {
+                    let (x, y) = back(at);
+                    found.push(Finding {
+                        index: i,
+                        map_num: map_num.clone(),
+                        defect,
+                        detail,
+                        x,
+                        y,
+                    });
+                };
+                for polygon in grid {
+                    rings(polygon, &mut push);
+                }
+
+                let area = grid.unsigned_area();
+                let perimeter = grid
+                    .iter()
+                    .map(|v| v.exterior().euclidean_length())
+                    .sum::<f64>();
+                if perimeter > 0.0 {
+                    let compactness = 4.0 * std::f64::consts::PI * area / perimeter.powi(2);
+                    if compactness < config.sliver_compactness && area < config.sliver_area {
+                        if let Some(c) = first_coord(grid) {
+                            push(
+                                Defect::Sliver,
+                                format!("{:.0} sq ft with compactness {:.3}.", area, compactness),
+                                c,
+                            );
+                        }
+                    }
+                }
+
+                // Compare each pair of neighbours once, from the lower index.
+                for j in topology.adjacent[i].iter().copied().filter(|j| *j > i) {
+                    let other = &topology.grid[j];
+                    let other_id = &parcels.records[j].owner.id;
+                    let distance = topology.distance(i, j);
+                    if distance > 0.0 {
+                        if let Some(c) = first_coord(grid) {
+                            push(
+                                Defect::Gap,
+                                format!("{:.2} ft gap to {}.", distance, other_id),
+                                c,
+                            );
+                        }
+                        continue;
+                    }
+                    // Boolean operations can panic on degenerate input, which is itself a sign of
+                    // a problem with the rings already reported above.
+                    let overlap = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        grid.intersection(other)
+                    }));
+                    if let Ok(overlap) = overlap {
+                        let shared = overlap.unsigned_area();
+                        if shared >= config.overlap_area {
+                            if let Some(c) = first_coord(&overlap) {
+                                push(
+                                    Defect::Overlap,
+                                    format!("{:.1} sq ft shared with {}.", shared, other_id),
+                                    c,
+                                );
+                            }
+                        }
+                    }
+                }
+                found.into_iter()
+            })
+            .collect::<Vec<Finding>>();
+        findings.sort_by(|a, b| a.index.cmp(&b.index).then(a.defect.cmp(&b.defect)));
+        info!("Validation found {} problems.", findings.len());
+        Self {
+            findings,
+            revision: parcels.revision,
+        }
+    }
+
+    /// Number of findings of each kind.
+    pub fn counts(&self) -> BTreeMap<Defect, usize> {
+        let mut counts = BTreeMap::new();
+        for finding in &self.findings {
+            *counts.entry(finding.defect).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_csv<P: AsRef<std::path::Path>>(&self, path: P) -> Polite<()> {
+        to_csv(&self.findings, path)?;
+        Ok(())
+    }
+
+    /// Remove repeated vertices and correct the winding of the parcels with fixable findings.
+    /// Returns the number of parcels changed, or `None` without changing anything if the parcels
+    /// are not the revision validated.  Gives the parcels a new revision, so that the topology,
+    /// snapping index and other data derived from the old geometry is rebuilt.
+    pub fn fix(&self, parcels: &mut Parcels) -> Option<usize> {
+        if parcels.revision != self.revision {
+            return None;
+        }
+        let indices = self
+            .findings
+            .iter()
+            .filter(|v| v.defect.fixable())
+            .map(|v| v.index)
+            .collect::<HashSet<usize>>();
+        for i in &indices {
+            let parcel = &mut parcels.records[*i];
+            let fixed = parcel
+                .geo()
+                .remove_repeated_points()
+                .orient(Direction::Default);
+            let (geometry, bounds) = Convert::new(fixed).bounded_multipolygon();
+            parcel.geometry = geometry;
+            parcel.bounds = bounds;
+        }
+        if !indices.is_empty() {
+            parcels.revision = Default::default();
+        }
+        info!("Fixed {} parcels.", indices.len());
+        Some(indices.len())
+    }
+}
+
+fn first_coord(multi: &geo::MultiPolygon) -> Option<Coord> {
+    multi.iter().next().and_then(|v| v.exterior().0.first().copied())
+}
+
+/// Check the rings of a polygon for crossings, winding, repeated vertices and stray holes.
+fn rings(polygon: &Polygon, push: &mut impl FnMut(Defect, String, Coord)) {
+    let exterior = polygon.exterior();
+    if exterior.is_cw() {
+        if let Some(c) = exterior.0.first() {
+            push(Defect::WrongWinding, "Shell is clockwise.".to_string(), *c);
+        }
+    }
+    let shell = Polygon::new(exterior.clone(), Vec::new());
+    for (n, hole) in polygon.interiors().iter().enumerate() {
+        if hole.is_ccw() {
+            if let Some(c) = hole.0.first() {
+                push(Defect::WrongWinding, format!("Hole {} is counter-clockwise.", n), *c);
+            }
+        }
+        if !shell.contains(hole) {
+            if let Some(c) = hole.0.first() {
+                push(Defect::HoleOutsideShell, format!("Hole {} is outside the shell.", n), *c);
+            }
+        }
+    }
+    for ring in std::iter::once(exterior).chain(polygon.interiors()) {
+        if let Some(c) = repeated(ring) {
+            push(Defect::DuplicateVertex, "Repeated vertex.".to_string(), c);
+        }
+        if let Some(c) = crossing(ring) {
+            push(Defect::SelfIntersection, "Ring crosses itself.".to_string(), c);
+        }
+    }
+}
+
+/// First vertex repeated in succession, ignoring the closing vertex.
+fn repeated(ring: &LineString) -> Option<Coord> {
+    ring.0.windows(2).find(|v| v[0] == v[1]).map(|v| v[0])
+}
+
+/// First point where two non-adjacent segments of the ring meet.
+fn crossing(ring: &LineString) -> Option<Coord> {
+    let lines = ring.lines().filter(|v| v.start != v.end).collect::<Vec<geo::Line>>();
+    let n = lines.len();
+    for i in 0..n {
+        for j in (i + 2)..n {
+            // The first and last segments of a closed ring share the closing vertex.
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            match line_intersection(lines[i], lines[j]) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => return Some(intersection),
+                Some(LineIntersection::Collinear { intersection }) => return Some(intersection.start),
+                None => {}
+            }
+        }
+    }
+    None
+}
+
+/// Validation results in the GUI, with a filter by kind of defect.
+#[derive(Debug, Clone)]
+pub struct ValidationPanel {
+    pub validation: Option<Validation>,
+    /// Validation running in the background.
+    pub running: Option<Task<Option<Validation>>>,
+    pub config: ValidationConfig,
+    pub visible: HashSet<Defect>,
+    pub report_path: String,
+    pub status: String,
+}
+
+impl Default for ValidationPanel {
+    fn default() -> Self {
+        Self {
+            validation: None,
+            running: None,
+            config: Default::default(),
+            visible: Defect::ALL.into_iter().collect(),
+            report_path: "parcel_validation.csv".to_string(),
+            status: Default::default(),
+        }
+    }
+}
+
+impl ValidationPanel {
+    /// Draw the validation controls and findings.  Returns the position of a finding when the
+    /// user clicks it.  Findings for an earlier revision of the parcels are dropped.
+    pub fn show(&mut self, ui: &mut Ui, data: &Arc<RwLock<Parcels>>) -> Option<Point2d> {
+        let mut picked = None;
+        if let Some(validation) = self.running.as_ref().and_then(|v| v.poll()) {
+            self.running = None;
+            self.validation = validation;
+        }
+        let revision = data.read().ok().map(|v| v.revision);
+        if self.validation.as_ref().is_some_and(|v| Some(v.revision) != revision) {
+            self.validation = None;
+            self.status = "Parcels changed since the last validation.".to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.config.gap_width).prefix("Gap (ft): "));
+            ui.add(egui::DragValue::new(&mut self.config.overlap_area).prefix("Overlap (sq ft): "));
+        });
+        ui.horizontal(|ui| {
+            if self.running.is_some() {
+                ui.spinner();
+                ui.label("Validating...");
+                return;
+            }
+            if ui.button("Validate").clicked() {
+                self.validate(ui.ctx(), data);
+            }
+            if let Some(validation) = &self.validation {
+                if ui.button("Fix winding and repeated vertices").clicked() {
+                    let fixed = match data.write() {
+                        Ok(mut parcels) => validation.fix(&mut parcels),
+                        Err(_) => None,
+                    };
+                    self.status = match fixed {
+                        Some(fixed) => format!("Fixed {} parcels.", fixed),
+                        None => "Parcels changed since the last validation.".to_string(),
+                    };
+                    self.validation = None;
+                    self.validate(ui.ctx(), data);
+                }
+            }
+        });
+
+        let Some(validation) = &self.validation else {
+            ui.label("No validation run.");
+            return None;
+        };
+
+        let counts = validation.counts();
+        for defect in Defect::ALL {
+            let mut visible = self.visible.contains(&defect);
+            let text = format!("{} ({})", defect, counts.get(&defect).unwrap_or(&0));
+            if ui
+                .checkbox(&mut visible, egui::RichText::new(text).color(defect.color()))
+                .changed()
+            {
+                if visible {
+                    self.visible.insert(defect);
+                } else {
+                    self.visible.remove(&defect);
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.report_path).hint_text("Report path"));
+            if ui.button("CSV").clicked() {
+                self.status = match validation.to_csv(&self.report_path) {
+                    Ok(_) => format!("Wrote {}.", self.report_path),
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+
+        let findings = validation
+            .findings
+            .iter()
+            .filter(|v| self.visible.contains(&v.defect))
+            .collect::<Vec<&Finding>>();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical().max_height(300.).show_rows(
+            ui,
+            row_height,
+            findings.len(),
+            |ui, range| {
+                for finding in &findings[range] {
+                    let text = egui::RichText::new(format!("{}: {}", finding.map_num, finding.detail))
+                        .color(finding.defect.color());
+                    if ui.selectable_label(false, text).clicked() {
+                        picked = Some(Point2d::new(finding.x, finding.y));
+                    }
+                }
+            },
+        );
+        picked
+    }
+
+    /// Start validating the current parcels in the background.
+    fn validate(&mut self, ctx: &egui::Context, data: &Arc<RwLock<Parcels>>) {
+        let data = data.clone();
+        let config = self.config;
+        self.running = Some(Task::spawn(ctx, move || {
+            data.read().ok().map(|parcels| Validation::new(&parcels, &config))
+        }));
+    }
+
+    /// Mark the visible findings on the map, unless `parcels` changed since the validation.
+    pub fn overlay(&self, painter: &Painter, map: &MapView, parcels: &Parcels) {
+        let Some(validation) = self.validation.as_ref().filter(|v| v.revision == parcels.revision)
+        else {
+            return;
+        };
+        let extent = map.extent();
+        for finding in validation.findings.iter().filter(|v| self.visible.contains(&v.defect)) {
+            let point = Point2d::new(finding.x, finding.y);
+            if extent.contains(&point) {
+                let center = map.point_to_screen(&point);
+                let color = finding.defect.color();
+                let size = 6.0;
+                painter.line_segment(
+                    [center - egui::vec2(size, size), center + egui::vec2(size, size)],
+                    egui::Stroke::new(2.0, color),
+                );
+                painter.line_segment(
+                    [center - egui::vec2(size, -size), center + egui::vec2(size, -size)],
+                    egui::Stroke::new(2.0, color),
+                );
+            }
+        }
+    }
+}
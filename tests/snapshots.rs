@@ -0,0 +1,127 @@
+//! Snapshot tests rendering the ui and map offscreen through [`Headless`].  Frames are compared
+//! with the PNGs in `tests/snapshots`.  Run with `UPDATE_SNAPSHOTS=1` to write new snapshots
+//! after an intended change in the output, and check the images in with the change.
+
+use galileo_types::cartesian::{CartesianPoint2d, Point2d};
+use std::sync::{Arc, RwLock};
+use whimsy::parcels::Owner;
+use whimsy::prelude::*;
+use whimsy::state::headless::matches_snapshot;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+/// Mean difference per channel allowed between a frame and its snapshot, for differences in
+/// rasterization between adapters.
+const TOLERANCE: f64 = 2.0;
+
+fn snapshot_path(name: &str) -> String {
+    format!("{}/tests/snapshots/{}.png", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// A three by three grid of 100 m parcels around `center`, in Web Mercator.
+fn fixture_parcels(center: Point2d) -> Parcels {
+    let mut records = Vec::new();
+    for row in 0..3 {
+        for col in 0..3 {
+            let x = center.x() - 150.0 + col as f64 * 100.0;
+            let y = center.y() - 150.0 + row as f64 * 100.0;
+            let polygon = geo::Polygon::new(
+                geo::LineString::from(vec![
+                    (x, y),
+                    (x + 100.0, y),
+                    (x + 100.0, y + 100.0),
+                    (x, y + 100.0),
+                    (x, y),
+                ]),
+                Vec::new(),
+            );
+            let (geometry, bounds) =
+                Convert::new(geo::MultiPolygon::new(vec![polygon])).bounded_multipolygon();
+            records.push(Parcel {
+                owner: Owner {
+                    name: Some(format!("Owner {}", row * 3 + col)),
+                    id: format!("36-05-{}{}", row, col),
+                },
+                attributes: Default::default(),
+                geometry,
+                bounds,
+                selected: row == 1 && col == 1,
+            });
+        }
+    }
+    Parcels {
+        records,
+        crs: Crs::WebMercator,
+        headers: Default::default(),
+        revision: Default::default(),
+    }
+}
+
+/// Ui state with the fixture parcels and no other data, independent of the files in `data` and
+/// any saved session.
+fn fixture_state() -> UiState {
+    let map = MapView {
+        resolution: 1.0,
+        ..Default::default()
+    };
+    UiState {
+        parcels: Some(Arc::new(RwLock::new(fixture_parcels(map.center)))),
+        appearance: Appearance {
+            mode: ThemeMode::Light,
+            ..Default::default()
+        },
+        map,
+        ..UiState::empty()
+    }
+}
+
+async fn headless() -> Headless {
+    match Headless::new(WIDTH, HEIGHT).await {
+        Ok(headless) => headless,
+        Err(e) => panic!("No adapter for headless rendering: {}", e),
+    }
+}
+
+#[tokio::test]
+async fn ui_matches_snapshot() {
+    let mut headless = headless().await;
+    let mut state = fixture_state();
+    state.show_map = false;
+    let frame = headless.render(3, |ctx| state.run(ctx));
+    let path = snapshot_path("ui");
+    assert!(
+        matches_snapshot(&frame, &path, TOLERANCE).unwrap(),
+        "ui frame differs from {}",
+        path
+    );
+}
+
+#[tokio::test]
+async fn map_matches_snapshot() {
+    let mut headless = headless().await;
+    let mut state = fixture_state();
+    let parcels = state.parcels.clone().unwrap();
+    let palette = state.appearance.palette();
+    let frame = headless.render(3, |ctx| {
+        let parcels = parcels.read().unwrap();
+        state.layers.refresh(Some(&parcels), None);
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| {
+                state.map.show(
+                    ui,
+                    Some(&parcels),
+                    None,
+                    &state.layers,
+                    &mut state.tiles,
+                    &palette,
+                );
+            });
+    });
+    let path = snapshot_path("map");
+    assert!(
+        matches_snapshot(&frame, &path, TOLERANCE).unwrap(),
+        "map frame differs from {}",
+        path
+    );
+}